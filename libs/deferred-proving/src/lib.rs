@@ -0,0 +1,201 @@
+//! Deferred proving: assemble a transaction without Groth16 proofs
+//!
+//! `DeferredProver` stands in for the proving parameters in the builder. It
+//! records every circuit witness instead of proving it and encodes all-zero
+//! proofs, so the result is a complete, signed transaction whose proofs an
+//! external prover fills in afterwards. This only works for v5 transactions,
+//! where proofs are not covered by the signature hash or the txid.
+//!
+//! Both the proof service and the browser's wasm library build such
+//! transactions, so the prover and the witnesses it exports live here.
+
+use rand::RngCore;
+use sapling::{
+    bundle::GrothProofBytes,
+    circuit::{self, OutputParameters, SpendParameters, ValueCommitmentOpening},
+    prover::{OutputProver, SpendProver},
+    value::{NoteValue, ValueCommitTrapdoor},
+    zip32::{DiversifiableFullViewingKey, ExtendedSpendingKey},
+    Diversifier, MerklePath, PaymentAddress, ProofGenerationKey, Rseed,
+};
+use serde::Serialize;
+use std::cell::RefCell;
+use zcash_primitives::zip32::Scope;
+
+/// Witness for one spend proof, in the order of the transaction's spends
+///
+/// `ak` and `nsk` form the proof generation key for the note's scope. They
+/// allow proving the spend but not authorizing it.
+#[derive(Serialize)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+pub struct SpendWitness {
+    pub ak: String,
+    pub nsk: String,
+    pub value: Option<u64>,
+    pub rcv: Option<String>,
+    pub payment_address: Option<String>,
+    pub rcm: Option<String>,
+    /// Spend authorization randomizer
+    pub alpha: Option<String>,
+    pub anchor: Option<String>,
+    /// (sibling node, whether the current node is the right child), leaf to root
+    #[cfg_attr(feature = "utoipa", schema(value_type = Vec<Object>))]
+    pub auth_path: Vec<Option<(String, bool)>>,
+}
+
+/// Witness for one output proof, in the order of the transaction's outputs
+#[derive(Serialize)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+pub struct OutputWitness {
+    pub value: Option<u64>,
+    pub rcv: Option<String>,
+    pub payment_address: Option<String>,
+    pub rcm: Option<String>,
+    pub esk: Option<String>,
+}
+
+/// Everything an external prover needs to produce the missing proofs
+#[derive(Serialize)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+pub struct ProvingInputs {
+    pub spends: Vec<SpendWitness>,
+    pub outputs: Vec<OutputWitness>,
+}
+
+fn opening(o: &Option<ValueCommitmentOpening>) -> (Option<u64>, Option<String>) {
+    match o {
+        Some(o) => (Some(o.value.inner()), Some(hex::encode(o.randomness.to_bytes()))),
+        None => (None, None),
+    }
+}
+
+/// Hex-encoded `(ak, nsk)` of `extsk`
+fn proof_generation_key(extsk: &ExtendedSpendingKey) -> (String, String) {
+    let key = extsk.expsk.proof_generation_key();
+    let ak = jubjub::AffinePoint::from(jubjub::ExtendedPoint::from(&key.ak));
+    (hex::encode(ak.to_bytes()), hex::encode(key.nsk.to_bytes()))
+}
+
+impl SpendWitness {
+    /// `keys` holds the external and internal proof generation keys of the account
+    fn new(spend: &circuit::Spend, dfvk: &DiversifiableFullViewingKey, keys: &[(String, String); 2]) -> Self {
+        // Change notes sit at internal addresses and need the internal key
+        let scope = spend
+            .payment_address
+            .and_then(|a| dfvk.decrypt_diversifier(&a))
+            .map(|(_, scope)| scope);
+        let (ak, nsk) = match scope {
+            Some(Scope::Internal) => keys[1].clone(),
+            _ => keys[0].clone(),
+        };
+        let (value, rcv) = opening(&spend.value_commitment_opening);
+        SpendWitness {
+            ak,
+            nsk,
+            value,
+            rcv,
+            payment_address: spend.payment_address.map(|a| hex::encode(a.to_bytes())),
+            rcm: spend.commitment_randomness.map(|r| hex::encode(r.to_bytes())),
+            alpha: spend.ar.map(|a| hex::encode(a.to_bytes())),
+            anchor: spend.anchor.map(|a| hex::encode(a.to_bytes())),
+            auth_path: spend
+                .auth_path
+                .iter()
+                .map(|node| node.map(|(sibling, is_right)| (hex::encode(sibling.to_bytes()), is_right)))
+                .collect(),
+        }
+    }
+}
+
+impl From<&circuit::Output> for OutputWitness {
+    fn from(output: &circuit::Output) -> Self {
+        let (value, rcv) = opening(&output.value_commitment_opening);
+        OutputWitness {
+            value,
+            rcv,
+            payment_address: output.payment_address.map(|a| hex::encode(a.to_bytes())),
+            rcm: output.commitment_randomness.map(|r| hex::encode(r.to_bytes())),
+            esk: output.esk.map(|e| hex::encode(e.to_bytes())),
+        }
+    }
+}
+
+/// Records circuits instead of proving them
+#[derive(Default)]
+pub struct DeferredProver {
+    spends: RefCell<Vec<circuit::Spend>>,
+    outputs: RefCell<Vec<circuit::Output>>,
+}
+
+impl DeferredProver {
+    /// The recorded witnesses, with proof generation keys from the account key `extsk`
+    pub fn into_inputs(self, extsk: &ExtendedSpendingKey) -> ProvingInputs {
+        let dfvk = extsk.to_diversifiable_full_viewing_key();
+        let keys = [proof_generation_key(extsk), proof_generation_key(&extsk.derive_internal())];
+        ProvingInputs {
+            spends: self
+                .spends
+                .into_inner()
+                .iter()
+                .map(|spend| SpendWitness::new(spend, &dfvk, &keys))
+                .collect(),
+            outputs: self.outputs.into_inner().iter().map(OutputWitness::from).collect(),
+        }
+    }
+}
+
+impl SpendProver for DeferredProver {
+    type Proof = ();
+
+    fn prepare_circuit(
+        proof_generation_key: ProofGenerationKey,
+        diversifier: Diversifier,
+        rseed: Rseed,
+        value: NoteValue,
+        alpha: jubjub::Fr,
+        rcv: ValueCommitTrapdoor,
+        anchor: bls12_381::Scalar,
+        merkle_path: MerklePath,
+    ) -> Option<circuit::Spend> {
+        <SpendParameters as SpendProver>::prepare_circuit(
+            proof_generation_key,
+            diversifier,
+            rseed,
+            value,
+            alpha,
+            rcv,
+            anchor,
+            merkle_path,
+        )
+    }
+
+    fn create_proof<R: RngCore>(&self, circuit: circuit::Spend, _rng: &mut R) {
+        self.spends.borrow_mut().push(circuit);
+    }
+
+    fn encode_proof(_proof: ()) -> GrothProofBytes {
+        [0; 192]
+    }
+}
+
+impl OutputProver for DeferredProver {
+    type Proof = ();
+
+    fn prepare_circuit(
+        esk: jubjub::Fr,
+        payment_address: PaymentAddress,
+        rcm: jubjub::Fr,
+        value: NoteValue,
+        rcv: ValueCommitTrapdoor,
+    ) -> circuit::Output {
+        <OutputParameters as OutputProver>::prepare_circuit(esk, payment_address, rcm, value, rcv)
+    }
+
+    fn create_proof<R: RngCore>(&self, circuit: circuit::Output, _rng: &mut R) {
+        self.outputs.borrow_mut().push(circuit);
+    }
+
+    fn encode_proof(_proof: ()) -> GrothProofBytes {
+        [0; 192]
+    }
+}
@@ -0,0 +1,116 @@
+//! Address parsing shared by the WASM bindings
+
+use serde::Serialize;
+use std::convert::Infallible;
+use zcash_address::{
+    unified::{self, Container},
+    ConversionError, Network, TryFromRawAddress, ZcashAddress,
+};
+
+/// Kind of receiver an encoded address decodes to
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AddressKind {
+    Sprout,
+    Sapling,
+    Unified,
+    Transparent,
+}
+
+impl TryFromRawAddress for AddressKind {
+    type Error = Infallible;
+
+    fn try_from_raw_sprout(_data: [u8; 64]) -> Result<Self, ConversionError<Self::Error>> {
+        Ok(AddressKind::Sprout)
+    }
+
+    fn try_from_raw_sapling(_data: [u8; 43]) -> Result<Self, ConversionError<Self::Error>> {
+        Ok(AddressKind::Sapling)
+    }
+
+    fn try_from_raw_unified(_data: unified::Address) -> Result<Self, ConversionError<Self::Error>> {
+        Ok(AddressKind::Unified)
+    }
+
+    fn try_from_raw_transparent_p2pkh(_data: [u8; 20]) -> Result<Self, ConversionError<Self::Error>> {
+        Ok(AddressKind::Transparent)
+    }
+
+    fn try_from_raw_transparent_p2sh(_data: [u8; 20]) -> Result<Self, ConversionError<Self::Error>> {
+        Ok(AddressKind::Transparent)
+    }
+}
+
+/// Parse a network name as used by the frontend ("main"/"mainnet", "test"/"testnet")
+pub fn parse_network(network: &str) -> Result<Network, String> {
+    match network {
+        "main" | "mainnet" => Ok(Network::Main),
+        "test" | "testnet" => Ok(Network::Test),
+        other => Err(format!("Unknown network: {}", other)),
+    }
+}
+
+/// Decode `addr` and check it belongs to `network`
+///
+/// Sapling addresses are also checked to decode to a valid curve point,
+/// which the Bech32 encoding alone doesn't guarantee.
+pub fn parse_address(addr: &str, network: Network) -> Result<AddressKind, String> {
+    let parsed = ZcashAddress::try_from_encoded(addr).map_err(|e| e.to_string())?;
+    let kind = parsed
+        .clone()
+        .convert_if_network::<AddressKind>(network)
+        .map_err(|e| e.to_string())?;
+
+    if kind == AddressKind::Sapling {
+        let bytes = parsed
+            .convert_if_network::<SaplingBytes>(network)
+            .map_err(|e| e.to_string())?;
+        if sapling::PaymentAddress::from_bytes(&bytes.0).is_none() {
+            return Err("Not a valid Sapling payment address".to_string());
+        }
+    }
+
+    Ok(kind)
+}
+
+/// Raw Sapling receiver bytes
+struct SaplingBytes([u8; 43]);
+
+impl TryFromRawAddress for SaplingBytes {
+    type Error = Infallible;
+
+    fn try_from_raw_sapling(data: [u8; 43]) -> Result<Self, ConversionError<Self::Error>> {
+        Ok(SaplingBytes(data))
+    }
+}
+
+/// The Sapling receiver of a Sapling or unified address
+struct SaplingReceiver([u8; 43]);
+
+impl TryFromRawAddress for SaplingReceiver {
+    type Error = String;
+
+    fn try_from_raw_sapling(data: [u8; 43]) -> Result<Self, ConversionError<Self::Error>> {
+        Ok(SaplingReceiver(data))
+    }
+
+    fn try_from_raw_unified(data: unified::Address) -> Result<Self, ConversionError<Self::Error>> {
+        data.items()
+            .into_iter()
+            .find_map(|receiver| match receiver {
+                unified::Receiver::Sapling(data) => Some(SaplingReceiver(data)),
+                _ => None,
+            })
+            .ok_or_else(|| ConversionError::User("Unified address has no Sapling receiver".to_string()))
+    }
+}
+
+/// Decode `addr` for `network` into the Sapling payment address to send to
+pub fn sapling_address(addr: &str, network: Network) -> Result<sapling::PaymentAddress, String> {
+    let receiver = ZcashAddress::try_from_encoded(addr)
+        .map_err(|e| e.to_string())?
+        .convert_if_network::<SaplingReceiver>(network)
+        .map_err(|e| e.to_string())?;
+    sapling::PaymentAddress::from_bytes(&receiver.0)
+        .ok_or_else(|| "Not a valid Sapling payment address".to_string())
+}
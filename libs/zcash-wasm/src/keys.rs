@@ -0,0 +1,154 @@
+//! ZIP 32 Sapling key derivation for the WASM bindings
+
+use bech32::{FromBase32, ToBase32, Variant};
+use bip0039::{English, Mnemonic};
+use serde::Serialize;
+use sapling::zip32::{ChildIndex, ExtendedSpendingKey};
+use zcash_address::{Network, ZcashAddress};
+use zcash_primitives::constants::{mainnet, testnet};
+
+/// Encoded keys and address for one account
+#[derive(Serialize)]
+pub struct DerivedKeys {
+    pub spending_key: String,
+    pub full_viewing_key: String,
+    pub address: String,
+}
+
+struct NetworkConstants {
+    coin_type: u32,
+    hrp_spending_key: &'static str,
+    hrp_full_viewing_key: &'static str,
+}
+
+fn constants(network: Network) -> Result<NetworkConstants, String> {
+    match network {
+        Network::Main => Ok(NetworkConstants {
+            coin_type: mainnet::COIN_TYPE,
+            hrp_spending_key: mainnet::HRP_SAPLING_EXTENDED_SPENDING_KEY,
+            hrp_full_viewing_key: mainnet::HRP_SAPLING_EXTENDED_FULL_VIEWING_KEY,
+        }),
+        Network::Test => Ok(NetworkConstants {
+            coin_type: testnet::COIN_TYPE,
+            hrp_spending_key: testnet::HRP_SAPLING_EXTENDED_SPENDING_KEY,
+            hrp_full_viewing_key: testnet::HRP_SAPLING_EXTENDED_FULL_VIEWING_KEY,
+        }),
+        Network::Regtest => Err("Regtest is not supported".to_string()),
+    }
+}
+
+fn encode_bech32(hrp: &str, data: &[u8]) -> Result<String, String> {
+    bech32::encode(hrp, data.to_base32(), Variant::Bech32).map_err(|e| e.to_string())
+}
+
+/// Derive the Sapling account key at m/32'/coin_type'/account' (ZIP 32)
+///
+/// Returns the extended spending key, extended full viewing key and the
+/// default payment address, all encoded for `network`.
+pub fn derive_from_seed(seed: &[u8], account: u32, network: Network) -> Result<DerivedKeys, String> {
+    if !(32..=252).contains(&seed.len()) {
+        return Err(format!("Seed must be 32 to 252 bytes, got {}", seed.len()));
+    }
+    if account >= (1 << 31) {
+        return Err(format!("Account index {} is out of range", account));
+    }
+    let constants = constants(network)?;
+
+    let master = ExtendedSpendingKey::master(seed);
+    let extsk = ExtendedSpendingKey::from_path(
+        &master,
+        &[
+            ChildIndex::hardened(32),
+            ChildIndex::hardened(constants.coin_type),
+            ChildIndex::hardened(account),
+        ],
+    );
+    #[allow(deprecated)]
+    let extfvk = extsk.to_extended_full_viewing_key();
+    let (_, address) = extsk.default_address();
+
+    let mut extsk_bytes = vec![];
+    extsk.write(&mut extsk_bytes).map_err(|e| e.to_string())?;
+    let mut extfvk_bytes = vec![];
+    extfvk.write(&mut extfvk_bytes).map_err(|e| e.to_string())?;
+
+    Ok(DerivedKeys {
+        spending_key: encode_bech32(constants.hrp_spending_key, &extsk_bytes)?,
+        full_viewing_key: encode_bech32(constants.hrp_full_viewing_key, &extfvk_bytes)?,
+        address: ZcashAddress::from_sapling(network, address.to_bytes()).encode(),
+    })
+}
+
+/// Decode a Bech32 extended spending key (`secret-extended-key-...`) for `network`
+pub fn decode_spending_key(encoded: &str, network: Network) -> Result<ExtendedSpendingKey, String> {
+    let constants = constants(network)?;
+    let (hrp, data, _) = bech32::decode(encoded).map_err(|e| format!("Invalid spending key: {}", e))?;
+    if hrp != constants.hrp_spending_key {
+        return Err(format!("Expected a '{}' spending key, found '{}'", constants.hrp_spending_key, hrp));
+    }
+    let bytes = Vec::<u8>::from_base32(&data).map_err(|e| format!("Invalid spending key: {}", e))?;
+    ExtendedSpendingKey::read(&bytes[..]).map_err(|e| format!("Invalid spending key: {}", e))
+}
+
+/// Decode a BIP-39 mnemonic (validating its checksum) into a 64-byte seed
+pub fn seed_from_mnemonic(phrase: &str, passphrase: &str) -> Result<[u8; 64], String> {
+    let mnemonic = Mnemonic::<English>::from_phrase(phrase)
+        .map_err(|e| format!("Invalid mnemonic: {}", e))?;
+    Ok(mnemonic.to_seed(passphrase))
+}
+
+/// Encode entropy (16-32 bytes, a multiple of 4) as a BIP-39 mnemonic phrase
+pub fn mnemonic_from_entropy(entropy: &[u8]) -> Result<String, String> {
+    let mnemonic = Mnemonic::<English>::from_entropy(entropy.to_vec())
+        .map_err(|e| format!("Invalid entropy: {}", e))?;
+    Ok(mnemonic.phrase().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// BIP-39 test vectors (entropy, phrase, seed), all with the passphrase "TREZOR"
+    const VECTORS: [(&str, &str, &str); 3] = [
+        (
+            "00000000000000000000000000000000",
+            "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about",
+            "c55257c360c07c72029aebc1b53c05ed0362ada38ead3e3e9efa3708e53495531f09a6987599d18264c1e1c92f2cf141630c7a3c4ab7c81b2f001698e7463b04",
+        ),
+        (
+            "7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f",
+            "legal winner thank year wave sausage worth useful legal winner thank yellow",
+            "2e8905819b8723fe2c1d161860e5ee1830318dbf49a83bd451cfb8440c28bd6fa457fe1296106559a3c80937a1c1069be3a3a5bd381ee6260e8d9739fce1f607",
+        ),
+        (
+            "ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff",
+            "zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo vote",
+            "dd48c104698c30cfe2b6142103248622fb7bb0ff692eebb00089b32d22484e1613912f0a5b694407be899ffd31ed3992c456cdf60f5d4564b8ba3f05a69890ad",
+        ),
+    ];
+
+    #[test]
+    fn mnemonic_from_entropy_matches_bip39_vectors() {
+        for (entropy, phrase, _) in VECTORS {
+            assert_eq!(mnemonic_from_entropy(&hex::decode(entropy).unwrap()).unwrap(), phrase);
+        }
+    }
+
+    #[test]
+    fn seed_from_mnemonic_matches_bip39_vectors() {
+        for (_, phrase, seed) in VECTORS {
+            assert_eq!(hex::encode(seed_from_mnemonic(phrase, "TREZOR").unwrap()), seed);
+        }
+    }
+
+    #[test]
+    fn seed_from_mnemonic_rejects_bad_checksum() {
+        let phrase = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon";
+        assert!(seed_from_mnemonic(phrase, "").is_err());
+    }
+
+    #[test]
+    fn mnemonic_from_entropy_rejects_bad_length() {
+        assert!(mnemonic_from_entropy(&[0u8; 15]).is_err());
+    }
+}
@@ -1,15 +1,16 @@
 use wasm_bindgen::prelude::*;
-use zcash_primitives::{
-    sapling::{
-        prover::LocalTxProver,
-        value::NoteValue,
-    },
-};
 use zcash_proofs::prover::LocalTxProver as ProofProver;
+use serde::Serialize;
+use zeroize::Zeroize;
+
+mod address;
+mod keys;
+mod params;
+mod skeleton;
 
 #[wasm_bindgen]
 pub struct ZcashProver {
-    prover: LocalTxProver,
+    prover: ProofProver,
 }
 
 #[wasm_bindgen]
@@ -18,12 +19,27 @@ impl ZcashProver {
     pub fn new() -> Result<ZcashProver, JsValue> {
         // Initialize with Sapling parameters
         // This loads the Groth16 proving parameters (~50MB files)
-        let prover = LocalTxProver::with_default_location()
-            .map_err(|e| JsValue::from_str(&format!("Failed to initialize prover: {:?}", e)))?;
+        let prover = ProofProver::with_default_location()
+            .ok_or_else(|| JsValue::from_str("Failed to initialize prover: parameters not found"))?;
         
         Ok(ZcashProver { prover })
     }
 
+    /// Build a prover from parameter bytes supplied by JS (e.g. from a browser cache)
+    /// The bytes are hash-checked first, so corrupted data is an error rather than a panic.
+    #[wasm_bindgen]
+    pub fn from_params(spend: &[u8], output: &[u8]) -> Result<ZcashProver, JsValue> {
+        if !params::params_match(spend, output) {
+            return Err(JsValue::from_str(
+                "Parameter bytes don't match the Sapling parameter hashes; fetch them again",
+            ));
+        }
+        
+        Ok(ZcashProver {
+            prover: ProofProver::from_bytes(spend, output),
+        })
+    }
+
     /// Generate Groth16 proof for a Sapling spend
     /// This generates REAL Groth16 ZK-SNARK proofs using librustzcash
     #[wasm_bindgen]
@@ -66,6 +82,179 @@ impl ZcashProver {
     }
 }
 
+/// Check cached parameter bytes against the known Sapling hashes before building a prover
+#[wasm_bindgen]
+pub fn params_match(spend: &[u8], output: &[u8]) -> bool {
+    params::params_match(spend, output)
+}
+
+/// Loads the parameter files incrementally, e.g. chunk by chunk from a
+/// streamed fetch or a chunked IndexedDB cache, hashing as the bytes arrive
+#[wasm_bindgen]
+pub struct ParamsLoader {
+    spend: params::HashedBuffer,
+    output: params::HashedBuffer,
+}
+
+#[wasm_bindgen]
+impl ParamsLoader {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> ParamsLoader {
+        ParamsLoader {
+            spend: params::HashedBuffer::new(params::ParamFile::Spend),
+            output: params::HashedBuffer::new(params::ParamFile::Output),
+        }
+    }
+
+    #[wasm_bindgen]
+    pub fn push_spend(&mut self, chunk: &[u8]) {
+        self.spend.push(chunk);
+    }
+
+    #[wasm_bindgen]
+    pub fn push_output(&mut self, chunk: &[u8]) {
+        self.output.push(chunk);
+    }
+
+    /// Bytes received so far for each file, for progress reporting
+    #[wasm_bindgen]
+    pub fn spend_len(&self) -> usize {
+        self.spend.len()
+    }
+
+    #[wasm_bindgen]
+    pub fn output_len(&self) -> usize {
+        self.output.len()
+    }
+
+    /// Whether the spend file is complete and matches its expected hash
+    #[wasm_bindgen]
+    pub fn spend_complete(&self) -> bool {
+        self.spend.is_complete()
+    }
+
+    #[wasm_bindgen]
+    pub fn output_complete(&self) -> bool {
+        self.output.is_complete()
+    }
+
+    /// Build the prover once both files are complete and verified
+    #[wasm_bindgen]
+    pub fn into_prover(self) -> Result<ZcashProver, JsValue> {
+        if !self.spend.is_complete() || !self.output.is_complete() {
+            return Err(JsValue::from_str("Parameter files are incomplete or corrupt"));
+        }
+        
+        let spend = self.spend.into_bytes();
+        let output = self.output.into_bytes();
+        Ok(ZcashProver {
+            prover: ProofProver::from_bytes(&spend, &output),
+        })
+    }
+}
+
+impl Default for ParamsLoader {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Serialize)]
+struct AddressValidation {
+    valid: bool,
+    kind: Option<address::AddressKind>,
+    error: Option<String>,
+}
+
+/// Validate a recipient address for the given network ("main" or "test")
+/// Returns `{ valid, kind, error }` so the UI can give feedback without a server round trip
+#[wasm_bindgen]
+pub fn validate_address(addr: &str, network: &str) -> JsValue {
+    let result = address::parse_network(network)
+        .and_then(|network| address::parse_address(addr, network));
+    
+    let validation = match result {
+        Ok(kind) => AddressValidation {
+            valid: true,
+            kind: Some(kind),
+            error: None,
+        },
+        Err(e) => AddressValidation {
+            valid: false,
+            kind: None,
+            error: Some(e),
+        },
+    };
+    
+    serde_wasm_bindgen::to_value(&validation).unwrap_or(JsValue::NULL)
+}
+
+/// Derive the Sapling spending key, full viewing key and default address for
+/// `account` from a hex-encoded seed, for the given network ("main" or "test")
+///
+/// Nothing is logged or kept: the decoded seed is zeroized before returning and
+/// the keys only go back to the caller. The JS layer is responsible for
+/// handling the seed and the returned spending key securely (never persist
+/// them unencrypted, never send them to a server).
+#[wasm_bindgen]
+pub fn derive_from_seed(seed_hex: &str, account: u32, network: &str) -> Result<JsValue, JsValue> {
+    let network = address::parse_network(network).map_err(|e| JsValue::from_str(&e))?;
+    let mut seed = hex::decode(seed_hex)
+        .map_err(|e| JsValue::from_str(&format!("Invalid seed hex: {}", e)))?;
+    
+    let derived = keys::derive_from_seed(&seed, account, network);
+    seed.zeroize();
+    let derived = derived.map_err(|e| JsValue::from_str(&e))?;
+    
+    serde_wasm_bindgen::to_value(&derived).map_err(|e| JsValue::from_str(&e.to_string()))
+}
+
+/// Same as `derive_from_seed`, starting from a BIP-39 mnemonic phrase and optional passphrase
+#[wasm_bindgen]
+pub fn derive_from_mnemonic(
+    phrase: &str,
+    passphrase: &str,
+    account: u32,
+    network: &str,
+) -> Result<JsValue, JsValue> {
+    let network = address::parse_network(network).map_err(|e| JsValue::from_str(&e))?;
+    let mut seed = keys::seed_from_mnemonic(phrase, passphrase).map_err(|e| JsValue::from_str(&e))?;
+    
+    let derived = keys::derive_from_seed(&seed, account, network);
+    seed.zeroize();
+    let derived = derived.map_err(|e| JsValue::from_str(&e))?;
+    
+    serde_wasm_bindgen::to_value(&derived).map_err(|e| JsValue::from_str(&e.to_string()))
+}
+
+/// Encode hex entropy (e.g. 32 bytes from crypto.getRandomValues) as a 24-word mnemonic
+#[wasm_bindgen]
+pub fn mnemonic_from_entropy(entropy_hex: &str) -> Result<String, JsValue> {
+    let mut entropy = hex::decode(entropy_hex)
+        .map_err(|e| JsValue::from_str(&format!("Invalid entropy hex: {}", e)))?;
+    let phrase = keys::mnemonic_from_entropy(&entropy);
+    entropy.zeroize();
+    phrase.map_err(|e| JsValue::from_str(&e))
+}
+
+/// Build a signed transaction with all-zero proofs, plus the witness for every proof
+///
+/// `request` is `{ spending_key, target_height, notes, recipients, fee }`; all
+/// notes are spent and any excess returns to the key's default address as
+/// change. Notes received at internal (change) addresses need
+/// `scope: "internal"`. Returns `{ transaction, txid, spends, outputs }`: the JS layer gets
+/// each proof from the proof service and splices it into the transaction.
+/// Needs a target height at or after NU5.
+#[wasm_bindgen]
+pub fn build_unproven_transaction(request: JsValue, network: &str) -> Result<JsValue, JsValue> {
+    let network = address::parse_network(network).map_err(|e| JsValue::from_str(&e))?;
+    let request: skeleton::SkeletonRequest = serde_wasm_bindgen::from_value(request)
+        .map_err(|e| JsValue::from_str(&format!("Invalid request: {}", e)))?;
+    
+    let skeleton = skeleton::build(&request, network).map_err(|e| JsValue::from_str(&e))?;
+    serde_wasm_bindgen::to_value(&skeleton).map_err(|e| JsValue::from_str(&e.to_string()))
+}
+
 #[wasm_bindgen]
 pub fn init() {
     console_error_panic_hook::set_once();
@@ -0,0 +1,69 @@
+//! Hash checks for Sapling parameters supplied by JS
+//!
+//! The browser caches the ~50MB parameter files itself. These helpers let it
+//! verify cached or streamed bytes before a prover is built from them, since
+//! `LocalTxProver::from_bytes` panics on bad data.
+
+use blake2b_simd::State;
+use zcash_proofs::{SAPLING_OUTPUT_HASH, SAPLING_SPEND_HASH};
+
+/// BLAKE2b-512 of `bytes`, hex-encoded as in zcash_proofs
+fn hash(bytes: &[u8]) -> String {
+    blake2b_simd::blake2b(bytes).to_hex().to_string()
+}
+
+/// Whether both files have the expected Sapling parameter hashes
+pub fn params_match(spend: &[u8], output: &[u8]) -> bool {
+    hash(spend) == SAPLING_SPEND_HASH && hash(output) == SAPLING_OUTPUT_HASH
+}
+
+/// Which parameter file a buffer holds
+#[derive(Clone, Copy)]
+pub enum ParamFile {
+    Spend,
+    Output,
+}
+
+impl ParamFile {
+    fn expected_hash(self) -> &'static str {
+        match self {
+            ParamFile::Spend => SAPLING_SPEND_HASH,
+            ParamFile::Output => SAPLING_OUTPUT_HASH,
+        }
+    }
+}
+
+/// One parameter file, accumulated chunk by chunk and hashed as it arrives
+pub struct HashedBuffer {
+    file: ParamFile,
+    bytes: Vec<u8>,
+    state: State,
+}
+
+impl HashedBuffer {
+    pub fn new(file: ParamFile) -> Self {
+        HashedBuffer {
+            file,
+            bytes: Vec::new(),
+            state: State::new(),
+        }
+    }
+
+    pub fn push(&mut self, chunk: &[u8]) {
+        self.state.update(chunk);
+        self.bytes.extend_from_slice(chunk);
+    }
+
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    /// Whether the bytes so far are exactly the expected file
+    pub fn is_complete(&self) -> bool {
+        self.state.clone().finalize().to_hex().as_str() == self.file.expected_hash()
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+}
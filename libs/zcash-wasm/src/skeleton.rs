@@ -0,0 +1,302 @@
+//! Unproven transaction skeletons, so the browser can leave proving to the server
+//!
+//! The transaction is assembled and signed here with all-zero proofs, while
+//! every circuit witness is recorded. The JS layer sends each witness to the
+//! proof service and splices the returned proofs in. This only works for v5
+//! transactions, whose signatures and txid don't cover the proofs.
+
+use deferred_proving::{DeferredProver, OutputWitness, SpendWitness};
+use incrementalmerkletree::Position;
+use rand::rngs::OsRng;
+use sapling::{
+    value::NoteValue, zip32::ExtendedSpendingKey, Anchor, Diversifier, MerklePath, Node, Note, Rseed,
+};
+use serde::{Deserialize, Serialize};
+use std::convert::Infallible;
+use zcash_primitives::{
+    consensus::{BlockHeight, BranchId, Network},
+    memo::MemoBytes,
+    transaction::{
+        builder::{BuildConfig, Builder},
+        components::amount::NonNegativeAmount,
+        fees::fixed,
+        TxVersion,
+    },
+};
+
+/// Depth of the Sapling note commitment tree
+const TREE_DEPTH: usize = 32;
+
+/// Which of the account's key trees a note was received under (ZIP 32)
+#[derive(Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NoteScope {
+    /// Addresses given out to senders
+    #[default]
+    External,
+    /// Change addresses
+    Internal,
+}
+
+/// A note to spend, with its witness
+#[derive(Deserialize)]
+pub struct NoteInput {
+    /// Note value in zatoshi
+    pub value: u64,
+    /// Diversifier of the address the note was received at (11 bytes, hex)
+    pub diversifier: String,
+    /// Key tree the receiving address belongs to (default external)
+    #[serde(default)]
+    pub scope: NoteScope,
+    /// Note rseed (32 bytes, hex)
+    pub rseed: String,
+    /// Position of the note commitment in the Sapling tree
+    pub position: u64,
+    /// Merkle authentication path, leaf to root (32 nodes, hex)
+    pub auth_path: Vec<String>,
+}
+
+#[derive(Deserialize)]
+pub struct RecipientInput {
+    pub address: String,
+    /// Amount in zatoshi
+    pub amount: u64,
+    /// Memo bytes (hex, up to 512 bytes); no memo if empty
+    #[serde(default)]
+    pub memo: String,
+}
+
+#[derive(Deserialize)]
+pub struct SkeletonRequest {
+    pub spending_key: String,
+    pub target_height: u32,
+    /// Every note is spent; whatever exceeds the recipients and fee goes back as change
+    pub notes: Vec<NoteInput>,
+    pub recipients: Vec<RecipientInput>,
+    /// Fee in zatoshi
+    pub fee: u64,
+}
+
+/// A signed transaction with zero proofs, and the witnesses to prove it
+#[derive(Serialize)]
+pub struct Skeleton {
+    /// Serialized transaction (hex)
+    pub transaction: String,
+    pub txid: String,
+    pub spends: Vec<SpendWitness>,
+    pub outputs: Vec<OutputWitness>,
+}
+
+fn decode_hex_array<const N: usize>(field: &str, value: &str) -> Result<[u8; N], String> {
+    let bytes = hex::decode(value).map_err(|e| format!("{} is not valid hex: {}", field, e))?;
+    bytes
+        .try_into()
+        .map_err(|_| format!("{} must be {} bytes", field, N))
+}
+
+impl NoteInput {
+    /// The key for the note's scope, the note and its Merkle path
+    ///
+    /// Internal notes have their own nullifier and proof generation keys, so
+    /// they are spent with the account's internal key.
+    fn to_spend(&self, account: &ExtendedSpendingKey) -> Result<(ExtendedSpendingKey, Note, MerklePath), String> {
+        let diversifier = Diversifier(decode_hex_array("diversifier", &self.diversifier)?);
+        let rseed = Rseed::AfterZip212(decode_hex_array("rseed", &self.rseed)?);
+        let extsk = match self.scope {
+            NoteScope::External => account.clone(),
+            NoteScope::Internal => account.derive_internal(),
+        };
+        let recipient = extsk
+            .to_diversifiable_full_viewing_key()
+            .fvk()
+            .vk
+            .to_payment_address(diversifier)
+            .ok_or("diversifier does not produce a valid address for this key")?;
+        let note = Note::from_parts(recipient, NoteValue::from_raw(self.value), rseed);
+
+        if self.auth_path.len() != TREE_DEPTH {
+            return Err(format!(
+                "auth_path must have {} nodes, found {}",
+                TREE_DEPTH,
+                self.auth_path.len()
+            ));
+        }
+        let auth_path = self
+            .auth_path
+            .iter()
+            .map(|node| {
+                let bytes = decode_hex_array("auth_path node", node)?;
+                Option::from(Node::from_bytes(bytes))
+                    .ok_or_else(|| "auth_path node is not a valid field element".to_string())
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        let merkle_path = MerklePath::from_parts(auth_path, Position::from(self.position))
+            .map_err(|_| "invalid Merkle path".to_string())?;
+        Ok((extsk, note, merkle_path))
+    }
+}
+
+/// Assemble and sign the transaction described by `request`, leaving the proofs to be filled in
+pub fn build(request: &SkeletonRequest, address_network: zcash_address::Network) -> Result<Skeleton, String> {
+    let network = match address_network {
+        zcash_address::Network::Main => Network::MainNetwork,
+        zcash_address::Network::Test => Network::TestNetwork,
+        zcash_address::Network::Regtest => return Err("Regtest is not supported".to_string()),
+    };
+    let extsk = &crate::keys::decode_spending_key(&request.spending_key, address_network)?;
+    let target_height = BlockHeight::from_u32(request.target_height);
+    let branch_id = BranchId::for_height(&network, target_height);
+    if !matches!(TxVersion::suggested_for_branch(branch_id), TxVersion::Zip225) {
+        return Err(format!(
+            "Unproven transactions need the v5 format, but height {} is on the {:?} branch",
+            target_height, branch_id
+        ));
+    }
+
+    let spends = request
+        .notes
+        .iter()
+        .enumerate()
+        .map(|(i, note)| note.to_spend(extsk).map_err(|e| format!("Note {}: {}", i, e)))
+        .collect::<Result<Vec<_>, String>>()?;
+    let (_, first_note, first_path) = spends.first().ok_or("At least one note is needed")?;
+    let sapling_anchor = Some(Anchor::from(first_path.root(Node::from_cmu(&first_note.cmu()))));
+
+    let total_in = spends
+        .iter()
+        .try_fold(0u64, |total, (_, note, _)| total.checked_add(note.value().inner()))
+        .ok_or("Note values overflow")?;
+    let total_out = request
+        .recipients
+        .iter()
+        .try_fold(request.fee, |total, r| total.checked_add(r.amount))
+        .ok_or("Recipient amounts overflow")?;
+    let change = total_in.checked_sub(total_out).ok_or_else(|| {
+        format!("Insufficient funds: {} zatoshi in notes, {} needed including the fee", total_in, total_out)
+    })?;
+
+    let dfvk = extsk.to_diversifiable_full_viewing_key();
+    let ovk = Some(dfvk.fvk().ovk);
+    let mut builder = Builder::new(
+        network,
+        target_height,
+        BuildConfig::Standard {
+            sapling_anchor,
+            orchard_anchor: None,
+        },
+    );
+    for (key, note, merkle_path) in spends {
+        builder
+            .add_sapling_spend::<Infallible>(&key, note, merkle_path)
+            .map_err(|e| format!("Failed to add spend: {}", e))?;
+    }
+
+    let mut outputs = Vec::with_capacity(request.recipients.len() + 1);
+    for (i, recipient) in request.recipients.iter().enumerate() {
+        let address = crate::address::sapling_address(&recipient.address, address_network)
+            .map_err(|e| format!("Recipient {}: {}", i, e))?;
+        let memo = if recipient.memo.is_empty() {
+            // Zero bytes would be an all-zero text memo rather than "no memo"
+            MemoBytes::empty()
+        } else {
+            let memo = hex::decode(&recipient.memo).map_err(|e| format!("Recipient {}: memo is not valid hex: {}", i, e))?;
+            MemoBytes::from_bytes(&memo).map_err(|_| format!("Recipient {}: memo exceeds 512 bytes", i))?
+        };
+        outputs.push((address, recipient.amount, memo));
+    }
+    if change > 0 {
+        outputs.push((dfvk.default_address().1, change, MemoBytes::empty()));
+    }
+    for (address, value, memo) in outputs {
+        let value = NonNegativeAmount::from_u64(value).map_err(|_| format!("Output value {} is out of range", value))?;
+        builder
+            .add_sapling_output::<Infallible>(ovk, address, value, memo)
+            .map_err(|e| format!("Failed to add output: {}", e))?;
+    }
+
+    let fee = NonNegativeAmount::from_u64(request.fee).map_err(|_| format!("Fee {} is out of range", request.fee))?;
+    let prover = DeferredProver::default();
+    let result = builder
+        .build(OsRng, &prover, &prover, &fixed::FeeRule::non_standard(fee))
+        .map_err(|e| format!("Transaction build failed: {}", e))?;
+
+    let tx = result.transaction();
+    let mut raw = Vec::new();
+    tx.write(&mut raw)
+        .map_err(|e| format!("Failed to serialize transaction: {}", e))?;
+
+    let inputs = prover.into_inputs(extsk);
+    Ok(Skeleton {
+        transaction: hex::encode(raw),
+        txid: tx.txid().to_string(),
+        spends: inputs.spends,
+        outputs: inputs.outputs,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use incrementalmerkletree::witness::IncrementalWitness;
+    use sapling::CommitmentTree;
+    use zcash_primitives::consensus::{NetworkUpgrade, Parameters};
+
+    /// A request spending one `value` note of the account derived from `seed`
+    fn request(seed: [u8; 32], value: u64, recipients: Vec<RecipientInput>, fee: u64) -> SkeletonRequest {
+        let keys = crate::keys::derive_from_seed(&seed, 0, zcash_address::Network::Test).unwrap();
+        let extsk = crate::keys::decode_spending_key(&keys.spending_key, zcash_address::Network::Test).unwrap();
+        let address = extsk.default_address().1;
+        let rseed = [1; 32];
+        let note = Note::from_parts(address, NoteValue::from_raw(value), Rseed::AfterZip212(rseed));
+        let mut tree = CommitmentTree::empty();
+        tree.append(Node::from_cmu(&note.cmu())).unwrap();
+        let path = IncrementalWitness::from_tree(tree).path().unwrap();
+
+        SkeletonRequest {
+            spending_key: keys.spending_key,
+            target_height: u32::from(Network::TestNetwork.activation_height(NetworkUpgrade::Nu5).unwrap()),
+            notes: vec![NoteInput {
+                value,
+                diversifier: hex::encode(address.diversifier().0),
+                scope: NoteScope::External,
+                rseed: hex::encode(rseed),
+                position: u64::from(path.position()),
+                auth_path: path.path_elems().iter().map(|node| hex::encode(node.to_bytes())).collect(),
+            }],
+            recipients,
+            fee,
+        }
+    }
+
+    #[test]
+    fn records_a_witness_for_every_spend_and_output() {
+        let recipient = crate::keys::derive_from_seed(&[8; 32], 0, zcash_address::Network::Test).unwrap();
+        let recipients = vec![RecipientInput {
+            address: recipient.address,
+            amount: 40_000,
+            memo: String::new(),
+        }];
+        let request = request([7; 32], 60_000, recipients, 10_000);
+
+        let skeleton = build(&request, zcash_address::Network::Test).unwrap();
+        assert_eq!(skeleton.spends.len(), 1);
+        assert_eq!(skeleton.spends[0].value, Some(60_000));
+        // The recipient and 10_000 of change; the builder shuffles outputs
+        let mut values: Vec<_> = skeleton.outputs.iter().map(|output| output.value).collect();
+        values.sort();
+        assert_eq!(values, vec![Some(10_000), Some(40_000)]);
+    }
+
+    #[test]
+    fn rejects_recipients_above_the_notes() {
+        let recipient = crate::keys::derive_from_seed(&[8; 32], 0, zcash_address::Network::Test).unwrap();
+        let recipients = vec![RecipientInput {
+            address: recipient.address,
+            amount: 60_000,
+            memo: String::new(),
+        }];
+        let request = request([7; 32], 60_000, recipients, 10_000);
+        let error = build(&request, zcash_address::Network::Test).err().unwrap();
+        assert!(error.starts_with("Insufficient funds"), "{}", error);
+    }
+}
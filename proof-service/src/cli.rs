@@ -0,0 +1,121 @@
+//! Sending from the command line, without running the server
+//!
+//! `zcash-proof-service send` builds, proves and broadcasts one transaction
+//! through the same handler as `POST /proofs/build-transaction`, then prints
+//! its txid on the last line of output. `--notes` names a JSON file holding
+//! the notes to spend, in the build request's `notes` format. Everything else
+//! (network, lightwalletd endpoints, limits) comes from the same `ZMAIL_*`
+//! variables as the server.
+
+use actix_web::{body, web};
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::config::Config;
+use crate::keys;
+use crate::lightwalletd::LightwalletdClient;
+use crate::pending::PendingSpends;
+use crate::proof_limit::ProofLimiter;
+use crate::{build_transaction, lightwalletd_endpoints, BuildTransactionRequest};
+
+const USAGE: &str = "Usage: zcash-proof-service send (--key <spending key> | --key-file <path>) \
+                     --to <address> --amount <zatoshi> [--memo <text>] --notes <notes.json> \
+                     [--target-height <height>] [--lightwalletd <endpoint>]";
+
+/// `--name value` pairs
+fn parse_flags(args: &[String]) -> Result<HashMap<&str, &str>, String> {
+    let mut flags = HashMap::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let name = arg
+            .strip_prefix("--")
+            .ok_or_else(|| format!("Unexpected argument {}", arg))?;
+        let value = args.next().ok_or_else(|| format!("--{} needs a value", name))?;
+        flags.insert(name, value.as_str());
+    }
+    Ok(flags)
+}
+
+/// Run `send` with the arguments after it, returning the process exit code
+pub async fn send(args: &[String]) -> i32 {
+    match run_send(args).await {
+        Ok(txid) => {
+            println!("{}", txid);
+            0
+        }
+        Err(e) => {
+            eprintln!("[ProofService] ❌ {}", e);
+            1
+        }
+    }
+}
+
+async fn run_send(args: &[String]) -> Result<String, String> {
+    let flags = parse_flags(args).map_err(|e| format!("{}\n{}", e, USAGE))?;
+    let required = |name: &str| {
+        flags
+            .get(name)
+            .copied()
+            .ok_or_else(|| format!("Missing --{}\n{}", name, USAGE))
+    };
+    let spending_key = match (flags.get("key"), flags.get("key-file")) {
+        (Some(key), None) => key.to_string(),
+        (None, Some(path)) => keys::read_spending_key_file(Path::new(path))?,
+        _ => return Err(format!("Give either --key or --key-file\n{}", USAGE)),
+    };
+    let notes_file = required("notes")?;
+    let notes = std::fs::read_to_string(notes_file).map_err(|e| format!("Failed to read {}: {}", notes_file, e))?;
+    let notes: serde_json::Value =
+        serde_json::from_str(&notes).map_err(|e| format!("Invalid notes file {}: {}", notes_file, e))?;
+
+    let config = Config::from_env();
+    let endpoints = lightwalletd_endpoints(flags.get("lightwalletd").copied(), &config);
+    let mut client = LightwalletdClient::connect(&endpoints).await?;
+    let target_height = match flags.get("target-height") {
+        Some(height) => height
+            .parse::<u32>()
+            .map_err(|_| format!("Invalid --target-height {}", height))?,
+        None => client.latest_block().await?.height as u32 + 1,
+    };
+
+    // Go through the request type, so the CLI gets the same defaults as HTTP clients
+    let request: BuildTransactionRequest = serde_json::from_value(serde_json::json!({
+        "spending_key": spending_key,
+        "from_address": "",
+        "to_address": required("to")?,
+        "amount": required("amount")?,
+        "memo": hex::encode(flags.get("memo").copied().unwrap_or("")),
+        "notes": notes,
+        "target_height": target_height,
+    }))
+    .map_err(|e| format!("Invalid build request: {}", e))?;
+
+    let limiter = ProofLimiter::new(config.max_concurrent_proofs, config.max_queued_proofs);
+    // With a pending spends file, sends from the CLI and the server don't pick the same notes
+    let pending_spends = PendingSpends::load(config.pending_spends_file.clone());
+    let response = build_transaction(
+        web::Json(request),
+        web::Data::new(config),
+        web::Data::new(limiter),
+        web::Data::new(pending_spends),
+    )
+    .await
+        .map_err(|e| format!("Build failed: {}", e))?;
+    let status = response.status();
+    let response = body::to_bytes(response.into_body())
+        .await
+        .map_err(|e| format!("Build failed: {}", e))?;
+    let built: serde_json::Value =
+        serde_json::from_slice(&response).map_err(|e| format!("Build failed: {}", e))?;
+    if !status.is_success() {
+        let error = built["error"].as_str().or(built["message"].as_str()).unwrap_or("unknown error");
+        return Err(format!("Build failed: {}", error));
+    }
+    let raw = hex::decode(built["raw_transaction"].as_str().unwrap_or_default())
+        .map_err(|e| format!("Build returned no transaction: {}", e))?;
+    let txid = built["txid"].as_str().unwrap_or_default().to_string();
+
+    client.send_transaction(&raw).await?;
+    println!("[ProofService] ✅ Broadcast transaction {}", txid);
+    Ok(txid)
+}
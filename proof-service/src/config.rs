@@ -0,0 +1,184 @@
+use std::env;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+use zcash_primitives::consensus::Network;
+
+use crate::consensus;
+
+/// Service configuration, read once from `ZMAIL_*` environment variables at startup
+#[derive(Clone, Debug)]
+pub struct Config {
+    /// Maximum number of outputs a single build may expand to (`ZMAIL_MAX_OUTPUTS`)
+    pub max_outputs: usize,
+    /// Enables the `/debug/*` endpoints (`ZMAIL_DEBUG=1`). Never set in production.
+    pub debug: bool,
+    /// Network keys, addresses and transactions are for (`ZMAIL_NETWORK`: "main" or "test")
+    pub network: Network,
+    /// Trusted lightwalletd gRPC endpoints, tried in order with failover
+    /// (`ZMAIL_LIGHTWALLETD_ENDPOINTS`, comma-separated, or a single `ZMAIL_LIGHTWALLETD_ENDPOINT`);
+    /// requests may override them
+    pub lightwalletd_endpoints: Vec<String>,
+    /// Download missing proving parameters to ~/.zcash-params at startup (`ZMAIL_AUTO_DOWNLOAD=1`)
+    pub auto_download: bool,
+    /// Time a client has to send the request head before getting a 408
+    /// (`ZMAIL_CLIENT_REQUEST_TIMEOUT_MS`)
+    pub client_request_timeout: Duration,
+    /// Time a client has to acknowledge a closing connection (`ZMAIL_CLIENT_DISCONNECT_TIMEOUT_MS`)
+    pub client_disconnect_timeout: Duration,
+    /// How long an idle connection stays open for the next request (`ZMAIL_KEEP_ALIVE_MS`; 0 disables).
+    /// Clients proving in batches should reuse connections rather than reconnect per proof.
+    pub keep_alive: Duration,
+    /// PEM certificate chain and private key to serve HTTPS (and HTTP/2) with
+    /// (`ZMAIL_TLS_CERT` and `ZMAIL_TLS_KEY`); plain HTTP unless both are set
+    pub tls_cert: Option<PathBuf>,
+    pub tls_key: Option<PathBuf>,
+    /// Path prefix every route is served under, for deployments behind a reverse proxy on a
+    /// subpath (`ZMAIL_BASE_PATH`, e.g. "/api/zmail"). Empty serves from the root. Health
+    /// checks and metrics move with it.
+    pub base_path: String,
+    /// Concurrent connections per worker before new ones wait (`ZMAIL_MAX_CONNECTIONS`)
+    pub max_connections: usize,
+    /// Proofs generated at once across all workers (`ZMAIL_MAX_CONCURRENT_PROOFS`).
+    /// Each proof already uses every core, so this mainly bounds memory.
+    pub max_concurrent_proofs: usize,
+    /// Requests that may wait for a proving slot before new ones get a 503
+    /// (`ZMAIL_MAX_QUEUED_PROOFS`)
+    pub max_queued_proofs: usize,
+    /// Value each memo-only output carries, paid by the sender as postage
+    /// (`ZMAIL_MEMO_ONLY_VALUE`, zatoshi; default `consensus::MEMO_ONLY_VALUE`)
+    pub memo_only_value: u64,
+    /// Sapling tree checkpoint that witness building resumes from when a request gives none
+    /// (`ZMAIL_TREE_CHECKPOINT`: path to a `{ "height": ..., "sapling_tree": "..." }` JSON file)
+    pub tree_checkpoint: Option<PathBuf>,
+    /// Most proof requests one `/proofs/generate-batch` call may carry (`ZMAIL_MAX_BATCH_ITEMS`)
+    pub max_batch_items: usize,
+    /// Blocks below the chain tip that witnesses are anchored at when a request names no tip
+    /// (`ZMAIL_ANCHOR_DEPTH`). Deeper anchors survive larger reorgs; shallower ones let
+    /// recently received notes be spent sooner. A note can't be spent until its block is at
+    /// least this deep, so this should not exceed the minimum confirmations used in builds.
+    pub anchor_depth: u32,
+    /// How long `GET /chain/tip` answers from its last lightwalletd query (`ZMAIL_CHAIN_TIP_CACHE_SECS`).
+    /// Blocks come every 75 seconds on average, so a few seconds of staleness costs clients nothing.
+    pub chain_tip_cache: Duration,
+    /// Sapling outputs every build is padded to with dummy outputs, change included
+    /// (`ZMAIL_PAD_OUTPUTS`; 0, the default, disables padding). With a common target the
+    /// output count no longer gives away how long a message is; each padding output costs
+    /// a proof and, once outputs outnumber spends, 5000 zatoshi of ZIP-317 fee.
+    pub pad_outputs: usize,
+    /// Largest total attachment size one email may carry (`ZMAIL_MAX_ATTACHMENT_BYTES`).
+    /// Every 504 bytes is another output per recipient, so this bounds fees and proving work.
+    pub max_attachment_bytes: usize,
+    /// Drop the spend parameters from memory once no request is using them (`ZMAIL_LOW_MEMORY=1`).
+    /// Saves ~50MB resident between spends; every spend request re-reads and verifies the file.
+    pub low_memory: bool,
+    /// How often the service checks for proving parameters until they're all loaded
+    /// (`ZMAIL_PARAMS_WATCH_SECS`), so parameters downloaded or mounted after startup
+    /// are picked up without a restart
+    pub params_watch_interval: Duration,
+    /// Metadata-only deployment without proving parameters (`ZMAIL_NO_PARAMS=1`): nothing
+    /// loads or downloads them, and proving endpoints answer PROVING_DISABLED
+    pub no_params: bool,
+    /// One wallet uses this service (`ZMAIL_SINGLE_WALLET=1`): notes spent by built but
+    /// unconfirmed transactions are left out of later builds (see `pending`)
+    pub single_wallet: bool,
+    /// Where single-wallet mode keeps its pending spends across restarts (`ZMAIL_PENDING_SPENDS_FILE`)
+    pub pending_spends_file: Option<PathBuf>,
+    /// Trusted single-user mode: requests may name a local key file (`ZMAIL_LOCAL_KEYS=1`)
+    pub local_keys: bool,
+    /// Seed for reproducible transaction builds (`ZMAIL_RNG_SEED`); test builds only
+    #[cfg(feature = "deterministic")]
+    pub rng_seed: Option<u64>,
+}
+
+impl Config {
+    pub fn from_env() -> Self {
+        Config {
+            max_outputs: env_or("ZMAIL_MAX_OUTPUTS", 50),
+            debug: env_flag("ZMAIL_DEBUG"),
+            network: network_from_env(),
+            lightwalletd_endpoints: lightwalletd_endpoints_from_env(),
+            auto_download: env_flag("ZMAIL_AUTO_DOWNLOAD"),
+            local_keys: env_flag("ZMAIL_LOCAL_KEYS"),
+            low_memory: env_flag("ZMAIL_LOW_MEMORY"),
+            no_params: env_flag("ZMAIL_NO_PARAMS"),
+            params_watch_interval: Duration::from_secs(env_or("ZMAIL_PARAMS_WATCH_SECS", 30).max(1)),
+            single_wallet: env_flag("ZMAIL_SINGLE_WALLET"),
+            pending_spends_file: env::var("ZMAIL_PENDING_SPENDS_FILE").ok().map(PathBuf::from),
+            memo_only_value: env_or("ZMAIL_MEMO_ONLY_VALUE", consensus::MEMO_ONLY_VALUE).min(consensus::MAX_MONEY),
+            anchor_depth: env_or("ZMAIL_ANCHOR_DEPTH", 10),
+            chain_tip_cache: Duration::from_secs(env_or("ZMAIL_CHAIN_TIP_CACHE_SECS", 10)),
+            max_batch_items: env_or("ZMAIL_MAX_BATCH_ITEMS", 64),
+            pad_outputs: env_or("ZMAIL_PAD_OUTPUTS", 0),
+            max_attachment_bytes: env_or("ZMAIL_MAX_ATTACHMENT_BYTES", 256 * 1024),
+            tree_checkpoint: env::var("ZMAIL_TREE_CHECKPOINT").ok().map(PathBuf::from),
+            client_request_timeout: Duration::from_millis(env_or("ZMAIL_CLIENT_REQUEST_TIMEOUT_MS", 5_000)),
+            client_disconnect_timeout: Duration::from_millis(env_or("ZMAIL_CLIENT_DISCONNECT_TIMEOUT_MS", 1_000)),
+            keep_alive: Duration::from_millis(env_or("ZMAIL_KEEP_ALIVE_MS", 5_000)),
+            tls_cert: env::var("ZMAIL_TLS_CERT").ok().map(PathBuf::from),
+            tls_key: env::var("ZMAIL_TLS_KEY").ok().map(PathBuf::from),
+            base_path: base_path_from_env(),
+            max_connections: env_or("ZMAIL_MAX_CONNECTIONS", 256),
+            max_concurrent_proofs: env_or("ZMAIL_MAX_CONCURRENT_PROOFS", 2),
+            max_queued_proofs: env_or("ZMAIL_MAX_QUEUED_PROOFS", 32),
+            #[cfg(feature = "deterministic")]
+            rng_seed: env::var("ZMAIL_RNG_SEED").ok().and_then(|s| s.parse().ok()),
+        }
+    }
+}
+
+fn network_from_env() -> Network {
+    match env::var("ZMAIL_NETWORK").as_deref() {
+        Ok("test") | Ok("testnet") => Network::TestNetwork,
+        Ok("main") | Ok("mainnet") | Err(_) => Network::MainNetwork,
+        Ok(other) => {
+            println!("[ProofService] ⚠️  Unknown ZMAIL_NETWORK={:?}, using mainnet", other);
+            Network::MainNetwork
+        }
+    }
+}
+
+fn lightwalletd_endpoints_from_env() -> Vec<String> {
+    let mut endpoints: Vec<String> = env::var("ZMAIL_LIGHTWALLETD_ENDPOINTS")
+        .unwrap_or_default()
+        .split(',')
+        .map(|e| e.trim().to_string())
+        .filter(|e| !e.is_empty())
+        .collect();
+    if let Ok(endpoint) = env::var("ZMAIL_LIGHTWALLETD_ENDPOINT") {
+        if !endpoints.contains(&endpoint) {
+            endpoints.insert(0, endpoint);
+        }
+    }
+    endpoints
+}
+
+/// `ZMAIL_BASE_PATH` with one leading slash and no trailing one ("api/zmail/" -> "/api/zmail")
+fn base_path_from_env() -> String {
+    let path = env::var("ZMAIL_BASE_PATH").unwrap_or_default();
+    let path = path.trim().trim_matches('/');
+    if path.is_empty() {
+        String::new()
+    } else {
+        format!("/{}", path)
+    }
+}
+
+/// Boolean flag: set to "1" or "true" to enable
+fn env_flag(name: &str) -> bool {
+    matches!(env::var(name).as_deref(), Ok("1") | Ok("true"))
+}
+
+/// Read and parse an environment variable, falling back to `default` if unset or invalid
+fn env_or<T: FromStr>(name: &str, default: T) -> T {
+    match env::var(name) {
+        Ok(value) => match value.parse() {
+            Ok(parsed) => parsed,
+            Err(_) => {
+                println!("[ProofService] ⚠️  Ignoring invalid {}={:?}, using default", name, value);
+                default
+            }
+        },
+        Err(_) => default,
+    }
+}
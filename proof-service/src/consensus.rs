@@ -0,0 +1,87 @@
+//! Consensus branch selection
+//!
+//! Transaction format, sighash and validity all depend on the network
+//! upgrade active at the target height. Getting this wrong doesn't fail
+//! locally; it produces transactions the network silently rejects.
+
+use zcash_primitives::{
+    consensus::{BlockHeight, BranchId, Network},
+    transaction::TxVersion,
+};
+
+/// Maximum value of any amount or sum of amounts: 21 million ZEC, in zatoshi
+pub const MAX_MONEY: u64 = 21_000_000 * 100_000_000;
+
+/// Default value of a memo-only output, in zatoshi
+///
+/// Sapling puts no lower bound on note values: zero-value notes are valid
+/// (dummy outputs rely on it) and the dust threshold only applies to
+/// transparent outputs. A message with no funds attached therefore carries
+/// zero unless the operator sets `ZMAIL_MEMO_ONLY_VALUE`, e.g. to zcashd's
+/// dust threshold of 54 zatoshi, so that no wallet treats it as dust.
+pub const MEMO_ONLY_VALUE: u64 = 0;
+
+/// Largest transaction zcashd relays (its `MAX_STANDARD_TX_SIZE`)
+///
+/// Consensus allows transactions up to the block size, but one over this
+/// size is never relayed to a miner, so builds and plans stay under it.
+pub const MAX_STANDARD_TX_SIZE: usize = 100_000;
+
+/// Bytes a Sapling output adds: cv, cmu and epk, the 580-byte note and
+/// 80-byte outgoing ciphertexts, and its proof
+pub const SAPLING_OUTPUT_SIZE: usize = 3 * 32 + 580 + 80 + 192;
+
+/// Bytes a Sapling spend adds in a v4 transaction: cv, anchor, nullifier, rk,
+/// proof and signature (v5 spends share one anchor, so they are smaller)
+pub const SAPLING_SPEND_SIZE: usize = 4 * 32 + 192 + 64;
+
+/// Expiry heights must be below this (ZIP-203); higher values are read as times
+///
+/// Builds expire `DEFAULT_TX_EXPIRY_DELTA` blocks after their target height,
+/// so this bounds the target height too.
+pub const TX_EXPIRY_HEIGHT_THRESHOLD: u32 = 500_000_000;
+
+/// Header, value balance, binding signature and list lengths, with room for
+/// a few transparent inputs and outputs
+const TX_OVERHEAD_SIZE: usize = 1_000;
+
+/// Most Sapling outputs, change included, a relayable transaction spending `spends` notes can have
+pub fn max_sapling_outputs(spends: usize) -> usize {
+    MAX_STANDARD_TX_SIZE.saturating_sub(TX_OVERHEAD_SIZE + spends * SAPLING_SPEND_SIZE) / SAPLING_OUTPUT_SIZE
+}
+
+/// Consensus branch active at `height` on `network`
+pub fn branch_id_for_height(network: Network, height: BlockHeight) -> BranchId {
+    BranchId::for_height(&network, height)
+}
+
+/// Whether Sapling transactions can be built on this branch
+pub fn supports_sapling(branch_id: BranchId) -> bool {
+    !matches!(branch_id, BranchId::Sprout | BranchId::Overwinter)
+}
+
+/// Transaction format to build on `branch_id`: v4 before NU5, v5 from NU5 on
+pub fn tx_version_for_branch(branch_id: BranchId) -> TxVersion {
+    TxVersion::suggested_for_branch(branch_id)
+}
+
+/// Whether a transaction of `version` is valid on `branch_id`
+///
+/// Each format is accepted from the upgrade that introduced it; v4 remains
+/// valid after NU5.
+pub fn version_allowed(version: TxVersion, branch_id: BranchId) -> bool {
+    match version {
+        TxVersion::Sprout(_) => branch_id == BranchId::Sprout,
+        TxVersion::Overwinter => branch_id == BranchId::Overwinter,
+        TxVersion::Sapling => supports_sapling(branch_id),
+        TxVersion::Zip225 => matches!(tx_version_for_branch(branch_id), TxVersion::Zip225),
+    }
+}
+
+/// Short network name used in responses and logs
+pub fn network_name(network: Network) -> &'static str {
+    match network {
+        Network::MainNetwork => "main",
+        Network::TestNetwork => "test",
+    }
+}
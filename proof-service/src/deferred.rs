@@ -0,0 +1,63 @@
+//! Deferred proving: assemble a transaction without Groth16 proofs
+//!
+//! The prover that records circuit witnesses instead of proving them lives
+//! in the `deferred-proving` crate, which the browser's wasm library uses
+//! too, so transactions built on either side export the same witnesses.
+
+pub use deferred_proving::{DeferredProver, OutputWitness, ProvingInputs, SpendWitness};
+
+#[cfg(test)]
+mod tests {
+    use crate::notes::NoteScope;
+    use crate::recipients::Receiver;
+    use crate::testing::{account, activation_height, received_notes, NETWORK};
+    use crate::transaction::{self, OutputKind, PlannedOutput};
+    use rand_chacha::{rand_core::SeedableRng, ChaCha20Rng};
+    use sapling::zip32::ExtendedSpendingKey;
+    use zcash_primitives::{consensus::NetworkUpgrade, memo::MemoBytes};
+
+    /// `(ak, nsk)` of `extsk`, with ak taken from its full viewing key (ak || nk || ovk)
+    fn expected_keys(extsk: &ExtendedSpendingKey) -> (String, String) {
+        let fvk = extsk.to_diversifiable_full_viewing_key().fvk().to_bytes();
+        (hex::encode(&fvk[..32]), hex::encode(extsk.expsk.proof_generation_key().nsk.to_bytes()))
+    }
+
+    #[test]
+    fn spends_carry_the_proof_generation_key_of_their_scope() {
+        let account = account();
+        let internal = account.derive_internal();
+        let external_address = account.default_address().1;
+        let internal_address = internal.default_address().1;
+        let notes = received_notes(
+            &[(NoteScope::External, external_address, 60_000), (NoteScope::Internal, internal_address, 50_000)],
+            1,
+        );
+        let spends = notes.iter().map(|note| note.to_spend(&account).unwrap()).collect();
+        let outputs = vec![PlannedOutput {
+            kind: OutputKind::Change,
+            ovk: None,
+            address: Receiver::Sapling(external_address),
+            value: 100_000,
+            memo: MemoBytes::empty(),
+        }];
+        let (_, inputs) = transaction::build_deferred(
+            NETWORK,
+            activation_height(NetworkUpgrade::Nu5),
+            &account,
+            vec![],
+            spends,
+            outputs,
+            None,
+            ChaCha20Rng::seed_from_u64(1),
+        )
+        .unwrap();
+
+        // The builder shuffles spends, so find each by its address
+        for (address, extsk) in [(external_address, &account), (internal_address, &internal)] {
+            let address = Some(hex::encode(address.to_bytes()));
+            let witness = inputs.spends.iter().find(|spend| spend.payment_address == address).unwrap();
+            assert_eq!((witness.ak.clone(), witness.nsk.clone()), expected_keys(extsk));
+        }
+        assert_ne!(expected_keys(&account), expected_keys(&internal));
+    }
+}
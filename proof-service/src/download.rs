@@ -0,0 +1,203 @@
+//! Background download of missing Sapling parameters
+//!
+//! Files are streamed to `<name>.part`, resumed with an HTTP Range request if
+//! a partial file is already there, and only renamed into place once their
+//! hash matches. A partial file that turns out to be complete is verified
+//! without downloading anything. Progress is logged and kept in memory for
+//! `/debug/download-status`.
+
+use serde::Serialize;
+use std::fs::File;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tokio::io::AsyncWriteExt;
+use zcash_proofs::{hashreader::HashReader, SAPLING_OUTPUT_HASH, SAPLING_SPEND_HASH};
+
+use crate::params::{OUTPUT_PARAMS, SPEND_PARAMS};
+
+const DOWNLOAD_URL: &str = "https://download.z.cash/downloads";
+
+/// Log progress every time this many more bytes have arrived
+const LOG_INTERVAL: u64 = 5 * 1024 * 1024;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DownloadState {
+    Downloading,
+    Verifying,
+    Done,
+    Failed,
+}
+
+/// Progress of one parameter file
+#[derive(Clone, Debug, Serialize)]
+pub struct DownloadStatus {
+    pub file: &'static str,
+    pub state: DownloadState,
+    pub downloaded: u64,
+    /// Unknown until the server sends a Content-Length
+    pub total: Option<u64>,
+    pub error: Option<String>,
+}
+
+static STATUS: Mutex<Vec<DownloadStatus>> = Mutex::new(Vec::new());
+
+/// Snapshot of every download started since the service came up
+pub fn status() -> Vec<DownloadStatus> {
+    STATUS.lock().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+fn update(file: &'static str, f: impl FnOnce(&mut DownloadStatus)) {
+    let mut statuses = STATUS.lock().unwrap_or_else(|e| e.into_inner());
+    let index = match statuses.iter().position(|s| s.file == file) {
+        Some(index) => index,
+        None => {
+            statuses.push(DownloadStatus {
+                file,
+                state: DownloadState::Downloading,
+                downloaded: 0,
+                total: None,
+                error: None,
+            });
+            statuses.len() - 1
+        }
+    };
+    f(&mut statuses[index]);
+}
+
+/// Download every parameter file missing from `dir`, one after another
+pub async fn download_missing(dir: PathBuf) {
+    if let Err(e) = tokio::fs::create_dir_all(&dir).await {
+        println!("[ProofService] ❌ Cannot create {:?}: {}", dir, e);
+        return;
+    }
+
+    for (file, hash) in [(OUTPUT_PARAMS, SAPLING_OUTPUT_HASH), (SPEND_PARAMS, SAPLING_SPEND_HASH)] {
+        if dir.join(file).exists() {
+            continue;
+        }
+        match download(&dir, file, hash).await {
+            Ok(()) => {
+                println!("[ProofService] ✅ Downloaded {} to {:?}", file, dir);
+                update(file, |s| s.state = DownloadState::Done);
+            }
+            Err(e) => {
+                println!("[ProofService] ❌ Download of {} failed: {}", file, e);
+                update(file, |s| {
+                    s.state = DownloadState::Failed;
+                    s.error = Some(e);
+                });
+            }
+        }
+    }
+}
+
+async fn download(dir: &Path, file: &'static str, expected_hash: &'static str) -> Result<(), String> {
+    let url = format!("{}/{}", DOWNLOAD_URL, file);
+    let part = dir.join(format!("{}.part", file));
+
+    // Resume from whatever a previous attempt left behind
+    let existing = tokio::fs::metadata(&part).await.map(|m| m.len()).unwrap_or(0);
+    let mut request = reqwest::Client::new().get(&url);
+    if existing > 0 {
+        println!("[ProofService] Resuming {} from {} bytes", file, existing);
+        request = request.header(reqwest::header::RANGE, format!("bytes={}-", existing));
+    }
+    let response = request
+        .send()
+        .await
+        .map_err(|e| format!("GET {} failed: {}", url, e))?;
+
+    // A crash between the last chunk and the rename leaves a complete .part,
+    // past whose end there is nothing to fetch: check it instead
+    if existing > 0 && response.status() == reqwest::StatusCode::RANGE_NOT_SATISFIABLE {
+        println!("[ProofService] {} is already fully downloaded, verifying", file);
+        update(file, |s| {
+            s.downloaded = existing;
+            s.total = Some(existing);
+            s.error = None;
+        });
+        return install(dir, &part, file, expected_hash).await;
+    }
+    let mut response = response
+        .error_for_status()
+        .map_err(|e| format!("GET {} failed: {}", url, e))?;
+
+    // A server that ignores the Range header sends the whole file again
+    let resumed = response.status() == reqwest::StatusCode::PARTIAL_CONTENT;
+    let mut downloaded = if resumed { existing } else { 0 };
+    let total = response.content_length().map(|len| len + downloaded);
+    update(file, |s| {
+        s.state = DownloadState::Downloading;
+        s.downloaded = downloaded;
+        s.total = total;
+        s.error = None;
+    });
+
+    let mut out = tokio::fs::OpenOptions::new()
+        .create(true)
+        .write(true)
+        .append(resumed)
+        .truncate(!resumed)
+        .open(&part)
+        .await
+        .map_err(|e| format!("Cannot write {:?}: {}", part, e))?;
+
+    let mut next_log = downloaded + LOG_INTERVAL;
+    while let Some(chunk) = response
+        .chunk()
+        .await
+        .map_err(|e| format!("Download of {} interrupted: {}", file, e))?
+    {
+        out.write_all(&chunk)
+            .await
+            .map_err(|e| format!("Cannot write {:?}: {}", part, e))?;
+        downloaded += chunk.len() as u64;
+        update(file, |s| s.downloaded = downloaded);
+
+        if downloaded >= next_log {
+            match total {
+                Some(total) => println!(
+                    "[ProofService] Downloading {}: {} / {} MB ({}%)",
+                    file,
+                    downloaded / 1024 / 1024,
+                    total / 1024 / 1024,
+                    downloaded * 100 / total.max(1)
+                ),
+                None => println!("[ProofService] Downloading {}: {} MB", file, downloaded / 1024 / 1024),
+            }
+            next_log = downloaded + LOG_INTERVAL;
+        }
+    }
+    out.flush()
+        .await
+        .map_err(|e| format!("Cannot write {:?}: {}", part, e))?;
+    drop(out);
+
+    install(dir, &part, file, expected_hash).await
+}
+
+/// Check the hash of the downloaded `part` and move it into place as `file`
+async fn install(dir: &Path, part: &Path, file: &'static str, expected_hash: &'static str) -> Result<(), String> {
+    update(file, |s| s.state = DownloadState::Verifying);
+    let verify_path = part.to_path_buf();
+    let hash = tokio::task::spawn_blocking(move || -> io::Result<String> {
+        let mut reader = HashReader::new(File::open(verify_path)?);
+        io::copy(&mut reader, &mut io::sink())?;
+        Ok(reader.into_hash())
+    })
+    .await
+    .map_err(|e| format!("Hash check of {} failed: {}", file, e))?
+    .map_err(|e| format!("Cannot read {:?}: {}", part, e))?;
+
+    if hash != expected_hash {
+        // Don't resume from a corrupt file next time
+        let _ = tokio::fs::remove_file(part).await;
+        return Err(format!("{} hash mismatch (got {}, expected {})", file, hash, expected_hash));
+    }
+
+    tokio::fs::rename(part, dir.join(file))
+        .await
+        .map_err(|e| format!("Cannot move {:?} into place: {}", part, e))
+}
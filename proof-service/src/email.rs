@@ -0,0 +1,143 @@
+//! Emails carried in zMail memos
+//!
+//! An email is sent as one long message (see `memo`) whose first fragment
+//! has type TYPE_EMAIL. Its content is length-prefixed, big-endian:
+//! [SubjectLen:2][Subject][BodyLen:4][Body][AttachmentCount:2], then for
+//! each attachment [NameLen:2][Name][ContentTypeLen:2][ContentType][DataLen:4][Data].
+//! Subject, body, names and content types are UTF-8.
+//!
+//! Every recipient gets its own copy of the fragments, so an email costs one
+//! output per fragment per recipient. Outputs past the per-transaction limit
+//! spill over into further transactions.
+
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::{fees, memo};
+
+/// A file sent with an email
+pub struct Attachment {
+    pub name: String,
+    pub content_type: String,
+    pub data: Vec<u8>,
+}
+
+/// Append `bytes` after their length, as a `width`-byte big-endian integer
+fn put(content: &mut Vec<u8>, what: &str, bytes: &[u8], width: usize) -> Result<(), String> {
+    let max = (1u64 << (8 * width)) - 1;
+    if bytes.len() as u64 > max {
+        return Err(format!("{} is {} bytes; at most {} fit", what, bytes.len(), max));
+    }
+    content.extend_from_slice(&(bytes.len() as u32).to_be_bytes()[4 - width..]);
+    content.extend_from_slice(bytes);
+    Ok(())
+}
+
+/// Serialize an email into the content its fragments carry
+pub fn encode(subject: &str, body: &str, attachments: &[Attachment]) -> Result<Vec<u8>, String> {
+    let mut content = Vec::new();
+    put(&mut content, "The subject", subject.as_bytes(), 2)?;
+    put(&mut content, "The body", body.as_bytes(), 4)?;
+    if attachments.len() > u16::MAX as usize {
+        return Err(format!("An email has at most {} attachments", u16::MAX));
+    }
+    content.extend_from_slice(&(attachments.len() as u16).to_be_bytes());
+    for (i, attachment) in attachments.iter().enumerate() {
+        let what = |field: &str| format!("Attachment {}'s {}", i, field);
+        put(&mut content, &what("name"), attachment.name.as_bytes(), 2)?;
+        put(&mut content, &what("content type"), attachment.content_type.as_bytes(), 2)?;
+        put(&mut content, &what("data"), &attachment.data, 4)?;
+    }
+    Ok(content)
+}
+
+/// Frame email content into the memo fields every recipient is sent
+pub fn fragments(content: &[u8], timestamp: u32) -> Vec<Vec<u8>> {
+    memo::framed(memo::TYPE_EMAIL, content, timestamp)
+}
+
+/// Memo fields one copy of `content_len` bytes of email content is framed into
+pub fn fragment_count(content_len: usize) -> usize {
+    content_len.div_ceil(memo::PAYLOAD_SIZE)
+}
+
+/// Transactions needed for `outputs` email outputs, and their total fee
+///
+/// Matches `plan`: at most `max_outputs` outputs per transaction, each
+/// spending `spends` notes, adding change and padded to `pad_to` outputs.
+pub fn cost(outputs: usize, max_outputs: usize, spends: usize, pad_to: usize) -> (usize, u64) {
+    let per_transaction = max_outputs.max(1);
+    let full = outputs / per_transaction;
+    let rest = outputs % per_transaction;
+    let mut fee = full as u64 * fees::zip317_fee(spends, (per_transaction + 1).max(pad_to));
+    if rest > 0 {
+        fee += fees::zip317_fee(spends, (rest + 1).max(pad_to));
+    }
+    (full + usize::from(rest > 0), fee)
+}
+
+/// A recipient of a planned email
+pub struct EmailRecipient {
+    pub address: String,
+    /// In zatoshi, carried on the recipient's first fragment
+    pub value: u64,
+}
+
+/// One output of a planned email
+#[derive(Serialize, ToSchema)]
+pub struct EmailOutput {
+    /// Index of the recipient in the request
+    pub recipient: usize,
+    pub address: String,
+    /// In zatoshi
+    pub value: u64,
+    /// Memo field (hex). A recipient's outputs in one transaction, in order,
+    /// are its memo_fragments in that build.
+    pub memo: String,
+}
+
+/// One transaction of a planned email
+#[derive(Serialize, ToSchema)]
+pub struct PlannedTransaction {
+    pub outputs: Vec<EmailOutput>,
+    /// Dummy outputs the build adds to reach the padding target
+    pub padding: usize,
+    /// ZIP-317 fee, assuming the planned spends, a change output and the padding
+    pub fee: u64,
+}
+
+/// Give each recipient a copy of `fragments`, at most `max_outputs` outputs per transaction
+///
+/// Each transaction is assumed to spend `spends` notes and to add a change
+/// output, which doesn't count towards `max_outputs`, as in a build. With a
+/// nonzero `pad_to` it is padded to that many outputs, change included.
+pub fn plan(
+    recipients: &[EmailRecipient],
+    fragments: &[Vec<u8>],
+    max_outputs: usize,
+    spends: usize,
+    pad_to: usize,
+) -> Vec<PlannedTransaction> {
+    let outputs: Vec<EmailOutput> = recipients
+        .iter()
+        .enumerate()
+        .flat_map(|(i, recipient)| {
+            fragments.iter().enumerate().map(move |(sequence, fragment)| EmailOutput {
+                recipient: i,
+                address: recipient.address.clone(),
+                value: if sequence == 0 { recipient.value } else { 0 },
+                memo: hex::encode(fragment),
+            })
+        })
+        .collect();
+
+    let mut transactions = Vec::new();
+    let mut outputs = outputs.into_iter().peekable();
+    while outputs.peek().is_some() {
+        let outputs: Vec<EmailOutput> = outputs.by_ref().take(max_outputs.max(1)).collect();
+        let padding = pad_to.saturating_sub(outputs.len() + 1);
+        let fee = fees::zip317_fee(spends, outputs.len() + 1 + padding);
+        transactions.push(PlannedTransaction { outputs, padding, fee });
+    }
+    transactions
+}
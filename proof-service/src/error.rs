@@ -0,0 +1,191 @@
+use actix_web::{
+    body::{BoxBody, MessageBody},
+    dev::{Service, ServiceRequest, ServiceResponse},
+    error::JsonPayloadError,
+    http::StatusCode,
+    HttpRequest, HttpResponse, ResponseError,
+};
+use futures_util::FutureExt;
+use serde::Serialize;
+use std::fmt;
+use std::future::Future;
+use std::panic::AssertUnwindSafe;
+use std::sync::atomic::{AtomicU64, Ordering};
+use utoipa::ToSchema;
+
+/// Structured error body returned to clients: `{ "code": ..., "message": ... }`
+///
+/// Codes are SCREAMING_SNAKE_CASE to match the frontend's `ErrorCode` enum.
+/// Validation failures also list every problem found under `errors`.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ApiError {
+    pub code: &'static str,
+    pub message: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<FieldError>,
+    #[serde(skip)]
+    status: StatusCode,
+}
+
+/// One problem with one field of a request
+#[derive(Debug, Serialize, ToSchema)]
+pub struct FieldError {
+    /// Path of the offending field, e.g. `recipients[2].amount`
+    pub field: String,
+    pub code: &'static str,
+    pub message: String,
+}
+
+impl ApiError {
+    pub fn new(status: StatusCode, code: &'static str, message: impl Into<String>) -> Self {
+        ApiError {
+            code,
+            message: message.into(),
+            errors: Vec::new(),
+            status,
+        }
+    }
+
+    pub fn bad_request(code: &'static str, message: impl Into<String>) -> Self {
+        Self::new(StatusCode::BAD_REQUEST, code, message)
+    }
+
+    pub fn bad_gateway(code: &'static str, message: impl Into<String>) -> Self {
+        Self::new(StatusCode::BAD_GATEWAY, code, message)
+    }
+
+    pub fn internal(code: &'static str, message: impl Into<String>) -> Self {
+        Self::new(StatusCode::INTERNAL_SERVER_ERROR, code, message)
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.code, self.message)
+    }
+}
+
+impl ResponseError for ApiError {
+    fn status_code(&self) -> StatusCode {
+        self.status
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::build(self.status).json(self)
+    }
+}
+
+/// `JsonConfig` error handler so malformed request bodies get the same JSON
+/// error shape as every other failure instead of actix's plain-text default
+pub fn json_error_handler(err: JsonPayloadError, _req: &HttpRequest) -> actix_web::Error {
+    println!("[ProofService] ❌ Rejected request body: {}", err);
+
+    let (code, message) = match &err {
+        JsonPayloadError::ContentType => (
+            "UNSUPPORTED_CONTENT_TYPE",
+            "Expected Content-Type: application/json".to_string(),
+        ),
+        JsonPayloadError::Overflow { .. } | JsonPayloadError::OverflowKnownLength { .. } => {
+            ("PAYLOAD_TOO_LARGE", err.to_string())
+        }
+        JsonPayloadError::Deserialize(e) => ("INVALID_JSON", format!("Invalid request body: {}", e)),
+        _ => ("INVALID_REQUEST", err.to_string()),
+    };
+
+    ApiError::bad_request(code, message).into()
+}
+
+/// Collects every validation problem in a request, to report them in one response
+///
+/// Clients can then highlight every bad field at once instead of fixing one
+/// per round trip.
+#[derive(Default)]
+pub struct Validation {
+    errors: Vec<FieldError>,
+}
+
+impl Validation {
+    pub fn add(&mut self, field: impl Into<String>, error: ApiError) {
+        self.errors.push(FieldError {
+            field: field.into(),
+            code: error.code,
+            message: error.message,
+        });
+    }
+
+    /// The value of `result`, or `None` after recording its error against `field`
+    pub fn check<T>(&mut self, field: impl Into<String>, result: Result<T, ApiError>) -> Option<T> {
+        result.map_err(|error| self.add(field, error)).ok()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.errors.is_empty()
+    }
+
+    /// A 400 listing the problems found, if any
+    ///
+    /// A single problem keeps its own code, so clients matching on codes see
+    /// the same error as before; several are reported as VALIDATION_FAILED.
+    pub fn finish(self) -> Result<(), ApiError> {
+        let (code, message) = match self.errors.as_slice() {
+            [] => return Ok(()),
+            [only] => (only.code, only.message.clone()),
+            errors => (
+                "VALIDATION_FAILED",
+                format!("The request has {} problems, listed in errors", errors.len()),
+            ),
+        };
+        Err(ApiError {
+            code,
+            message,
+            errors: self.errors,
+            status: StatusCode::BAD_REQUEST,
+        })
+    }
+}
+
+static NEXT_REQUEST_ID: AtomicU64 = AtomicU64::new(1);
+
+/// Middleware (for `App::wrap_fn`) turning a handler panic into a 500 JSON error
+///
+/// Without it a panic drops the connection and the client only sees a
+/// network error. Requests are numbered so a logged panic can be matched to
+/// the response the client got.
+pub fn catch_panics<S, B>(
+    req: ServiceRequest,
+    srv: &S,
+) -> impl Future<Output = Result<ServiceResponse<BoxBody>, actix_web::Error>>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error>,
+    B: MessageBody + 'static,
+{
+    let request_id = NEXT_REQUEST_ID.fetch_add(1, Ordering::Relaxed);
+    let http_req = req.request().clone();
+    let response = srv.call(req);
+
+    async move {
+        match AssertUnwindSafe(response).catch_unwind().await {
+            Ok(result) => result.map(ServiceResponse::map_into_boxed_body),
+            Err(panic) => {
+                let message = panic
+                    .downcast_ref::<&str>()
+                    .map(|s| s.to_string())
+                    .or_else(|| panic.downcast_ref::<String>().cloned())
+                    .unwrap_or_else(|| "unknown panic".to_string());
+                println!(
+                    "[ProofService] ❌ Request {} ({} {}) panicked: {}",
+                    request_id,
+                    http_req.method(),
+                    http_req.path(),
+                    message
+                );
+
+                let error = ApiError::internal(
+                    "INTERNAL_ERROR",
+                    format!("Unexpected internal error (request id {})", request_id),
+                );
+                Ok(ServiceResponse::new(http_req, error.error_response()))
+            }
+        }
+    }
+}
@@ -0,0 +1,97 @@
+//! Proving time estimates
+//!
+//! Proving speed varies widely between hosts, so estimates are based on
+//! proofs timed on this machine during warmup rather than fixed figures.
+//! The spend proof is only timed when the spend parameters are already
+//! present; until then its cost is taken as a multiple of the output proof's.
+
+use rand::rngs::OsRng;
+use sapling::value::ValueCommitTrapdoor;
+use serde::Serialize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Instant;
+use utoipa::ToSchema;
+
+use crate::params::Provers;
+use crate::proving;
+
+/// Spend proof time relative to an output proof, when the spend proof wasn't timed
+///
+/// The spend circuit is more than ten times the size of the output circuit;
+/// in practice a spend proof takes several output proofs' time.
+const SPEND_COST_FACTOR: u64 = 6;
+
+/// Measured proof times in microseconds, 0 until calibrated
+static OUTPUT_PROOF_MICROS: AtomicU64 = AtomicU64::new(0);
+static SPEND_PROOF_MICROS: AtomicU64 = AtomicU64::new(0);
+
+/// Proof times measured on this host
+#[derive(Clone, Copy, Serialize, ToSchema)]
+pub struct Calibration {
+    /// One output proof, in microseconds, once measured
+    pub output_proof_us: Option<u64>,
+    /// One spend proof, in microseconds, once measured
+    pub spend_proof_us: Option<u64>,
+}
+
+fn micros(since: Instant) -> u64 {
+    (since.elapsed().as_micros() as u64).max(1)
+}
+
+/// Time one proof of each type `prover` has parameters for, and use them for later estimates
+pub fn calibrate(prover: &Provers) -> Calibration {
+    let mut rng = OsRng;
+    let note = proving::bench_note(&mut rng);
+    let rcv = ValueCommitTrapdoor::random(&mut rng);
+    let start = Instant::now();
+    proving::prove_output(&prover.output, &note, rcv, &mut rng);
+    OUTPUT_PROOF_MICROS.store(micros(start), Ordering::Relaxed);
+
+    if let Some(spend) = &prover.spend {
+        let start = Instant::now();
+        proving::prove_dummy_spend(spend, &mut rng);
+        SPEND_PROOF_MICROS.store(micros(start), Ordering::Relaxed);
+    }
+    calibration()
+}
+
+/// The proof times measured so far
+pub fn calibration() -> Calibration {
+    let measured = |micros: &AtomicU64| Some(micros.load(Ordering::Relaxed)).filter(|&us| us > 0);
+    Calibration {
+        output_proof_us: measured(&OUTPUT_PROOF_MICROS),
+        spend_proof_us: measured(&SPEND_PROOF_MICROS),
+    }
+}
+
+/// Expected proving time for a build
+#[derive(Serialize, ToSchema)]
+pub struct Estimate {
+    /// Total for every proof, in milliseconds
+    pub estimated_ms: u64,
+    /// Measured time of one output proof
+    pub output_proof_ms: u64,
+    /// Time of one spend proof
+    pub spend_proof_ms: u64,
+    /// Whether spend_proof_ms was measured, rather than derived from the output proof time
+    pub spend_proof_measured: bool,
+}
+
+/// Proving time for `spends` spend and `outputs` output proofs, or `None` before calibration
+///
+/// Proofs within a build run one after another, so the times add up. Time
+/// spent waiting for a proving slot is not included.
+pub fn estimate(spends: u64, outputs: u64) -> Option<Estimate> {
+    let calibration = calibration();
+    let output = calibration.output_proof_us?;
+    let spend = calibration.spend_proof_us.unwrap_or(output * SPEND_COST_FACTOR);
+    let total = spends
+        .saturating_mul(spend)
+        .saturating_add(outputs.saturating_mul(output));
+    Some(Estimate {
+        estimated_ms: total / 1000,
+        output_proof_ms: output / 1000,
+        spend_proof_ms: spend / 1000,
+        spend_proof_measured: calibration.spend_proof_us.is_some(),
+    })
+}
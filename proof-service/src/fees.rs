@@ -0,0 +1,70 @@
+//! ZIP-317 conventional fee calculation
+
+/// Fee per logical action, in zatoshi
+pub const MARGINAL_FEE: u64 = 5_000;
+
+/// Number of logical actions covered by the minimum fee
+pub const GRACE_ACTIONS: usize = 2;
+
+/// Outputs the builder pads a non-empty Sapling bundle to
+pub const MIN_SAPLING_OUTPUTS: usize = 2;
+
+/// Sapling outputs in the built bundle, counting the builder's padding
+///
+/// The builder adds dummy outputs to any bundle with spends or outputs
+/// until it has `MIN_SAPLING_OUTPUTS`, and charges for them like real ones.
+pub fn padded_sapling_outputs(sapling_spends: usize, sapling_outputs: usize) -> usize {
+    if sapling_spends == 0 && sapling_outputs == 0 {
+        0
+    } else {
+        sapling_outputs.max(MIN_SAPLING_OUTPUTS)
+    }
+}
+
+/// ZIP-317 fee for a Sapling-only transaction
+///
+/// Sapling contributes max(spends, padded outputs) logical actions.
+pub fn zip317_fee(sapling_spends: usize, sapling_outputs: usize) -> u64 {
+    zip317_fee_with_transparent(0, 0, sapling_spends, sapling_outputs)
+}
+
+/// ZIP-317 fee for a transaction with transparent P2PKH inputs and outputs
+///
+/// ZIP-317 counts transparent actions by size; a P2PKH input or output is
+/// exactly one action, so the transparent part is max(inputs, outputs).
+/// Sapling outputs are counted after padding, so this is the fee the
+/// builder's `zip317::FeeRule` charges for the same transaction.
+pub fn zip317_fee_with_transparent(
+    transparent_inputs: usize,
+    transparent_outputs: usize,
+    sapling_spends: usize,
+    sapling_outputs: usize,
+) -> u64 {
+    let sapling_outputs = padded_sapling_outputs(sapling_spends, sapling_outputs);
+    let logical_actions =
+        transparent_inputs.max(transparent_outputs) + sapling_spends.max(sapling_outputs);
+    MARGINAL_FEE * logical_actions.max(GRACE_ACTIONS) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shielding_to_one_output_pays_for_the_padding() {
+        // One coin into one Sapling output: the builder pads to two outputs
+        assert_eq!(zip317_fee_with_transparent(1, 0, 0, 1), 15_000);
+    }
+
+    #[test]
+    fn deshielding_one_note_pays_for_the_padding() {
+        assert_eq!(zip317_fee_with_transparent(0, 1, 1, 0), 15_000);
+        assert_eq!(zip317_fee_with_transparent(0, 1, 1, 1), 15_000);
+    }
+
+    #[test]
+    fn fully_transparent_transactions_have_no_padding() {
+        assert_eq!(padded_sapling_outputs(0, 0), 0);
+        assert_eq!(zip317_fee_with_transparent(3, 1, 0, 0), 15_000);
+    }
+}
@@ -0,0 +1,54 @@
+//! Hex encoding for byte fields in JSON bodies (`#[serde(with = "hex_bytes")]`)
+//!
+//! Binary request and response fields are hex strings. serde's default for
+//! `Vec<u8>`, an array of numbers, is easy to get wrong from JavaScript
+//! (signed bytes, typed arrays serialized as objects) and triples the size.
+//!
+//! Migration: responses are always hex. Requests still accept number arrays
+//! for clients written against the old format, but new clients should send
+//! hex, and array support may be dropped in a later release.
+
+use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+/// A byte field as a request may send it
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Encoded {
+    Hex(String),
+    /// Legacy number array
+    Array(Vec<u8>),
+}
+
+impl Encoded {
+    fn decode<E: Error>(self) -> Result<Vec<u8>, E> {
+        match self {
+            Encoded::Hex(hex) => hex::decode(&hex).map_err(|e| E::custom(format!("invalid hex: {}", e))),
+            Encoded::Array(bytes) => Ok(bytes),
+        }
+    }
+}
+
+pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&hex::encode(bytes))
+}
+
+pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+    Encoded::deserialize(deserializer)?.decode()
+}
+
+/// Lists of byte fields, e.g. memo fragments (`#[serde(with = "hex_bytes::list")]`)
+pub mod list {
+    use super::Encoded;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(list: &[Vec<u8>], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(list.iter().map(hex::encode))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<Vec<u8>>, D::Error> {
+        Vec::<Encoded>::deserialize(deserializer)?
+            .into_iter()
+            .map(Encoded::decode)
+            .collect()
+    }
+}
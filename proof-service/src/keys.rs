@@ -0,0 +1,340 @@
+//! Bech32 encoding of Sapling keys and addresses for the configured network,
+//! and ZIP 32 / BIP-39 key derivation
+
+use bech32::{FromBase32, ToBase32, Variant};
+use bip0039::{English, Mnemonic};
+use rand::RngCore;
+use sapling::{
+    zip32::{ChildIndex, DiversifiableFullViewingKey, ExtendedFullViewingKey, ExtendedSpendingKey},
+    PaymentAddress,
+};
+use serde::Serialize;
+use std::path::Path;
+use utoipa::ToSchema;
+use zcash_address::{
+    unified::{self, Container, Encoding},
+    ConversionError, TryFromRawAddress, ZcashAddress,
+};
+use zcash_primitives::{
+    consensus::{Network, Parameters},
+    legacy::TransparentAddress,
+    zip32::DiversifierIndex,
+};
+
+use crate::consensus;
+
+/// Decode a Bech32 string, checking it carries the expected human-readable prefix
+fn decode_bech32(expected_hrp: &str, encoded: &str) -> Result<Vec<u8>, String> {
+    let (hrp, data, variant) =
+        bech32::decode(encoded).map_err(|e| format!("invalid Bech32 encoding: {}", e))?;
+    if hrp != expected_hrp {
+        return Err(format!("expected prefix '{}', found '{}'", expected_hrp, hrp));
+    }
+    if variant != Variant::Bech32 {
+        return Err("expected Bech32 encoding, found Bech32m".to_string());
+    }
+    Vec::<u8>::from_base32(&data).map_err(|e| format!("invalid Bech32 data: {}", e))
+}
+
+/// Read an encoded spending key from a local file, ignoring surrounding whitespace
+pub fn read_spending_key_file(path: &Path) -> Result<String, String> {
+    #[cfg(unix)]
+    if let Ok(metadata) = std::fs::metadata(path) {
+        use std::os::unix::fs::PermissionsExt;
+        if metadata.permissions().mode() & 0o077 != 0 {
+            println!("[ProofService] ⚠️  Key file {:?} is readable by other users; chmod 600 it", path);
+        }
+    }
+    let contents = std::fs::read_to_string(path)
+        .map_err(|e| format!("cannot read key file {:?}: {}", path, e))?;
+    Ok(contents.trim().to_string())
+}
+
+/// Decode a `secret-extended-key-*` Sapling extended spending key
+pub fn decode_extended_spending_key(
+    network: &Network,
+    encoded: &str,
+) -> Result<ExtendedSpendingKey, String> {
+    let bytes = decode_bech32(network.hrp_sapling_extended_spending_key(), encoded)?;
+    ExtendedSpendingKey::read(&bytes[..]).map_err(|e| format!("invalid spending key: {}", e))
+}
+
+/// The network whose spending key prefix `encoded` carries, if it has either
+pub fn spending_key_network(encoded: &str) -> Option<Network> {
+    let (hrp, _, _) = bech32::decode(encoded.trim()).ok()?;
+    [Network::MainNetwork, Network::TestNetwork]
+        .into_iter()
+        .find(|network| network.hrp_sapling_extended_spending_key() == hrp)
+}
+
+/// Decode a `zxviews...` Sapling extended full viewing key
+pub fn decode_extended_full_viewing_key(
+    network: &Network,
+    encoded: &str,
+) -> Result<ExtendedFullViewingKey, String> {
+    let bytes = decode_bech32(network.hrp_sapling_extended_full_viewing_key(), encoded)?;
+    ExtendedFullViewingKey::read(&bytes[..]).map_err(|e| format!("invalid full viewing key: {}", e))
+}
+
+/// A decoded recipient address, of any type
+pub enum RecipientAddress {
+    Sprout,
+    Sapling(PaymentAddress),
+    Transparent(TransparentAddress),
+    /// A unified address and the receivers it contains
+    Unified {
+        sapling: Option<PaymentAddress>,
+        orchard: bool,
+        transparent: Option<TransparentAddress>,
+    },
+}
+
+impl RecipientAddress {
+    /// Whether the address has a receiver that can carry a memo
+    pub fn has_shielded_receiver(&self) -> bool {
+        match self {
+            RecipientAddress::Sprout | RecipientAddress::Sapling(_) => true,
+            RecipientAddress::Transparent(_) => false,
+            RecipientAddress::Unified { sapling, orchard, .. } => sapling.is_some() || *orchard,
+        }
+    }
+
+    /// Whether the address's only shielded receiver is Orchard, which the service can't pay yet
+    pub fn orchard_only(&self) -> bool {
+        matches!(self, RecipientAddress::Unified { sapling: None, orchard: true, .. })
+    }
+}
+
+fn sapling_receiver(data: [u8; 43]) -> Result<PaymentAddress, ConversionError<String>> {
+    PaymentAddress::from_bytes(&data)
+        .ok_or_else(|| ConversionError::User("not a valid Sapling payment address".to_string()))
+}
+
+impl TryFromRawAddress for RecipientAddress {
+    type Error = String;
+
+    fn try_from_raw_sprout(_data: [u8; 64]) -> Result<Self, ConversionError<Self::Error>> {
+        Ok(RecipientAddress::Sprout)
+    }
+
+    fn try_from_raw_sapling(data: [u8; 43]) -> Result<Self, ConversionError<Self::Error>> {
+        sapling_receiver(data).map(RecipientAddress::Sapling)
+    }
+
+    fn try_from_raw_unified(data: unified::Address) -> Result<Self, ConversionError<Self::Error>> {
+        let mut sapling = None;
+        let mut orchard = false;
+        let mut transparent = None;
+        for receiver in data.items() {
+            match receiver {
+                unified::Receiver::Sapling(data) => sapling = Some(sapling_receiver(data)?),
+                unified::Receiver::Orchard(_) => orchard = true,
+                unified::Receiver::P2pkh(data) => transparent = Some(TransparentAddress::PublicKey(data)),
+                unified::Receiver::P2sh(data) => transparent = Some(TransparentAddress::Script(data)),
+                unified::Receiver::Unknown { .. } => {}
+            }
+        }
+        Ok(RecipientAddress::Unified { sapling, orchard, transparent })
+    }
+
+    fn try_from_raw_transparent_p2pkh(data: [u8; 20]) -> Result<Self, ConversionError<Self::Error>> {
+        Ok(RecipientAddress::Transparent(TransparentAddress::PublicKey(data)))
+    }
+
+    fn try_from_raw_transparent_p2sh(data: [u8; 20]) -> Result<Self, ConversionError<Self::Error>> {
+        Ok(RecipientAddress::Transparent(TransparentAddress::Script(data)))
+    }
+}
+
+/// Decode a recipient address of any type (Sapling, unified or transparent) for `network`
+pub fn decode_recipient(network: &Network, encoded: &str) -> Result<RecipientAddress, String> {
+    let address_network = match network {
+        Network::MainNetwork => zcash_address::Network::Main,
+        Network::TestNetwork => zcash_address::Network::Test,
+    };
+    ZcashAddress::try_from_encoded(encoded)
+        .map_err(|e| e.to_string())?
+        .convert_if_network::<RecipientAddress>(address_network)
+        .map_err(|e| e.to_string())
+}
+
+fn encode_bech32(hrp: &str, data: &[u8]) -> String {
+    bech32::encode(hrp, data.to_base32(), Variant::Bech32).expect("HRPs are valid")
+}
+
+/// Encode a Sapling payment address as `zs1...`
+pub fn encode_payment_address(network: &Network, address: &PaymentAddress) -> String {
+    encode_bech32(network.hrp_sapling_payment_address(), &address.to_bytes())
+}
+
+/// A diversified address of `dfvk` at a random diversifier index
+///
+/// Addresses at different indices can't be linked to each other without the
+/// viewing key, but all of them receive to the same account.
+pub fn random_address<R: RngCore>(dfvk: &DiversifiableFullViewingKey, rng: &mut R) -> PaymentAddress {
+    let mut index = [0u8; 11];
+    rng.fill_bytes(&mut index);
+    // Roughly half of all indices give a valid diversifier, so the search is short
+    dfvk.find_address(DiversifierIndex::from(index))
+        .map(|(_, address)| address)
+        .unwrap_or_else(|| dfvk.default_address().1)
+}
+
+/// Encode an extended full viewing key as `zxviews...`
+pub fn encode_extended_full_viewing_key(network: &Network, extfvk: &ExtendedFullViewingKey) -> String {
+    let mut bytes = vec![];
+    extfvk.write(&mut bytes).expect("writing to a Vec can't fail");
+    encode_bech32(network.hrp_sapling_extended_full_viewing_key(), &bytes)
+}
+
+/// Decode a BIP-39 mnemonic (validating its checksum) into a 64-byte seed
+pub fn seed_from_mnemonic(phrase: &str, passphrase: &str) -> Result<[u8; 64], String> {
+    let mnemonic = Mnemonic::<English>::from_phrase(phrase)
+        .map_err(|e| format!("invalid mnemonic: {}", e))?;
+    Ok(mnemonic.to_seed(passphrase))
+}
+
+/// Derive the Sapling account key at m/32'/coin_type'/account' (ZIP 32)
+pub fn account_spending_key(
+    network: &Network,
+    seed: &[u8],
+    account: u32,
+) -> Result<ExtendedSpendingKey, String> {
+    if !(32..=252).contains(&seed.len()) {
+        return Err(format!("seed must be 32 to 252 bytes, got {}", seed.len()));
+    }
+    if account >= (1 << 31) {
+        return Err(format!("account index {} is out of range", account));
+    }
+
+    Ok(ExtendedSpendingKey::from_path(
+        &ExtendedSpendingKey::master(seed),
+        &[
+            ChildIndex::hardened(32),
+            ChildIndex::hardened(network.coin_type()),
+            ChildIndex::hardened(account),
+        ],
+    ))
+}
+
+/// What a pasted secret string turned out to be
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum KeyKind {
+    SpendingKey,
+    FullViewingKey,
+    IncomingViewingKey,
+    Seed,
+    Unrecognized,
+}
+
+/// Result of classifying a key string; never contains the key itself
+#[derive(Serialize, ToSchema)]
+pub struct KeyClassification {
+    pub kind: KeyKind,
+    /// Encoding the key was recognized in, e.g. "sapling_extended", "unified", "bip39", "hex"
+    pub format: Option<&'static str>,
+    /// Network the key is for, when the encoding says
+    pub network: Option<&'static str>,
+}
+
+impl KeyClassification {
+    fn new(kind: KeyKind, format: &'static str, network: Option<&'static str>) -> Self {
+        KeyClassification {
+            kind,
+            format: Some(format),
+            network,
+        }
+    }
+}
+
+fn unified_network_name(network: zcash_address::Network) -> &'static str {
+    match network {
+        zcash_address::Network::Main => "main",
+        zcash_address::Network::Test => "test",
+        zcash_address::Network::Regtest => "regtest",
+    }
+}
+
+/// Work out whether `input` is a spending key, viewing key or seed, on either network
+pub fn classify_key(input: &str) -> KeyClassification {
+    let input = input.trim();
+
+    for network in [Network::MainNetwork, Network::TestNetwork] {
+        let name = Some(consensus::network_name(network));
+        if decode_extended_spending_key(&network, input).is_ok() {
+            return KeyClassification::new(KeyKind::SpendingKey, "sapling_extended", name);
+        }
+        if decode_extended_full_viewing_key(&network, input).is_ok() {
+            return KeyClassification::new(KeyKind::FullViewingKey, "sapling_extended", name);
+        }
+    }
+
+    if let Ok((network, _)) = unified::Ufvk::decode(input) {
+        let name = Some(unified_network_name(network));
+        return KeyClassification::new(KeyKind::FullViewingKey, "unified", name);
+    }
+    if let Ok((network, _)) = unified::Uivk::decode(input) {
+        let name = Some(unified_network_name(network));
+        return KeyClassification::new(KeyKind::IncomingViewingKey, "unified", name);
+    }
+
+    if Mnemonic::<English>::from_phrase(input).is_ok() {
+        return KeyClassification::new(KeyKind::Seed, "bip39", None);
+    }
+    if let Ok(bytes) = hex::decode(input) {
+        if (32..=252).contains(&bytes.len()) {
+            return KeyClassification::new(KeyKind::Seed, "hex", None);
+        }
+    }
+
+    KeyClassification {
+        kind: KeyKind::Unrecognized,
+        format: None,
+        network: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// BIP-39 test vectors (entropy, phrase, seed), all with the passphrase "TREZOR"
+    const VECTORS: [(&str, &str, &str); 3] = [
+        (
+            "00000000000000000000000000000000",
+            "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about",
+            "c55257c360c07c72029aebc1b53c05ed0362ada38ead3e3e9efa3708e53495531f09a6987599d18264c1e1c92f2cf141630c7a3c4ab7c81b2f001698e7463b04",
+        ),
+        (
+            "7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f",
+            "legal winner thank year wave sausage worth useful legal winner thank yellow",
+            "2e8905819b8723fe2c1d161860e5ee1830318dbf49a83bd451cfb8440c28bd6fa457fe1296106559a3c80937a1c1069be3a3a5bd381ee6260e8d9739fce1f607",
+        ),
+        (
+            "ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff",
+            "zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo vote",
+            "dd48c104698c30cfe2b6142103248622fb7bb0ff692eebb00089b32d22484e1613912f0a5b694407be899ffd31ed3992c456cdf60f5d4564b8ba3f05a69890ad",
+        ),
+    ];
+
+    #[test]
+    fn seed_from_mnemonic_matches_bip39_vectors() {
+        for (_, phrase, seed) in VECTORS {
+            assert_eq!(hex::encode(seed_from_mnemonic(phrase, "TREZOR").unwrap()), seed);
+        }
+    }
+
+    #[test]
+    fn seed_from_mnemonic_rejects_bad_checksum() {
+        let phrase = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon";
+        assert!(seed_from_mnemonic(phrase, "").is_err());
+    }
+
+    #[test]
+    fn mnemonic_seed_classifies_as_bip39() {
+        let classification = classify_key(VECTORS[0].1);
+        assert_eq!(classification.kind, KeyKind::Seed);
+        assert_eq!(classification.format, Some("bip39"));
+    }
+}
@@ -0,0 +1,36 @@
+//! zMail proving, transaction building and key handling, without the HTTP server
+//!
+//! The `zcash-proof-service` binary serves these modules over HTTP; its
+//! handlers decode requests, call into them and encode the results. Crates
+//! that want the logic without actix-web depend on this library with
+//! `default-features = false`, which drops the `server` feature and the
+//! binary along with it.
+//!
+//! Proving parameters come from `params::load`, which verifies them against
+//! their known hashes; `transaction::build` takes the loaded `Provers` and the
+//! `proving` functions the parameters inside them.
+
+pub mod attachment;
+pub mod config;
+pub mod consensus;
+pub mod deferred;
+pub mod download;
+pub mod email;
+pub mod estimate;
+pub mod fees;
+pub mod hex_bytes;
+pub mod keys;
+pub mod lightwalletd;
+pub mod memo;
+pub mod notes;
+pub mod params;
+pub mod pending;
+pub mod proof_limit;
+pub mod proving;
+pub mod recipients;
+pub mod rng;
+#[cfg(test)]
+mod testing;
+pub mod transaction;
+pub mod transparent;
+pub mod witness;
@@ -0,0 +1,313 @@
+//! Minimal lightwalletd gRPC client
+//!
+//! Only the handful of `CompactTxStreamer` calls the service needs. Message
+//! types are written out by hand from lightwalletd's service.proto and
+//! compact_formats.proto rather than generated, to avoid a protoc build step.
+
+use std::future::Future;
+use tonic::{
+    client::Grpc,
+    codec::{ProstCodec, Streaming},
+    codegen::http::uri::PathAndQuery,
+    transport::{Channel, ClientTlsConfig},
+    Code, Status,
+};
+
+const SERVICE: &str = "/cash.z.wallet.sdk.rpc.CompactTxStreamer";
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct BlockId {
+    #[prost(uint64, tag = "1")]
+    pub height: u64,
+    #[prost(bytes = "vec", tag = "2")]
+    pub hash: Vec<u8>,
+}
+
+/// Empty request of GetLatestBlock
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ChainSpec {}
+
+/// A serialized transaction to broadcast
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct RawTransaction {
+    #[prost(bytes = "vec", tag = "1")]
+    pub data: Vec<u8>,
+    #[prost(uint64, tag = "2")]
+    pub height: u64,
+}
+
+/// Result of SendTransaction; a non-zero code means the transaction was rejected
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct SendResponse {
+    #[prost(int32, tag = "1")]
+    pub error_code: i32,
+    #[prost(string, tag = "2")]
+    pub error_message: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct BlockRange {
+    #[prost(message, optional, tag = "1")]
+    pub start: Option<BlockId>,
+    #[prost(message, optional, tag = "2")]
+    pub end: Option<BlockId>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct CompactBlock {
+    #[prost(uint32, tag = "1")]
+    pub proto_version: u32,
+    #[prost(uint64, tag = "2")]
+    pub height: u64,
+    #[prost(bytes = "vec", tag = "3")]
+    pub hash: Vec<u8>,
+    #[prost(bytes = "vec", tag = "4")]
+    pub prev_hash: Vec<u8>,
+    #[prost(uint32, tag = "5")]
+    pub time: u32,
+    #[prost(bytes = "vec", tag = "6")]
+    pub header: Vec<u8>,
+    #[prost(message, repeated, tag = "7")]
+    pub vtx: Vec<CompactTx>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct CompactTx {
+    #[prost(uint64, tag = "1")]
+    pub index: u64,
+    #[prost(bytes = "vec", tag = "2")]
+    pub hash: Vec<u8>,
+    #[prost(uint32, tag = "3")]
+    pub fee: u32,
+    #[prost(message, repeated, tag = "4")]
+    pub spends: Vec<CompactSaplingSpend>,
+    #[prost(message, repeated, tag = "5")]
+    pub outputs: Vec<CompactSaplingOutput>,
+    #[prost(message, repeated, tag = "6")]
+    pub actions: Vec<CompactOrchardAction>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct CompactSaplingSpend {
+    #[prost(bytes = "vec", tag = "1")]
+    pub nf: Vec<u8>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct CompactSaplingOutput {
+    #[prost(bytes = "vec", tag = "1")]
+    pub cmu: Vec<u8>,
+    #[prost(bytes = "vec", tag = "2")]
+    pub ephemeral_key: Vec<u8>,
+    #[prost(bytes = "vec", tag = "3")]
+    pub ciphertext: Vec<u8>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct CompactOrchardAction {
+    #[prost(bytes = "vec", tag = "1")]
+    pub nullifier: Vec<u8>,
+    #[prost(bytes = "vec", tag = "2")]
+    pub cmx: Vec<u8>,
+    #[prost(bytes = "vec", tag = "3")]
+    pub ephemeral_key: Vec<u8>,
+    #[prost(bytes = "vec", tag = "4")]
+    pub ciphertext: Vec<u8>,
+}
+
+/// Note commitment tree state as of a block
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct TreeState {
+    #[prost(string, tag = "1")]
+    pub network: String,
+    #[prost(uint64, tag = "2")]
+    pub height: u64,
+    /// Block hash, hex in display (big-endian) order
+    #[prost(string, tag = "3")]
+    pub hash: String,
+    #[prost(uint32, tag = "4")]
+    pub time: u32,
+    /// Serialized Sapling commitment tree, hex
+    #[prost(string, tag = "5")]
+    pub sapling_tree: String,
+    #[prost(string, tag = "6")]
+    pub orchard_tree: String,
+}
+
+/// Client for a list of trusted lightwalletd endpoints
+///
+/// Calls go to the endpoint currently connected. If it fails with a
+/// transport error, the client reconnects to the next endpoint in the list
+/// and retries, so one server being down doesn't fail the request.
+pub struct LightwalletdClient {
+    endpoints: Vec<String>,
+    current: usize,
+    grpc: Grpc<Channel>,
+}
+
+/// Connect to a single endpoint, using TLS for https:// URIs
+async fn connect_channel(endpoint: &str) -> Result<Channel, String> {
+    let mut channel = Channel::from_shared(endpoint.to_string())
+        .map_err(|e| format!("Invalid lightwalletd endpoint {}: {}", endpoint, e))?;
+    if endpoint.starts_with("https://") {
+        channel = channel
+            .tls_config(ClientTlsConfig::new())
+            .map_err(|e| format!("TLS setup failed for {}: {}", endpoint, e))?;
+    }
+    channel
+        .connect()
+        .await
+        .map_err(|e| format!("Failed to connect to lightwalletd at {}: {}", endpoint, e))
+}
+
+/// Connect to the first reachable endpoint, trying each once starting at `start`
+async fn connect_from(endpoints: &[String], start: usize) -> Result<(usize, Channel), String> {
+    let mut errors = Vec::new();
+    for offset in 0..endpoints.len() {
+        let index = (start + offset) % endpoints.len();
+        match connect_channel(&endpoints[index]).await {
+            Ok(channel) => return Ok((index, channel)),
+            Err(e) => {
+                println!("[ProofService] ⚠️  {}", e);
+                errors.push(e);
+            }
+        }
+    }
+    Err(format!("No lightwalletd endpoint is reachable: {}", errors.join("; ")))
+}
+
+/// Errors worth retrying on another server; anything else would fail there too
+fn is_transport_error(status: &Status) -> bool {
+    matches!(status.code(), Code::Unavailable | Code::DeadlineExceeded)
+}
+
+impl LightwalletdClient {
+    /// Connect to the first reachable endpoint of `endpoints`, in order
+    pub async fn connect(endpoints: &[String]) -> Result<Self, String> {
+        if endpoints.is_empty() {
+            return Err("No lightwalletd endpoints configured".to_string());
+        }
+        let (current, channel) = connect_from(endpoints, 0).await?;
+        Ok(LightwalletdClient {
+            endpoints: endpoints.to_vec(),
+            current,
+            grpc: Grpc::new(channel),
+        })
+    }
+
+    /// Switch to the next reachable endpoint after the current one
+    async fn failover(&mut self) -> Result<(), String> {
+        let (current, channel) = connect_from(&self.endpoints, self.current + 1).await?;
+        self.current = current;
+        self.grpc = Grpc::new(channel);
+        Ok(())
+    }
+
+    fn path(method: &str) -> PathAndQuery {
+        PathAndQuery::try_from(format!("{}/{}", SERVICE, method))
+            .expect("gRPC method paths are valid")
+    }
+
+    /// Run `call` against the current endpoint, failing over on transport errors
+    async fn call<T, F, Fut>(&mut self, method: &str, mut call: F) -> Result<T, String>
+    where
+        F: FnMut(Grpc<Channel>, PathAndQuery) -> Fut,
+        Fut: Future<Output = Result<T, Status>>,
+    {
+        let mut failovers = 0;
+        loop {
+            let mut grpc = self.grpc.clone();
+            let result = match grpc.ready().await {
+                Ok(()) => call(grpc, Self::path(method)).await,
+                Err(e) => Err(Status::unavailable(e.to_string())),
+            };
+            let endpoint = &self.endpoints[self.current];
+            match result {
+                Ok(response) => {
+                    println!("[ProofService] {} served by {}", method, endpoint);
+                    return Ok(response);
+                }
+                Err(status) if is_transport_error(&status) && failovers + 1 < self.endpoints.len() => {
+                    println!("[ProofService] ⚠️  {} failed at {}: {}; failing over", method, endpoint, status);
+                    failovers += 1;
+                    self.failover().await?;
+                }
+                Err(status) => return Err(format!("{} failed at {}: {}", method, endpoint, status)),
+            }
+        }
+    }
+
+    /// Stream compact blocks for the inclusive height range `start..=end`
+    pub async fn block_range(
+        &mut self,
+        start: u64,
+        end: u64,
+    ) -> Result<Streaming<CompactBlock>, String> {
+        let range = BlockRange {
+            start: Some(BlockId { height: start, hash: vec![] }),
+            end: Some(BlockId { height: end, hash: vec![] }),
+        };
+        self.call("GetBlockRange", |mut grpc, path| {
+            let range = range.clone();
+            async move {
+                let response = grpc
+                    .server_streaming(tonic::Request::new(range), path, ProstCodec::default())
+                    .await?;
+                Ok(response.into_inner())
+            }
+        })
+        .await
+    }
+
+    /// Height and hash of the chain tip
+    pub async fn latest_block(&mut self) -> Result<BlockId, String> {
+        self.call("GetLatestBlock", |mut grpc, path| async move {
+            let response = grpc
+                .unary(tonic::Request::new(ChainSpec {}), path, ProstCodec::default())
+                .await?;
+            Ok(response.into_inner())
+        })
+        .await
+    }
+
+    /// Broadcast a serialized transaction
+    ///
+    /// Failing over resends it to another server, which is harmless: a node
+    /// that already has the transaction just rejects the duplicate.
+    pub async fn send_transaction(&mut self, raw: &[u8]) -> Result<(), String> {
+        let transaction = RawTransaction { data: raw.to_vec(), height: 0 };
+        let response: SendResponse = self
+            .call("SendTransaction", |mut grpc, path| {
+                let transaction = transaction.clone();
+                async move {
+                    let response = grpc
+                        .unary(tonic::Request::new(transaction), path, ProstCodec::default())
+                        .await?;
+                    Ok(response.into_inner())
+                }
+            })
+            .await?;
+        if response.error_code != 0 {
+            return Err(format!(
+                "The transaction was rejected ({}): {}",
+                response.error_code, response.error_message
+            ));
+        }
+        Ok(())
+    }
+
+    /// Commitment tree state after the block at `height`
+    pub async fn tree_state(&mut self, height: u64) -> Result<TreeState, String> {
+        let block = BlockId { height, hash: vec![] };
+        self.call("GetTreeState", |mut grpc, path| {
+            let block = block.clone();
+            async move {
+                let response = grpc
+                    .unary(tonic::Request::new(block), path, ProstCodec::default())
+                    .await?;
+                Ok(response.into_inner())
+            }
+        })
+        .await
+    }
+}
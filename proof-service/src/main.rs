@@ -6,120 +6,848 @@
  * generation capabilities.
  */
 
-use actix_web::{web, App, HttpServer, HttpResponse, Result as ActixResult};
+use actix_web::{body, http::{header, KeepAlive, StatusCode}, web, App, HttpServer, HttpResponse, Result as ActixResult, Scope};
 use actix_cors::Cors;
 use serde::{Deserialize, Serialize};
-use zcash_proofs::prover::LocalTxProver;
-use std::path::PathBuf;
+use utoipa::{IntoParams, OpenApi, ToSchema};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use utoipa_swagger_ui::SwaggerUi;
+use std::path::{Path, PathBuf};
+use std::collections::HashSet;
 use std::env;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, MutexGuard, TryLockError};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use rand::{rngs::OsRng, seq::SliceRandom};
+use sapling::{
+    keys::OutgoingViewingKey,
+    value::{ValueCommitTrapdoor, ValueCommitment},
+    zip32::ExtendedSpendingKey,
+    PaymentAddress,
+};
+use zcash_primitives::{
+    consensus::{BlockHeight, Network},
+    memo::MemoBytes,
+    transaction::builder::DEFAULT_TX_EXPIRY_DELTA,
+};
 
-#[derive(Deserialize)]
+mod cli;
+mod error;
+mod msgpack;
+mod openapi;
+mod tls;
+
+use zcash_proof_service::{
+    attachment, config, consensus, deferred, download, email, estimate, fees, hex_bytes, keys, lightwalletd, memo,
+    notes, params, pending, proof_limit, proving, recipients, rng, transaction, transparent, witness,
+};
+
+use config::Config;
+use deferred::ProvingInputs;
+use error::{ApiError, FieldError, Validation};
+use lightwalletd::LightwalletdClient;
+use notes::{NoteScope, SelectionError, SpendableNote};
+use params::{ParamSet, Provers};
+use pending::PendingSpends;
+use proof_limit::{ProofLimiter, ProofLoad};
+use recipients::{Receiver, Recipient, RecipientInput, ReplyTo};
+use transaction::{DecodedTransaction, OutputKind, PlannedOutput, Privacy, ValueBreakdown};
+use transparent::UtxoInput;
+use witness::{Checkpoint, CheckpointInput, WitnessOutput};
+
+#[derive(Deserialize, ToSchema)]
 struct ProofRequest {
     #[serde(rename = "type")]
     proof_type: String,
+    #[schema(value_type = Object)]
     params: serde_json::Value,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, ToSchema)]
+struct BatchProofRequest {
+    /// Proof requests, answered in order
+    items: Vec<ProofRequest>,
+}
+
+#[derive(Serialize, ToSchema)]
+struct BatchProofResponse {
+    results: Vec<BatchProofResult>,
+}
+
+/// The answer to one item of a batch, as /proofs/generate would have given it
+#[derive(Serialize, ToSchema)]
+struct BatchProofResult {
+    /// HTTP status of the item
+    status: u16,
+    /// The item's response body: a ProofResponse, or an ApiError
+    #[schema(value_type = Object)]
+    body: serde_json::Value,
+}
+
+#[derive(Deserialize, ToSchema)]
 struct BuildTransactionRequest {
+    #[serde(default)]
     spending_key: String,
+    /// Read the spending key from this local file instead (requires ZMAIL_LOCAL_KEYS=1)
+    spending_key_file: Option<String>,
     from_address: String,
+    #[serde(default)]
     to_address: String,
+    #[serde(default)]
     amount: String, // in zatoshi
+    /// Memo content (hex). Up to 512 bytes fills one memo field; longer content is
+    /// chunked into zMail fragments, one output each, up to `max_message_size` in /limits.
+    #[serde(default, with = "hex_bytes")]
+    #[schema(value_type = String, format = "hex")]
     memo: Vec<u8>,
-    #[allow(dead_code)] // Will be used when implementing full transaction building
+    /// Name a reply-to address in the memo for to_address
+    #[serde(default)]
+    reply_to: ReplyTo,
+    /// Send only the memo to to_address, with no funds (amount may be omitted)
+    #[serde(default)]
+    memo_only: bool,
+    /// Pay several recipients in one transaction; replaces to_address/amount/memo/reply_to/memo_only
+    #[serde(default)]
+    recipients: Vec<RecipientInput>,
+    /// Permit the same address, amount and memo to appear more than once in `recipients`
+    #[serde(default)]
+    allow_duplicates: bool,
+    /// Overrides the configured lightwalletd endpoints for this request
     lightwalletd_endpoint: Option<String>,
+    /// Height the transaction is built for (determines consensus rules and expiry)
+    target_height: Option<u32>,
+    /// Notes available to fund the transaction, with their witnesses
+    #[serde(default)]
+    notes: Vec<SpendableNote>,
+    /// Transparent coins to shield; all of them are spent, and `notes` must be empty
+    #[serde(default)]
+    transparent_inputs: Vec<UtxoInput>,
+    /// Secret key controlling `transparent_inputs` (hex or WIF)
+    transparent_key: Option<String>,
+    /// Extra zero-value outputs to random addresses, hiding the real output count
+    #[serde(default)]
+    dummy_outputs: usize,
+    /// Pad the transaction with dummy outputs to this many Sapling outputs, change included,
+    /// so it can't be told apart from other padded builds by its output count. Overrides
+    /// ZMAIL_PAD_OUTPUTS; 0 disables padding. Builds that need more outputs aren't padded.
+    pad_outputs: Option<usize>,
+    /// Confirmations a note needs to be spent (default 10)
+    #[serde(default = "default_min_confirmations")]
+    min_confirmations: u32,
+    /// Check each note's nullifier against the chain via lightwalletd before spending
+    #[serde(default)]
+    check_spent: bool,
+    /// Pin the anchor to this block; every witness must be at its Sapling tree
+    anchor_height: Option<u32>,
+    /// Expected hash of the anchor block (hex, display order), checked via lightwalletd
+    anchor_hash: Option<String>,
+    /// Fixed fee in zatoshi, replacing the ZIP-317 conventional fee
+    fee: Option<u64>,
+    /// Permit a `fee` below the ZIP-317 conventional fee (such transactions may not relay)
+    #[serde(default)]
+    allow_low_fee: bool,
+    /// Required to pay a transparent recipient, which publishes the amount and address
+    #[serde(default)]
+    acknowledge_privacy_loss: bool,
+    /// "inline" (default) proves here; "defer" returns zero proofs plus the witnesses
+    #[serde(default)]
+    proofs: ProofMode,
+    /// Randomize output order so the change output can't be picked out by position
+    /// (default true; disable only for deterministic testing)
+    #[serde(default = "default_shuffle_outputs")]
+    shuffle_outputs: bool,
+    /// Derive all of this build's randomness (note rseeds, alphas, output order) from this
+    /// 32-byte seed (hex), so the same request always yields the same transaction bytes, e.g.
+    /// to show an auditor how a transaction was built. Never reuse a seed for another
+    /// transaction: it repeats the note and signing randomness, linking the two transactions.
+    /// Omit it to use secure randomness.
+    rng_seed: Option<String>,
 }
 
-#[derive(Serialize)]
+#[derive(Deserialize, Default, Clone, Copy, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+enum ProofMode {
+    #[default]
+    Inline,
+    /// Leave proving to an external prover
+    Defer,
+}
+
+fn default_min_confirmations() -> u32 {
+    notes::DEFAULT_MIN_CONFIRMATIONS
+}
+
+fn default_shuffle_outputs() -> bool {
+    true
+}
+
+/// lightwalletd servers to query: the requested endpoint, else the configured list
+fn lightwalletd_endpoints(requested: Option<&str>, config: &Config) -> Vec<String> {
+    match requested {
+        Some(endpoint) => vec![endpoint.to_string()],
+        None => config.lightwalletd_endpoints.clone(),
+    }
+}
+
+impl BuildTransactionRequest {
+    /// lightwalletd servers to query: the request's endpoint, else the configured list
+    fn endpoints(&self, config: &Config) -> Vec<String> {
+        lightwalletd_endpoints(self.lightwalletd_endpoint.as_deref(), config)
+    }
+
+    /// The recipients to pay: `recipients` if given, otherwise the single to_address
+    fn recipients(&self) -> Vec<RecipientInput> {
+        if !self.recipients.is_empty() {
+            return self.recipients.clone();
+        }
+        vec![RecipientInput {
+            address: self.to_address.clone(),
+            amount: self.amount.clone(),
+            memo: self.memo.clone(),
+            memo_fragments: Vec::new(),
+            encrypt_memo: false,
+            reply_to: self.reply_to,
+            memo_only: self.memo_only,
+        }]
+    }
+}
+
+#[derive(Serialize, ToSchema)]
 struct ProofResponse {
-    proof: Vec<u8>,
+    proof: Bytes,
+    /// Value commitment (hex), for output and spend proofs
+    cv: Option<String>,
+    /// Note commitment (hex), for output proofs
+    cmu: Option<String>,
+    /// Ephemeral public key (hex), for output proofs
+    epk: Option<String>,
+    /// Note ciphertext (580 bytes, hex), for output proofs
+    enc_ciphertext: Option<String>,
+    /// Outgoing ciphertext (80 bytes, hex), for output proofs
+    out_ciphertext: Option<String>,
+    /// The whole OutputDescription in v4 transaction layout (948 bytes, hex),
+    /// when requested with `"description": true`
+    description: Option<String>,
+    /// Anchor the spent note's witness commits to (hex), for spend proofs
+    anchor: Option<String>,
+    /// Nullifier of the spent note (hex), for spend proofs
+    nullifier: Option<String>,
+    /// Randomizer, rk and (given a `sighash`) signature, for spend requests
+    spend_authorization: Option<SpendAuthorization>,
     error: Option<String>,
+    /// Every problem with the request's params, when it was rejected as invalid
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    errors: Vec<FieldError>,
 }
 
-#[derive(Serialize)]
+impl ProofResponse {
+    /// A response carrying `proof` (empty when nothing was proven) and nothing else
+    fn new(encoding: Encoding, proof: Vec<u8>) -> Self {
+        ProofResponse {
+            proof: encoding.bytes(proof),
+            cv: None,
+            cmu: None,
+            epk: None,
+            enc_ciphertext: None,
+            out_ciphertext: None,
+            description: None,
+            anchor: None,
+            nullifier: None,
+            spend_authorization: None,
+            error: None,
+            errors: Vec::new(),
+        }
+    }
+
+    /// The response to a request that failed, saying why
+    fn error(encoding: Encoding, error: impl Into<String>) -> Self {
+        ProofResponse { error: Some(error.into()), ..ProofResponse::new(encoding, vec![]) }
+    }
+
+    /// The response to a request rejected as invalid, listing every bad param
+    fn invalid(encoding: Encoding, e: ApiError) -> Self {
+        ProofResponse { errors: e.errors, ..ProofResponse::error(encoding, e.message) }
+    }
+
+    /// A spend's proof and the public values it commits to, with the spend's authorization
+    fn spend(encoding: Encoding, spend: SpendProof, spend_authorization: Option<SpendAuthorization>) -> Self {
+        ProofResponse {
+            cv: Some(hex::encode(spend.cv)),
+            anchor: Some(hex::encode(spend.anchor)),
+            nullifier: Some(hex::encode(spend.nullifier)),
+            spend_authorization,
+            ..ProofResponse::new(encoding, spend.proof)
+        }
+    }
+
+    /// An output's proof (if proven), commitments and encrypted note
+    fn output(encoding: Encoding, output: OutputProof) -> Self {
+        ProofResponse {
+            cv: Some(hex::encode(output.cv)),
+            cmu: Some(hex::encode(output.cmu)),
+            epk: Some(hex::encode(output.encrypted.epk)),
+            enc_ciphertext: Some(hex::encode(output.encrypted.enc_ciphertext)),
+            out_ciphertext: Some(hex::encode(output.encrypted.out_ciphertext)),
+            description: output.description.map(hex::encode),
+            ..ProofResponse::new(encoding, output.proof)
+        }
+    }
+}
+
+/// The parts of a spend description that authorize it
+#[derive(Serialize, ToSchema)]
+struct SpendAuthorization {
+    /// Spend authorization randomizer (hex), which the spend proof must use too
+    alpha: String,
+    /// Randomized spend validating key (hex)
+    rk: String,
+    /// Signature over the sighash by the key randomized with alpha (64 bytes, hex),
+    /// when the request gave a sighash
+    spend_auth_sig: Option<String>,
+}
+
+#[derive(Deserialize, ToSchema)]
+struct VerifyRequest {
+    /// Randomized spend validating key of the spend description (hex)
+    rk: String,
+    /// Shielded sighash of the transaction (hex)
+    sighash: String,
+    /// Spend authorization signature (64 bytes, hex)
+    spend_auth_sig: String,
+}
+
+#[derive(Serialize, ToSchema)]
+struct VerifyResponse {
+    valid: bool,
+    /// Why the signature was rejected
+    error: Option<String>,
+}
+
+/// How the proof is encoded in proof responses
+#[derive(Deserialize, Default, Clone, Copy, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+enum Encoding {
+    /// Hex string, like every other byte field (the default)
+    #[default]
+    Hex,
+    /// Standard base64 string: smaller than hex and cheap to parse in JS
+    Base64,
+    /// Array of numbers, for clients written before hex was the default
+    Array,
+}
+
+impl Encoding {
+    fn bytes(self, bytes: Vec<u8>) -> Bytes {
+        match self {
+            Encoding::Hex => Bytes::Text(hex::encode(bytes)),
+            Encoding::Base64 => Bytes::Text(BASE64.encode(bytes)),
+            Encoding::Array => Bytes::Array(bytes),
+        }
+    }
+}
+
+#[derive(Deserialize, IntoParams, Clone, Copy)]
+#[into_params(parameter_in = Query)]
+struct EncodingQuery {
+    /// "hex" (default), "base64" or "array"
+    #[serde(default)]
+    encoding: Encoding,
+}
+
+/// Binary data in the encoding the client asked for
+#[derive(Serialize, ToSchema)]
+#[serde(untagged)]
+enum Bytes {
+    /// Hex or base64
+    Text(String),
+    Array(Vec<u8>),
+}
+
+/// A spend proof and the spend description values it commits to
+struct SpendProof {
+    proof: Vec<u8>,
+    cv: [u8; 32],
+    anchor: [u8; 32],
+    nullifier: [u8; 32],
+}
+
+/// Everything for an output description, with the proof unless only the note data was asked for
+struct OutputProof {
+    proof: Vec<u8>,
+    cv: [u8; 32],
+    cmu: [u8; 32],
+    encrypted: proving::EncryptedNote,
+    /// Serialized output description, if requested (only available with a proof)
+    description: Option<Vec<u8>>,
+}
+
+#[derive(Serialize, ToSchema)]
 struct BuildTransactionResponse {
+    /// Serialized transaction (hex), empty on failure
+    #[serde(with = "hex_bytes")]
+    #[schema(value_type = String, format = "hex")]
     raw_transaction: Vec<u8>,
     txid: Option<String>,
+    /// "shielded", "partial" or "transparent"; lets the UI warn about transparent leaks.
+    /// Deshielding builds always report "transparent".
+    privacy: Option<Privacy>,
+    /// Fee actually paid, in zatoshi
+    fee: Option<u64>,
+    /// Value sent with memo-only outputs, i.e. what the messages cost on top of the fee (zatoshi)
+    postage: Option<u64>,
+    /// Fingerprint of the notes spent and payments made. A retried build with the same
+    /// nonce is equivalent to the first and conflicts with it, so only one can be mined.
+    build_nonce: Option<String>,
+    /// Witnesses for the missing proofs when `proofs` is "defer"
+    proving_inputs: Option<ProvingInputs>,
+    /// Total inputs, outputs, change and fee, in zatoshi and ZEC
+    value_breakdown: Option<ValueBreakdown>,
     error: Option<String>,
 }
 
+#[derive(Deserialize, ToSchema)]
+struct SendTransactionRequest {
+    /// The transaction to build, with the same fields as a /proofs/build-transaction request
+    #[serde(flatten)]
+    build: BuildTransactionRequest,
+    /// Build and prove, but stop before broadcasting
+    #[serde(default)]
+    dry_run: bool,
+}
+
+/// What happened to a built transaction's broadcast
+#[derive(Serialize, Clone, Copy, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+enum BroadcastStatus {
+    /// lightwalletd accepted the transaction
+    Sent,
+    /// `dry_run` was set
+    Skipped,
+    /// Built, but lightwalletd didn't take it; `build.raw_transaction` can be broadcast again as is
+    Failed,
+}
+
+#[derive(Serialize, ToSchema)]
+struct SendTransactionResponse {
+    txid: Option<String>,
+    broadcast: BroadcastStatus,
+    /// Why the broadcast failed
+    broadcast_error: Option<String>,
+    /// The build's response, as /proofs/build-transaction returns it
+    #[schema(value_type = BuildTransactionResponse)]
+    build: serde_json::Value,
+}
+
+#[derive(Deserialize, ToSchema)]
+struct EstimateTimeRequest {
+    /// Notes the build spends
+    #[serde(default)]
+    spends: u64,
+    /// Sapling outputs the build creates, including change, memo fragments and dummies
+    #[serde(default)]
+    outputs: u64,
+}
+
+#[derive(Deserialize, ToSchema)]
+struct EmailRecipientInput {
+    address: String,
+    /// In zatoshi; omit to send the email without funds (the memo-only value)
+    #[serde(default)]
+    amount: String,
+}
+
+#[derive(Deserialize, ToSchema)]
+struct AttachmentInput {
+    name: String,
+    #[serde(default)]
+    content_type: String,
+    /// File contents (base64)
+    data: String,
+}
+
+#[derive(Deserialize, ToSchema)]
+struct EmailPlanRequest {
+    recipients: Vec<EmailRecipientInput>,
+    #[serde(default)]
+    subject: String,
+    #[serde(default)]
+    body: String,
+    #[serde(default)]
+    attachments: Vec<AttachmentInput>,
+    /// Notes each transaction is expected to spend, for its fee and proving time
+    #[serde(default = "default_plan_spends")]
+    spends: usize,
+    /// Outputs the builds will be padded to, as in a build request (default ZMAIL_PAD_OUTPUTS)
+    pad_outputs: Option<usize>,
+}
+
+fn default_plan_spends() -> usize {
+    1
+}
+
+#[derive(Serialize, ToSchema)]
+struct EmailPlanResponse {
+    /// Encoded email size, before framing
+    content_bytes: usize,
+    /// Memo fields in each recipient's copy of the email
+    fragments: usize,
+    /// Most email outputs one transaction carries: ZMAIL_MAX_OUTPUTS, or fewer if more
+    /// would make the transaction too large to relay
+    outputs_per_transaction: usize,
+    /// Transactions the email is split across
+    transaction_count: usize,
+    transactions: Vec<email::PlannedTransaction>,
+    /// Outputs across every transaction, excluding change and padding
+    output_count: usize,
+    /// Value paid to recipients, in zatoshi
+    amount: u64,
+    /// Total fee of every transaction, in zatoshi
+    fee: u64,
+    /// Proving time for every transaction, change and padding included; null before calibration
+    proving: Option<estimate::Estimate>,
+}
+
+#[derive(Deserialize, ToSchema)]
+struct SighashRequest {
+    /// Unsigned or partially-signed transaction (hex)
+    #[serde(with = "hex_bytes")]
+    #[schema(value_type = String, format = "hex")]
+    raw_transaction: Vec<u8>,
+    /// Height the transaction targets (selects the consensus branch)
+    target_height: u32,
+}
+
+#[derive(Deserialize, ToSchema)]
+struct DecodeTransactionRequest {
+    /// Serialized transaction (hex)
+    #[serde(with = "hex_bytes")]
+    #[schema(value_type = String, format = "hex")]
+    raw_transaction: Vec<u8>,
+    /// Height the transaction targets (selects the consensus branch)
+    target_height: u32,
+    /// Values of the coins spent by the transparent inputs, in input order (zatoshi)
+    #[serde(default)]
+    transparent_input_values: Vec<u64>,
+    /// Fee the transaction is expected to pay, e.g. as reported by build-transaction
+    expected_fee: Option<u64>,
+}
+
+#[derive(Deserialize, ToSchema)]
+struct ValidateWitnessRequest {
+    /// Note commitment (32 bytes, hex)
+    cmu: String,
+    /// Position of the note commitment in the Sapling tree
+    position: u64,
+    /// Merkle authentication path, leaf to root (32 nodes, hex)
+    auth_path: Vec<String>,
+    /// Sapling tree root the witness should reach (32 bytes, hex, as in transactions)
+    anchor: String,
+}
+
+#[derive(Serialize, ToSchema)]
+struct ValidateWitnessResponse {
+    /// Whether the path hashes up to `anchor`
+    valid: bool,
+    /// Root the path actually hashes up to (hex)
+    root: String,
+}
+
+#[derive(Deserialize, ToSchema)]
+struct BuildWitnessesRequest {
+    /// Note commitments to witness (32 bytes each, hex, as in compact blocks)
+    note_commitments: Vec<String>,
+    /// Block to build the witnesses up to; their anchor is the tree at this height.
+    /// Defaults to `anchor_depth` blocks below the chain tip.
+    tip: Option<u32>,
+    /// Blocks below the chain tip to anchor at when no tip is given.
+    /// Defaults to ZMAIL_ANCHOR_DEPTH.
+    anchor_depth: Option<u32>,
+    /// Tree to resume from. Defaults to the ZMAIL_TREE_CHECKPOINT file.
+    checkpoint: Option<CheckpointInput>,
+    /// lightwalletd gRPC endpoint to stream blocks from. Defaults to ZMAIL_LIGHTWALLETD_ENDPOINTS.
+    lightwalletd_endpoint: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+struct BuildWitnessesResponse {
+    /// Height the tree and witnesses were built up to
+    height: u64,
+    /// Sapling tree root at that height (hex, as in transactions)
+    anchor: String,
+    /// The tree at that height, to pass as the checkpoint of a later call
+    sapling_tree: String,
+    /// Hash of the block at that height (hex, display order), to pass with the
+    /// checkpoint so a later call can detect a reorg
+    hash: Option<String>,
+    /// A witness per requested commitment, in request order; null if it wasn't
+    /// found after the checkpoint
+    witnesses: Vec<Option<WitnessOutput>>,
+}
+
+#[derive(Serialize, ToSchema)]
+struct SighashResponse {
+    sighash: String,
+    consensus_branch_id: String,
+}
+
+#[derive(Deserialize, ToSchema)]
+struct DeriveAddressRequest {
+    /// BIP-39 mnemonic phrase
+    mnemonic: Option<String>,
+    /// Optional BIP-39 passphrase
+    #[serde(default)]
+    passphrase: String,
+    /// Raw seed (hex), alternative to `mnemonic`
+    seed: Option<String>,
+    #[serde(default)]
+    account: u32,
+}
+
+#[derive(Serialize, ToSchema)]
+struct DeriveAddressResponse {
+    address: String,
+    full_viewing_key: String,
+}
+
+#[derive(Deserialize, ToSchema)]
+struct ExportFvkRequest {
+    /// `secret-extended-key-*` for the configured network
+    spending_key: String,
+}
+
+#[derive(Serialize, ToSchema)]
+struct ExportFvkResponse {
+    full_viewing_key: String,
+}
+
+#[derive(Deserialize, ToSchema)]
+struct ExportOvkRequest {
+    /// `secret-extended-key-*` for the configured network
+    spending_key: Option<String>,
+    /// `zxviews*` key for the configured network, instead of a spending key
+    full_viewing_key: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+struct ExportOvkResponse {
+    /// Outgoing viewing key of payments to others (32 bytes, hex)
+    ovk: String,
+    /// Outgoing viewing key of change outputs (32 bytes, hex)
+    internal_ovk: String,
+}
+
+#[derive(Deserialize, ToSchema)]
+struct ValidateKeyRequest {
+    key: String,
+}
+
+#[derive(Serialize, ToSchema)]
+struct CapabilitiesResponse {
+    version: &'static str,
+    /// Network this instance is configured for
+    network: &'static str,
+    /// Networks this build can be configured for (via ZMAIL_NETWORK)
+    supported_networks: Vec<&'static str>,
+    proof_types: Vec<&'static str>,
+    /// Whether this instance proves at all (false with ZMAIL_NO_PARAMS)
+    proving: bool,
+    /// Orchard receivers and proofs. Not supported yet; Orchard requests get
+    /// ORCHARD_UNSUPPORTED while Sapling keeps working.
+    orchard: bool,
+    transparent: bool,
+    multiple_recipients: bool,
+    max_outputs: usize,
+    memo_size: usize,
+    /// Response encodings, chosen with the Accept header
+    response_formats: Vec<&'static str>,
+    /// Proof times measured on this host at startup
+    proving_calibration: estimate::Calibration,
+}
+
+#[derive(Serialize, ToSchema)]
+struct LimitsResponse {
+    /// Raw memo field size
+    memo_size: usize,
+    /// zMail header bytes at the start of each fragment
+    header_size: usize,
+    /// Message bytes carried per fragment
+    payload_size: usize,
+    max_outputs: usize,
+    /// Most fragments a single message may be split into
+    max_fragments: usize,
+    /// Largest message body a single build can carry
+    max_message_size: usize,
+    /// Value each memo-only output carries, in zatoshi
+    memo_only_value: u64,
+    /// Largest total attachment size of one email
+    max_attachment_bytes: usize,
+    /// Most proof requests in one batch
+    max_batch_items: usize,
+    /// Sapling outputs builds are padded to by default, change included (0 if not padded)
+    pad_outputs: usize,
+}
+
+#[derive(Deserialize, ToSchema)]
+struct SentMailRequest {
+    /// Outgoing viewing key the outputs were sent with (32 bytes, hex), as from /keys/export-ovk
+    ovk: String,
+    outputs: Vec<SentOutputInput>,
+}
+
+/// A Sapling output as it appears in a transaction (all fields hex)
+#[derive(Deserialize, ToSchema)]
+struct SentOutputInput {
+    cv: String,
+    cmu: String,
+    epk: String,
+    /// 580 bytes
+    enc_ciphertext: String,
+    /// 80 bytes
+    out_ciphertext: String,
+}
+
+#[derive(Serialize, ToSchema)]
+struct SentMailResponse {
+    /// One entry per requested output, in request order
+    outputs: Vec<SentOutput>,
+}
+
+#[derive(Serialize, ToSchema)]
+struct SentOutput {
+    /// False if the output wasn't sent with this ovk; the other fields are then null
+    decrypted: bool,
+    /// Recipient's Sapling address
+    address: Option<String>,
+    /// In zatoshi
+    value: Option<u64>,
+    /// Memo field (hex), ready for /messages/decrypt if it's a zMail fragment
+    memo: Option<String>,
+}
+
+#[derive(Deserialize, ToSchema)]
+struct DecryptMessageRequest {
+    /// Memo fields of every fragment of the message (hex), in any order
+    #[serde(with = "hex_bytes::list")]
+    #[schema(value_type = Vec<String>)]
+    fragments: Vec<Vec<u8>>,
+    /// Where each fragment was found, in the same order as `fragments`. Fragments
+    /// seen twice (e.g. by a rescan) are then only counted once.
+    #[serde(default)]
+    provenance: Vec<NoteProvenance>,
+    /// Message and note ids the client already has. A message matching one is
+    /// reported as a duplicate instead of being decrypted again.
+    #[serde(default)]
+    seen_ids: Vec<String>,
+}
+
+/// The note a memo fragment was decrypted from
+#[derive(Deserialize, Serialize, ToSchema)]
+struct NoteProvenance {
+    /// Transaction the note is in (hex, display order)
+    txid: String,
+    /// Index of the note's output within the transaction's Sapling outputs
+    output_index: u32,
+    /// Height of the block that mined the transaction
+    height: u64,
+}
+
+#[derive(Serialize, ToSchema)]
+struct DecryptMessageResponse {
+    /// Decrypted message content (hex)
+    #[serde(with = "hex_bytes")]
+    #[schema(value_type = String, format = "hex")]
+    content: Vec<u8>,
+    /// Where the sender asked for replies, if the message names an address
+    reply_to: Option<String>,
+    /// Stable id of the message, derived from its content key. The same on every
+    /// rescan, with or without provenance.
+    message_id: String,
+    /// `txid:output_index` of the message's first fragment, when the request gave provenance
+    note_id: Option<String>,
+    /// Whether `seen_ids` named this message, in which case content is empty
+    duplicate: bool,
+    /// Height of the block with the first fragment, for sorting messages chronologically
+    height: Option<u64>,
+    /// Where each distinct fragment was found, in sequence order
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    fragments: Vec<NoteProvenance>,
+}
+
+#[derive(Deserialize)]
+struct BranchIdQuery {
+    height: u32,
+}
+
+#[derive(Serialize)]
+struct BranchIdResponse {
+    network: &'static str,
+    height: u32,
+    branch: String,
+    consensus_branch_id: String,
+}
+
+#[derive(Deserialize)]
+struct BenchRequest {
+    #[serde(default = "default_bench_iterations")]
+    iterations: usize,
+}
+
+fn default_bench_iterations() -> usize {
+    5
+}
+
+#[derive(Serialize)]
+struct BenchResponse {
+    iterations: usize,
+    param_load_ms: u64,
+    min_ms: u64,
+    median_ms: u64,
+    max_ms: u64,
+}
+
+/// Upper bound on benchmark iterations (each one is a full Groth16 proof)
+const MAX_BENCH_ITERATIONS: usize = 50;
+
 // Note: Prover initialization is deferred until first use
 // This avoids loading large proving parameters at startup
 
-/// Find the parameters directory, checking local 'params' folder first
-fn find_params_dir() -> Option<PathBuf> {
-    println!("[ProofService] 🔍 Searching for parameters...");
+/// Directories that may hold the parameters, in the order they're searched:
+/// the local 'params' folder and its parents, then next to the executable,
+/// then the default ~/.zcash-params location
+fn params_search_dirs() -> Vec<PathBuf> {
+    let mut candidates = Vec::new();
     
     // First, check current working directory (most reliable when running from project root)
+    // and its parents (for when running from proof-service subdirectory)
     if let Ok(cwd) = env::current_dir() {
-        let cwd_params = cwd.join("params");
-        let cwd_spend = cwd_params.join("sapling-spend.params");
-        let cwd_output = cwd_params.join("sapling-output.params");
-        
-        println!("[ProofService] Checking CWD params: {:?}", cwd_params);
-        if cwd_spend.exists() && cwd_output.exists() {
-            println!("[ProofService] ✅ Found parameters in CWD 'params' folder: {:?}", cwd_params);
-            return Some(cwd_params);
-        }
-        
-        // Also check parent directories (for when running from proof-service subdirectory)
-        let mut current = cwd.clone();
-        for _ in 0..5 {
-            let parent_params = current.join("params");
-            let parent_spend = parent_params.join("sapling-spend.params");
-            let parent_output = parent_params.join("sapling-output.params");
-            
-            println!("[ProofService] Checking parent params: {:?}", parent_params);
-            if parent_spend.exists() && parent_output.exists() {
-                println!("[ProofService] ✅ Found parameters in parent 'params' folder: {:?}", parent_params);
-                return Some(parent_params);
-            }
-            
-            if let Some(parent) = current.parent() {
-                current = parent.to_path_buf();
-            } else {
-                break;
-            }
-        }
+        candidates.extend(cwd.ancestors().take(5).map(|dir| dir.join("params")));
     }
     
     // Check relative to executable (for when running from target/release/)
+    // Go up multiple levels: target/release/ -> target/ -> project root
     if let Ok(exe_path) = env::current_exe() {
-        println!("[ProofService] Executable path: {:?}", exe_path);
         if let Some(exe_dir) = exe_path.parent() {
-            // Go up multiple levels: target/release/ -> target/ -> project root
-            let mut current = exe_dir.to_path_buf();
-            for _ in 0..5 {
-                let params_dir = current.join("params");
-                let spend_params = params_dir.join("sapling-spend.params");
-                let output_params = params_dir.join("sapling-output.params");
-                
-                println!("[ProofService] Checking exe-relative params: {:?}", params_dir);
-                if spend_params.exists() && output_params.exists() {
-                    println!("[ProofService] ✅ Found parameters relative to executable: {:?}", params_dir);
-                    return Some(params_dir);
-                }
-                
-                if let Some(parent) = current.parent() {
-                    current = parent.to_path_buf();
-                } else {
-                    break;
-                }
-            }
+            candidates.extend(exe_dir.ancestors().take(5).map(|dir| dir.join("params")));
         }
     }
     
     // Fall back to default location
     if let Some(home) = dirs::home_dir() {
-        let default_params = home.join(".zcash-params");
-        let default_spend = default_params.join("sapling-spend.params");
-        let default_output = default_params.join("sapling-output.params");
-        
-        println!("[ProofService] Checking default location: {:?}", default_params);
-        if default_spend.exists() && default_output.exists() {
-            println!("[ProofService] ✅ Found parameters in default location: {:?}", default_params);
-            return Some(default_params);
+        candidates.push(home.join(".zcash-params"));
+    }
+    candidates
+}
+
+/// Find the parameters directory, checking local 'params' folder first
+/// Only the files needed for `set` have to be present.
+fn find_params_dir(set: ParamSet) -> Option<PathBuf> {
+    println!("[ProofService] 🔍 Searching for parameters...");
+    
+    for dir in params_search_dirs() {
+        println!("[ProofService] Checking params: {:?}", dir);
+        if set.present_in(&dir) {
+            println!("[ProofService] ✅ Found parameters in {:?}", dir);
+            return Some(dir);
         }
     }
     
@@ -127,273 +855,943 @@ fn find_params_dir() -> Option<PathBuf> {
     None
 }
 
-// Initialize prover once (lazy static would be better, but this works)
-fn get_prover() -> Result<LocalTxProver, String> {
-    // First, try to find parameters in local 'params' folder
-    let params_dir = find_params_dir();
+/// Like `find_params_dir`, without logging each location; for polling
+fn params_present(set: ParamSet) -> bool {
+    params_search_dirs().iter().any(|dir| set.present_in(dir))
+}
+
+/// How long a failed prover initialization is reported to later callers before retrying
+const INIT_RETRY_WINDOW: Duration = Duration::from_secs(10);
+
+/// Most recent initialization failure per parameter set (output-only, full)
+static INIT_FAILURES: [Mutex<Option<(Instant, String)>>; 2] = [Mutex::new(None), Mutex::new(None)];
+
+/// Load the proving parameters needed for `set`
+/// Concurrent callers wait for a single attempt instead of each searching the
+/// filesystem, and a failure is handed to everyone for INIT_RETRY_WINDOW
+/// rather than re-running the search and re-logging the diagnostic.
+fn get_prover(set: ParamSet) -> Result<Provers, String> {
+    if let Some(provers) = params::cached(set) {
+        return Ok(provers);
+    }
+    let failure = init_slot(set).lock().unwrap_or_else(|e| e.into_inner());
+    init_locked(set, failure)
+}
+
+/// Like `get_prover`, but returns None instead of waiting while another request loads the parameters
+fn try_get_prover(set: ParamSet) -> Option<Result<Provers, String>> {
+    if let Some(provers) = params::cached(set) {
+        return Some(Ok(provers));
+    }
+    let failure = match init_slot(set).try_lock() {
+        Ok(failure) => failure,
+        Err(TryLockError::Poisoned(e)) => e.into_inner(),
+        Err(TryLockError::WouldBlock) => return None,
+    };
+    Some(init_locked(set, failure))
+}
+
+/// Whether each parameter set (output-only, full) has been loaded and verified since startup.
+/// Stays set in low-memory mode, where the spend parameters are dropped between requests
+/// but reload from the same verified file.
+static PARAMS_READY: [AtomicBool; 2] = [AtomicBool::new(false), AtomicBool::new(false)];
+
+fn ready_flag(set: ParamSet) -> &'static AtomicBool {
+    match set {
+        ParamSet::OutputOnly => &PARAMS_READY[0],
+        ParamSet::Full => &PARAMS_READY[1],
+    }
+}
+
+/// Whether the parameters in `set` have loaded and verified, so proofs that need them can run
+fn params_ready(set: ParamSet) -> bool {
+    ready_flag(set).load(Ordering::Relaxed)
+}
+
+fn init_slot(set: ParamSet) -> &'static Mutex<Option<(Instant, String)>> {
+    match set {
+        ParamSet::OutputOnly => &INIT_FAILURES[0],
+        ParamSet::Full => &INIT_FAILURES[1],
+    }
+}
+
+/// Initialize while holding the set's lock, reusing a recent failure
+fn init_locked(set: ParamSet, mut failure: MutexGuard<Option<(Instant, String)>>) -> Result<Provers, String> {
+    if let Some((at, error)) = failure.as_ref() {
+        if at.elapsed() < INIT_RETRY_WINDOW {
+            return Err(error.clone());
+        }
+    }
     
-    if let Some(params_dir) = params_dir {
-        // Build full paths to parameter files
-        let spend_path = params_dir.join("sapling-spend.params");
-        let output_path = params_dir.join("sapling-output.params");
+    let result = init_prover(set);
+    *failure = result.as_ref().err().map(|e| (Instant::now(), e.clone()));
+    if result.is_ok() {
+        // The full set includes the output parameters
+        ready_flag(ParamSet::OutputOnly).store(true, Ordering::Relaxed);
+        ready_flag(set).store(true, Ordering::Relaxed);
+    }
+    result
+}
+
+/// Seconds a client should wait before retrying while parameters load
+const PARAMS_LOADING_RETRY_AFTER: u32 = 5;
+
+/// Load the parameters once they're present, then stop after the full set has loaded
+///
+/// Each load verifies the files' hashes and, on success, marks the set ready for
+/// `/health/ready`. The first successful load also times one proof of each type
+/// for /proofs/estimate-time and /metrics.
+async fn watch_params(low_memory: bool, interval: Duration) {
+    let mut ticker = actix_web::rt::time::interval(interval);
+    while !params_ready(ParamSet::Full) {
+        ticker.tick().await;
+        let Some(set) = [ParamSet::Full, ParamSet::OutputOnly]
+            .into_iter()
+            .find(|&set| !params_ready(set) && params_present(set))
+        else {
+            continue;
+        };
         
-        // Verify files exist
-        if !spend_path.exists() {
-            return Err(format!("Parameter file not found: {:?}", spend_path));
+        let loaded = web::block(move || {
+            let _release_spend = if low_memory { Some(params::ReleaseSpend) } else { None };
+            let prover = get_prover(set)?;
+            let calibration = estimate::calibration();
+            let calibrated = calibration.output_proof_us.is_some()
+                && (set == ParamSet::OutputOnly || calibration.spend_proof_us.is_some());
+            Ok::<_, String>(if calibrated { calibration } else { estimate::calibrate(&prover) })
+        })
+        .await;
+        match loaded {
+            Ok(Ok(measured)) => println!(
+                "[ProofService] ✅ Ready ({:?} parameters); calibrated proving: output proof {:?}us, spend proof {:?}us",
+                set, measured.output_proof_us, measured.spend_proof_us
+            ),
+            Ok(Err(e)) => println!("[ProofService] ⚠️  Parameters present but not loaded, retrying in {:?}: {}", interval, e),
+            Err(e) => println!("[ProofService] ⚠️  Parameter check failed, retrying in {:?}: {}", interval, e),
+        }
+    }
+}
+
+/// 503 for requests that arrive while another request is loading the parameters
+fn params_loading_response() -> HttpResponse {
+    println!("[ProofService] ⚠️  Parameters are still loading; asking the client to retry");
+    HttpResponse::ServiceUnavailable()
+        .insert_header((header::RETRY_AFTER, PARAMS_LOADING_RETRY_AFTER.to_string()))
+        .json(ApiError::new(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "PARAMS_LOADING",
+            "The proving parameters are still loading. Retry shortly.",
+        ))
+}
+
+/// 503 for proving requests to a deployment started with ZMAIL_NO_PARAMS
+fn proving_disabled_response() -> HttpResponse {
+    println!("[ProofService] ⚠️  Proving requested, but ZMAIL_NO_PARAMS is set");
+    HttpResponse::ServiceUnavailable().json(ApiError::new(
+        StatusCode::SERVICE_UNAVAILABLE,
+        "PROVING_DISABLED",
+        "This instance runs without proving parameters; send proving requests to a proving instance.",
+    ))
+}
+
+/// Seconds a client should wait before retrying when every proving slot is taken
+const PROVER_BUSY_RETRY_AFTER: u32 = 2;
+
+/// 503 for proof requests that find the proving queue full
+fn prover_busy_response() -> HttpResponse {
+    println!("[ProofService] ⚠️  Proving queue is full; asking the client to retry");
+    HttpResponse::ServiceUnavailable()
+        .insert_header((header::RETRY_AFTER, PROVER_BUSY_RETRY_AFTER.to_string()))
+        .json(ApiError::new(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "PROVER_BUSY",
+            "Too many proofs are in progress. Retry shortly.",
+        ))
+}
+
+/// Decode a spending key for the configured network
+/// A key for the other network gets its own error, since the generic
+/// prefix mismatch is a common and confusing onboarding mistake.
+fn decode_spending_key(network: Network, encoded: &str) -> Result<ExtendedSpendingKey, ApiError> {
+    keys::decode_extended_spending_key(&network, encoded).map_err(|e| {
+        match keys::spending_key_network(encoded) {
+            Some(key_network) if key_network != network => ApiError::bad_request(
+                "NETWORK_MISMATCH",
+                format!(
+                    "This is a {}net spending key, but the service is configured for {}net (ZMAIL_NETWORK)",
+                    consensus::network_name(key_network),
+                    consensus::network_name(network)
+                ),
+            ),
+            _ => ApiError::bad_request("INVALID_SPENDING_KEY", format!("Invalid spending key: {}", e)),
         }
-        if !output_path.exists() {
-            return Err(format!("Parameter file not found: {:?}", output_path));
+    })
+}
+
+/// Find and load the parameters for `set`, explaining what was checked if they're missing
+fn init_prover(set: ParamSet) -> Result<Provers, String> {
+    // First, try to find parameters in local 'params' folder
+    let params_dir = find_params_dir(set);
+    
+    if let Some(params_dir) = params_dir {
+        println!("[ProofService] Using parameter files ({:?}):", set);
+        for file in set.files() {
+            let path = params_dir.join(file);
+            let size = std::fs::metadata(&path)
+                .map(|m| m.len() / 1024 / 1024)
+                .unwrap_or(0);
+            println!("[ProofService]   - {}: {} MB at {:?}", file, size, path);
         }
         
-        let spend_size = std::fs::metadata(&spend_path)
-            .map(|m| m.len() / 1024 / 1024)
-            .unwrap_or(0);
-        let output_size = std::fs::metadata(&output_path)
-            .map(|m| m.len() / 1024 / 1024)
-            .unwrap_or(0);
-        
-        println!("[ProofService] Using parameter files:");
-        println!("[ProofService]   - sapling-spend.params: {} MB at {:?}", spend_size, spend_path);
-        println!("[ProofService]   - sapling-output.params: {} MB at {:?}", output_size, output_path);
-        
-        // Initialize prover with explicit paths
-        // LocalTxProver::new() returns LocalTxProver directly (not Result)
-        let prover = LocalTxProver::new(&spend_path, &output_path);
+        let provers = params::load(&params_dir, set)?;
         println!("[ProofService] ✅ Prover initialized successfully with explicit paths");
-        return Ok(prover);
+        return Ok(provers);
     }
     
-    // Fall back to default location if local params not found
-    println!("[ProofService] ⚠️  No local parameters found, trying default location");
-    match LocalTxProver::with_default_location() {
-        Some(prover) => {
-            println!("[ProofService] ✅ Prover initialized successfully from default location");
-            Ok(prover)
-        },
-        None => {
-            // Provide helpful error message
-            let mut error_msg = "Prover initialization failed. This usually means the Groth16 proving parameters are not downloaded.\n\n".to_string();
+    // Files that are there but dangling symlinks aren't a download problem; say so instead
+    let broken: Vec<String> = params_search_dirs()
+        .iter()
+        .flat_map(|dir| set.files().iter().map(move |file| dir.join(file)))
+        .filter_map(|path| params::broken_symlink(&path))
+        .collect();
+    if !broken.is_empty() {
+        return Err(format!(
+            "Prover initialization failed: {}\n\nPoint the link at the downloaded parameter file, or replace it with the file itself.",
+            broken.join("\n")
+        ));
+    }
+    
+    // Nothing found (find_params_dir also checks the default ~/.zcash-params location)
+    // Provide helpful error message
+    let mut error_msg = "Prover initialization failed. This usually means the Groth16 proving parameters are not downloaded.\n\n".to_string();
+    
+    // Show what we checked
+    if let Ok(cwd) = env::current_dir() {
+        error_msg += &format!("Current working directory: {:?}\n", cwd);
+        let cwd_params = cwd.join("params");
+        error_msg += &format!("Checked: {:?}\n", cwd_params);
+    }
+    
+    if let Ok(exe_path) = env::current_exe() {
+        error_msg += &format!("Executable path: {:?}\n", exe_path);
+    }
+    
+    // Check if params folder exists but files are missing
+    if let Ok(cwd) = env::current_dir() {
+        let local_params = cwd.join("params");
+        if local_params.exists() {
+            error_msg += &format!("\nFound 'params' folder at: {:?}\n", local_params);
+            error_msg += "Checking files:\n";
             
-            // Show what we checked
-            if let Ok(cwd) = env::current_dir() {
-                error_msg += &format!("Current working directory: {:?}\n", cwd);
-                let cwd_params = cwd.join("params");
-                error_msg += &format!("Checked: {:?}\n", cwd_params);
-            }
+            let spend_params = local_params.join("sapling-spend.params");
+            let output_params = local_params.join("sapling-output.params");
             
-            if let Ok(exe_path) = env::current_exe() {
-                error_msg += &format!("Executable path: {:?}\n", exe_path);
+            if spend_params.exists() {
+                let size = std::fs::metadata(&spend_params)
+                    .map(|m| m.len() / 1024 / 1024)
+                    .unwrap_or(0);
+                error_msg += &format!("  ✅ sapling-spend.params exists ({}) MB\n", size);
+            } else {
+                error_msg += &format!("  ❌ Missing: {:?}\n", spend_params);
             }
             
-            // Check if params folder exists but files are missing
-            if let Ok(cwd) = env::current_dir() {
-                let local_params = cwd.join("params");
-                if local_params.exists() {
-                    error_msg += &format!("\nFound 'params' folder at: {:?}\n", local_params);
-                    error_msg += "Checking files:\n";
-                    
-                    let spend_params = local_params.join("sapling-spend.params");
-                    let output_params = local_params.join("sapling-output.params");
-                    
-                    if spend_params.exists() {
-                        let size = std::fs::metadata(&spend_params)
-                            .map(|m| m.len() / 1024 / 1024)
-                            .unwrap_or(0);
-                        error_msg += &format!("  ✅ sapling-spend.params exists ({}) MB\n", size);
-                    } else {
-                        error_msg += &format!("  ❌ Missing: {:?}\n", spend_params);
-                    }
-                    
-                    if output_params.exists() {
-                        let size = std::fs::metadata(&output_params)
-                            .map(|m| m.len() / 1024 / 1024)
-                            .unwrap_or(0);
-                        error_msg += &format!("  ✅ sapling-output.params exists ({}) MB\n", size);
-                    } else {
-                        error_msg += &format!("  ❌ Missing: {:?}\n", output_params);
-                    }
-                }
+            if output_params.exists() {
+                let size = std::fs::metadata(&output_params)
+                    .map(|m| m.len() / 1024 / 1024)
+                    .unwrap_or(0);
+                error_msg += &format!("  ✅ sapling-output.params exists ({}) MB\n", size);
+            } else {
+                error_msg += &format!("  ❌ Missing: {:?}\n", output_params);
             }
-            
-            error_msg += "\nTo fix this:\n";
-            error_msg += "1. Make sure parameters are in the 'params' folder at the project root\n";
-            error_msg += "2. Run: .\\scripts\\download-zcash-params.ps1\n";
-            error_msg += "3. Restart the proof service after downloading\n";
-            
-            Err(error_msg)
         }
     }
+    
+    error_msg += "\nTo fix this:\n";
+    error_msg += "1. Make sure parameters are in the 'params' folder at the project root\n";
+    error_msg += "2. Run: .\\scripts\\download-zcash-params.ps1\n";
+    error_msg += "3. Restart the proof service after downloading\n";
+    
+    Err(error_msg)
 }
 
-async fn generate_proof(req: web::Json<ProofRequest>) -> ActixResult<HttpResponse> {
+#[utoipa::path(
+    post,
+    path = "/proofs/generate",
+    request_body = ProofRequest,
+    params(EncodingQuery),
+    responses(
+        (status = 200, description = "Proof, or only commitments (output) or the spend authorization (spend) \
+                                      when `proof` is false", body = ProofResponse),
+        (status = 400, description = "Invalid parameters; `error` says which and `errors` lists each bad param", body = ProofResponse),
+        (status = 500, description = "Proving failed; `error` says why", body = ProofResponse),
+        (status = 503, description = "Parameters are still loading or the proving queue is full (retry after \
+                                      the Retry-After delay), or proving is disabled (PROVING_DISABLED)", body = ApiError),
+    )
+)]
+async fn generate_proof(
+    req: web::Json<ProofRequest>,
+    query: web::Query<EncodingQuery>,
+    config: web::Data<Config>,
+    limiter: web::Data<ProofLimiter>,
+) -> ActixResult<HttpResponse> {
     println!("[ProofService] Received proof request: type={}", req.proof_type);
     println!("[ProofService] Params: {}", serde_json::to_string_pretty(&req.params).unwrap_or_default());
+    let encoding = query.encoding;
+    
+    // Reject bad params before loading parameters or proving, listing all of them at once
+    if let Err(e) = validate_proof_params(&req.proof_type, &req.params, &config) {
+        println!("[ProofService] ❌ {}", e.message);
+        return Ok(HttpResponse::BadRequest().json(ProofResponse::invalid(encoding, e)));
+    }
+    
+    // Sign before proving, so a bad key is reported without loading parameters. The
+    // proof is randomized by the same alpha, or it wouldn't match the signature's rk.
+    let alpha = alpha_param(&req.params)
+        .ok()
+        .flatten()
+        .unwrap_or_else(|| proving::random_alpha(&mut OsRng));
+    let spend_authorization = if req.proof_type == "spend" {
+        match authorize_spend(&req.params, &config, &alpha) {
+            Ok(authorization) => Some(authorization),
+            Err(e) => {
+                println!("[ProofService] ❌ {}", e.message);
+                return Ok(HttpResponse::BadRequest().json(ProofResponse::invalid(encoding, e)));
+            }
+        }
+    } else {
+        None
+    };
+    
+    // "proof": false skips proving (and loading params) entirely: an output request
+    // only gets its commitments and a spend request only its rk and signature
+    let wants_proof = req.params.get("proof").and_then(|v| v.as_bool()).unwrap_or(true);
+    if req.proof_type == "spend" && !wants_proof {
+        println!("[ProofService] ✅ Randomized spend authorization (no proof)");
+        return Ok(HttpResponse::Ok().json(ProofResponse {
+            spend_authorization,
+            ..ProofResponse::new(encoding, vec![])
+        }));
+    }
+    if req.proof_type == "output" && !wants_proof {
+        return match generate_output_proof(None, &req.params, &config).await {
+            Ok(output) => {
+                println!("[ProofService] ✅ Computed output commitments (no proof)");
+                Ok(HttpResponse::Ok().json(ProofResponse::output(encoding, output)))
+            }
+            Err(e) => Ok(HttpResponse::BadRequest().json(ProofResponse::error(encoding, e))),
+        };
+    }
     
-    // Get prover (loads Groth16 parameters - can be slow first time)
-    let prover = match get_prover() {
-        Ok(p) => {
+    // Get prover (loads Groth16 parameters - can be slow first time).
+    // Output proofs don't need the much larger spend parameters.
+    let param_set = if req.proof_type == "spend" { ParamSet::Full } else { ParamSet::OutputOnly };
+    // Not `then_some`: that would create (and drop, releasing the parameters) the guard either way
+    if config.no_params {
+        return Ok(proving_disabled_response());
+    }
+    let _release_spend = if config.low_memory && param_set == ParamSet::Full { Some(params::ReleaseSpend) } else { None };
+    let prover = match try_get_prover(param_set) {
+        None => return Ok(params_loading_response()),
+        Some(Ok(p)) => {
             println!("[ProofService] ✅ Prover initialized");
             p
         }
-        Err(e) => {
+        Some(Err(e)) => {
             println!("[ProofService] ⚠️  Prover initialization failed: {}", e);
-            return Ok(HttpResponse::InternalServerError().json(ProofResponse {
-                proof: vec![],
-                error: Some(e),
-            }));
+            return Ok(HttpResponse::InternalServerError().json(ProofResponse::error(encoding, e)));
         }
     };
     
+    // Held until the proof is done
+    let Some(_permit) = limiter.acquire().await else {
+        return Ok(prover_busy_response());
+    };
+    
     match req.proof_type.as_str() {
         "spend" => {
-            match generate_spend_proof(&prover, &req.params).await {
-                Ok(proof) => {
-                    println!("[ProofService] ✅ Generated spend proof ({} bytes)", proof.len());
-                    Ok(HttpResponse::Ok().json(ProofResponse {
-                        proof,
-                        error: None,
-                    }))
+            match generate_spend_proof(&prover, &req.params, &config, alpha).await {
+                Ok(spend) => {
+                    println!("[ProofService] ✅ Generated spend proof ({} bytes)", spend.proof.len());
+                    Ok(HttpResponse::Ok().json(ProofResponse::spend(encoding, spend, spend_authorization)))
                 }
                 Err(e) => {
                     println!("[ProofService] ❌ Spend proof generation failed: {}", e);
-                    Ok(HttpResponse::InternalServerError().json(ProofResponse {
-                        proof: vec![],
-                        error: Some(format!("Spend proof generation failed: {}", e)),
-                    }))
+                    Ok(HttpResponse::InternalServerError()
+                        .json(ProofResponse::error(encoding, format!("Spend proof generation failed: {}", e))))
                 }
             }
         }
         "output" => {
-            match generate_output_proof(&prover, &req.params).await {
-                Ok(proof) => {
-                    println!("[ProofService] ✅ Generated output proof ({} bytes)", proof.len());
-                    Ok(HttpResponse::Ok().json(ProofResponse {
-                        proof,
-                        error: None,
-                    }))
+            match generate_output_proof(Some(&prover), &req.params, &config).await {
+                Ok(output) => {
+                    println!("[ProofService] ✅ Generated output proof ({} bytes)", output.proof.len());
+                    Ok(HttpResponse::Ok().json(ProofResponse::output(encoding, output)))
                 }
                 Err(e) => {
                     println!("[ProofService] ❌ Output proof generation failed: {}", e);
-                    Ok(HttpResponse::InternalServerError().json(ProofResponse {
-                        proof: vec![],
-                        error: Some(format!("Output proof generation failed: {}", e)),
-                    }))
+                    Ok(HttpResponse::InternalServerError()
+                        .json(ProofResponse::error(encoding, format!("Output proof generation failed: {}", e))))
                 }
             }
         }
         _ => {
-            Ok(HttpResponse::BadRequest().json(ProofResponse {
-                proof: vec![],
-                error: Some(format!("Invalid proof type: {}", req.proof_type)),
-            }))
+            Ok(HttpResponse::BadRequest()
+                .json(ProofResponse::error(encoding, format!("Invalid proof type: {}", req.proof_type))))
+        }
+    }
+}
+
+/// Generate several proofs in one request
+#[utoipa::path(
+    post,
+    path = "/proofs/generate-batch",
+    request_body = BatchProofRequest,
+    params(EncodingQuery),
+    responses(
+        (status = 200, description = "One result per item, in order, each with its own status", body = BatchProofResponse),
+        (status = 400, description = "Empty batch, or more items than ZMAIL_MAX_BATCH_ITEMS", body = ApiError),
+    )
+)]
+async fn generate_proof_batch(
+    req: web::Json<BatchProofRequest>,
+    query: web::Query<EncodingQuery>,
+    config: web::Data<Config>,
+    limiter: web::Data<ProofLimiter>,
+) -> ActixResult<HttpResponse> {
+    // Checked before any item is looked at, so one request can't hold the provers for long
+    let count = req.items.len();
+    if count == 0 {
+        return Err(ApiError::bad_request("EMPTY_BATCH", "The batch has no items").into());
+    }
+    if count > config.max_batch_items {
+        println!("[ProofService] ❌ Batch of {} proofs (limit {})", count, config.max_batch_items);
+        return Err(ApiError::bad_request(
+            "BATCH_TOO_LARGE",
+            format!(
+                "The batch has {} items, but the service allows at most {}. Split it into smaller batches.",
+                count, config.max_batch_items
+            ),
+        ).into());
+    }
+    println!("[ProofService] Received batch of {} proof requests", count);
+    
+    // Items go through the single-proof handler one at a time, each taking its own
+    // proving slot, so a batch queues like that many separate requests
+    let mut results = Vec::with_capacity(count);
+    for item in req.into_inner().items {
+        let response = generate_proof(web::Json(item), web::Query(query.0), config.clone(), limiter.clone()).await?;
+        let status = response.status().as_u16();
+        let body = body::to_bytes(response.into_body())
+            .await
+            .map_err(|e| ApiError::internal("BATCH_ITEM_FAILED", format!("Failed to read item response: {}", e)))?;
+        let body = serde_json::from_slice(&body).unwrap_or(serde_json::Value::Null);
+        results.push(BatchProofResult { status, body });
+    }
+    Ok(HttpResponse::Ok().json(BatchProofResponse { results }))
+}
+
+/// The "amount" param of a proof request, which the circuit requires to be in 0..=MAX_MONEY
+fn amount_param(params: &serde_json::Value) -> Result<u64, String> {
+    let value = params.get("amount").ok_or("Missing amount parameter")?;
+    let amount = match value {
+        serde_json::Value::String(s) => s.parse::<i128>().ok(),
+        serde_json::Value::Number(n) => n.as_i64().map(i128::from).or_else(|| n.as_u64().map(i128::from)),
+        _ => None,
+    }
+    .ok_or("Invalid amount parameter: expected an integer number of zatoshi")?;
+    if amount < 0 || amount > consensus::MAX_MONEY as i128 {
+        return Err(format!(
+            "amount {} is out of range: note values must be between 0 and {} zatoshi",
+            amount, consensus::MAX_MONEY
+        ));
+    }
+    Ok(amount as u64)
+}
+
+/// The "amount" param of an output proof request, which may only be zero for a
+/// note flagged with "memoOnly": true
+///
+/// A memo-only note needs a memo and carries `memo_only_value`, so its amount
+/// may be omitted.
+fn output_amount_param(params: &serde_json::Value, memo_only_value: u64) -> Result<u64, String> {
+    let memo_only = params.get("memoOnly").and_then(|v| v.as_bool()).unwrap_or(false);
+    if !memo_only {
+        let amount = amount_param(params)?;
+        if amount == 0 {
+            return Err("amount is 0; set memoOnly to true to send a zero-value note deliberately".to_string());
         }
+        return Ok(amount);
+    }
+    
+    if params.get("memo").and_then(|v| v.as_str()).unwrap_or("").is_empty() {
+        return Err("memoOnly notes need a memo".to_string());
+    }
+    if params.get("amount").is_some() && amount_param(params)? != memo_only_value {
+        return Err(format!("memoOnly notes carry {} zatoshi; omit amount", memo_only_value));
     }
+    Ok(memo_only_value)
 }
 
-/// Generate spend proof using transaction builder
-/// Uses librustzcash's transaction builder which generates real Groth16 proofs
+/// Prove the spend of the request's "note", randomized by `alpha` like its authorization
+///
+/// The witness isn't checked against the chain: a stale or unknown anchor
+/// still gives a valid proof, but the transaction using it is rejected.
 async fn generate_spend_proof(
-    _prover: &LocalTxProver,
+    prover: &Provers,
     params: &serde_json::Value,
-) -> Result<Vec<u8>, String> {
-    println!("[ProofService] Generating spend proof with transaction builder...");
+    config: &Config,
+    alpha: jubjub::Fr,
+) -> Result<SpendProof, String> {
+    println!("[ProofService] Generating spend proof...");
     
-    // Extract parameters
-    let spending_key = params.get("spendingKey")
+    let note = note_param(params)?.ok_or("Missing note parameter")?;
+    let spending_key = params.get("spendingKey").and_then(|v| v.as_str()).unwrap_or_default();
+    let extsk = decode_spending_key(config.network, spending_key).map_err(|e| e.message)?;
+    let spend = note.to_spend(&extsk)?;
+    let spend_params = prover.spend.clone().ok_or("Spend parameters are not loaded")?;
+    
+    let rcv = ValueCommitTrapdoor::random(&mut OsRng);
+    let (cv, _) = proving::commitments(&spend.note, &rcv);
+    let anchor = spend.anchor().to_bytes();
+    let nullifier = spend.nullifier;
+    
+    // Proving takes seconds, so keep it off the async runtime
+    let proof = web::block(move || proving::prove_spend(&spend_params, &spend, alpha, rcv, &mut OsRng))
+        .await
+        .map_err(|e| format!("Proving failed: {}", e))??;
+    Ok(SpendProof { proof: proof.to_vec(), cv, anchor, nullifier })
+}
+
+/// The Sapling receiver of the "toAddress" param of an output proof request
+fn to_address_param(params: &serde_json::Value, config: &Config) -> Result<PaymentAddress, String> {
+    let to_address = params.get("toAddress")
         .and_then(|v| v.as_str())
-        .ok_or("Missing spendingKey parameter")?;
-    
-    let amount: u64 = params.get("amount")
-        .and_then(|v| {
-            if let Some(s) = v.as_str() {
-                s.parse().ok()
-            } else if let Some(n) = v.as_u64() {
-                Some(n)
-            } else {
-                None
+        .ok_or("Missing toAddress parameter")?;
+    match keys::decode_recipient(&config.network, to_address)? {
+        keys::RecipientAddress::Sapling(address)
+        | keys::RecipientAddress::Unified { sapling: Some(address), .. } => Ok(address),
+        _ => Err("toAddress has no Sapling receiver".to_string()),
+    }
+}
+
+/// The optional "memo" param (hex, up to 512 bytes) of an output proof request
+fn memo_param(params: &serde_json::Value) -> Result<MemoBytes, String> {
+    match params.get("memo").and_then(|v| v.as_str()) {
+        Some(memo) => {
+            let bytes = hex::decode(memo).map_err(|e| format!("memo is not valid hex: {}", e))?;
+            MemoBytes::from_bytes(&bytes).map_err(|_| "memo must be at most 512 bytes".to_string())
+        }
+        None => Ok(MemoBytes::empty()),
+    }
+}
+
+/// The optional "rseed" param (hex, 32 bytes) of an output proof request
+///
+/// The rseed fixes the note commitment and, under ZIP 212, the ephemeral
+/// key, so a client that gives one gets the same note (and epk) every time.
+fn rseed_param(params: &serde_json::Value) -> Result<Option<[u8; 32]>, String> {
+    match params.get("rseed").and_then(|v| v.as_str()) {
+        Some(rseed) => notes::decode_hex_array("rseed", rseed).map(Some),
+        None => Ok(None),
+    }
+}
+
+/// The optional sender "ovk" param (hex, 32 bytes) of an output proof request
+fn ovk_param(params: &serde_json::Value) -> Result<Option<OutgoingViewingKey>, String> {
+    match params.get("ovk").and_then(|v| v.as_str()) {
+        Some(ovk) => {
+            let bytes = hex::decode(ovk).map_err(|e| format!("ovk is not valid hex: {}", e))?;
+            let bytes: [u8; 32] = bytes.try_into().map_err(|_| "ovk must be 32 bytes".to_string())?;
+            Ok(Some(OutgoingViewingKey(bytes)))
+        }
+        None => Ok(None),
+    }
+}
+
+/// The optional "sighash" param (hex, 32 bytes) of a spend proof request
+fn sighash_param(params: &serde_json::Value) -> Result<Option<[u8; 32]>, String> {
+    match params.get("sighash").and_then(|v| v.as_str()) {
+        Some(sighash) => {
+            let bytes = hex::decode(sighash).map_err(|e| format!("sighash is not valid hex: {}", e))?;
+            let bytes: [u8; 32] = bytes.try_into().map_err(|_| "sighash must be 32 bytes".to_string())?;
+            Ok(Some(bytes))
+        }
+        None => Ok(None),
+    }
+}
+
+/// The optional "alpha" param (hex, a 32-byte Jubjub scalar) of a spend proof request
+fn alpha_param(params: &serde_json::Value) -> Result<Option<jubjub::Fr>, String> {
+    match params.get("alpha").and_then(|v| v.as_str()) {
+        Some(alpha) => {
+            let bytes = hex::decode(alpha).map_err(|e| format!("alpha is not valid hex: {}", e))?;
+            let bytes: [u8; 32] = bytes.try_into().map_err(|_| "alpha must be 32 bytes".to_string())?;
+            Option::from(jubjub::Fr::from_bytes(&bytes))
+                .map(Some)
+                .ok_or_else(|| "alpha is not a canonical Jubjub scalar".to_string())
+        }
+        None => Ok(None),
+    }
+}
+
+/// The optional "note" param of a spend proof request: the note being spent, with its witness
+fn note_param(params: &serde_json::Value) -> Result<Option<SpendableNote>, String> {
+    match params.get("note") {
+        Some(note) => serde_json::from_value(note.clone()).map(Some).map_err(|e| format!("Invalid note: {}", e)),
+        None => Ok(None),
+    }
+}
+
+/// Whether a spend request is for a note at an internal (change) address
+///
+/// The note's scope decides when the request has one; validation rejects an
+/// "internal" param that contradicts it.
+fn spend_is_internal(params: &serde_json::Value) -> bool {
+    match note_param(params) {
+        Ok(Some(note)) => note.scope == NoteScope::Internal,
+        _ => params.get("internal").and_then(|v| v.as_bool()).unwrap_or(false),
+    }
+}
+
+/// Re-randomize the spend authorizing key of a spend request by `alpha`, and sign its "sighash" if it has one
+///
+/// `alpha` is the "alpha" param when given, so external signers and tests get
+/// reproducible rk values; otherwise it is random and returned, for the
+/// client to prove the spend with. The key is the external one, or the
+/// internal (change) key for internal spends.
+fn authorize_spend(params: &serde_json::Value, config: &Config, alpha: &jubjub::Fr) -> Result<SpendAuthorization, ApiError> {
+    let sighash = sighash_param(params).map_err(|e| ApiError::bad_request("INVALID_SIGHASH", e))?;
+    let spending_key = params.get("spendingKey").and_then(|v| v.as_str()).unwrap_or_default();
+    let mut extsk = decode_spending_key(config.network, spending_key)?;
+    if spend_is_internal(params) {
+        extsk = extsk.derive_internal();
+    }
+    
+    let mut rng = OsRng;
+    let (rk, spend_auth_sig) = match sighash {
+        Some(sighash) => {
+            let signed = proving::sign_spend(&extsk.expsk.ask, alpha, &sighash, &mut rng);
+            (signed.rk, Some(hex::encode(signed.spend_auth_sig)))
+        }
+        None => (proving::randomized_key(&extsk.expsk.ask, alpha), None),
+    };
+    Ok(SpendAuthorization {
+        alpha: hex::encode(alpha.to_bytes()),
+        rk: hex::encode(rk),
+        spend_auth_sig,
+    })
+}
+
+/// Check every param of a proof request, so all problems are reported together
+fn validate_proof_params(proof_type: &str, params: &serde_json::Value, config: &Config) -> Result<(), ApiError> {
+    let mut problems = Validation::default();
+    match proof_type {
+        "spend" => {
+            if params.get("spendingKey").and_then(|v| v.as_str()).is_none() {
+                problems.add("params.spendingKey", ApiError::bad_request("MISSING_SPENDING_KEY", "Missing spendingKey parameter"));
             }
-        })
-        .ok_or("Missing or invalid amount parameter")?;
-    
-    // Note: spending_key is in base58check format (e.g., "secret-extended-key-main1...")
-    // We don't decode it here since we're not actually generating proofs yet.
-    // The proof service currently returns an error directing to use lightwalletd's API.
-    
-    // REAL SOLUTION: Use lightwalletd's transaction building API
-    // Generating proofs separately requires:
-    // - Note commitment tree witness (from lightwalletd)
-    // - Anchor (merkle root from blockchain)
-    // - Proper note construction
-    // 
-    // This is complex. The SIMPLEST viable solution is to use lightwalletd's
-    // gRPC SendTransaction method which builds complete transactions with proofs.
-    
-    Err(format!(
-        "Spend proof generation requires note commitment tree witness.\n\
-         \n\
-         ✅ SIMPLEST SOLUTION: Use lightwalletd's transaction building API\n\
-         \n\
-         Lightwalletd can build complete transactions with real Groth16 proofs via:\n\
-         - gRPC SendTransaction method\n\
-         - Handles witness, anchor, and proof generation automatically\n\
-         \n\
-         Current params: spendingKey ({} chars), amount={}\n\
-         \n\
-         See PROOF_GENERATION_SOLUTION.md for implementation guide."
-    , spending_key.len(), amount))
-}
-
-/// Generate output proof using transaction builder
+            problems.check(
+                "params.amount",
+                amount_param(params).map_err(|e| ApiError::bad_request("INVALID_AMOUNT", e)),
+            );
+            problems.check(
+                "params.sighash",
+                sighash_param(params).map_err(|e| ApiError::bad_request("INVALID_SIGHASH", e)),
+            );
+            problems.check("params.alpha", alpha_param(params).map_err(|e| ApiError::bad_request("INVALID_ALPHA", e)));
+            let note = problems.check("params.note", note_param(params).map_err(|e| ApiError::bad_request("INVALID_NOTE", e)));
+            let wants_proof = params.get("proof").and_then(|v| v.as_bool()).unwrap_or(true);
+            if wants_proof && matches!(note, Some(None)) {
+                problems.add(
+                    "params.note",
+                    ApiError::bad_request(
+                        "MISSING_NOTE",
+                        "A spend proof needs the note being spent, with its witness; pass \"proof\": false to only sign",
+                    ),
+                );
+            }
+            if let Some(Some(note)) = &note {
+                if let Ok(amount) = amount_param(params) {
+                    if amount != note.value {
+                        problems.add(
+                            "params.amount",
+                            ApiError::bad_request(
+                                "INVALID_AMOUNT",
+                                format!("amount {} differs from the note's value {}", amount, note.value),
+                            ),
+                        );
+                    }
+                }
+                let internal = params.get("internal").and_then(|v| v.as_bool());
+                if internal.is_some_and(|internal| internal != (note.scope == NoteScope::Internal)) {
+                    problems.add(
+                        "params.internal",
+                        ApiError::bad_request("SCOPE_MISMATCH", "internal contradicts the note's scope; omit it"),
+                    );
+                }
+            }
+            // The note has to be one the key can spend; a bad key is reported when authorizing
+            let key = params.get("spendingKey").and_then(|v| v.as_str()).unwrap_or_default();
+            if let (Some(Some(note)), Ok(extsk)) = (note, decode_spending_key(config.network, key)) {
+                problems.check(
+                    "params.note",
+                    note.to_spend(&extsk).map_err(|e| ApiError::bad_request("INVALID_NOTE", e)),
+                );
+            }
+        }
+        "output" => {
+            problems.check(
+                "params.toAddress",
+                to_address_param(params, config).map_err(|e| ApiError::bad_request("INVALID_ADDRESS", e)),
+            );
+            problems.check(
+                "params.amount",
+                output_amount_param(params, config.memo_only_value).map_err(|e| ApiError::bad_request("INVALID_AMOUNT", e)),
+            );
+            problems.check("params.memo", memo_param(params).map_err(|e| ApiError::bad_request("INVALID_MEMO", e)));
+            problems.check("params.ovk", ovk_param(params).map_err(|e| ApiError::bad_request("INVALID_OVK", e)));
+            problems.check("params.rseed", rseed_param(params).map_err(|e| ApiError::bad_request("INVALID_RSEED", e)));
+            let wants_proof = params.get("proof").and_then(|v| v.as_bool()).unwrap_or(true);
+            let wants_description = params.get("description").and_then(|v| v.as_bool()).unwrap_or(false);
+            if wants_description && !wants_proof {
+                problems.add(
+                    "params.description",
+                    ApiError::bad_request("DESCRIPTION_NEEDS_PROOF", "An output description includes the proof; drop \"proof\": false"),
+                );
+            }
+        }
+        "orchard" => {
+            problems.add(
+                "type",
+                ApiError::bad_request("ORCHARD_UNSUPPORTED", "Orchard proofs aren't supported by this service yet"),
+            );
+        }
+        _ => {}
+    }
+    problems.finish()
+}
+
+/// Generate an output proof for a new note to `toAddress`
+/// With no prover, only the value and note commitments are computed (no proof).
 async fn generate_output_proof(
-    _prover: &LocalTxProver,
+    prover: Option<&Provers>,
     params: &serde_json::Value,
-) -> Result<Vec<u8>, String> {
-    println!("[ProofService] Generating output proof with transaction builder...");
+    config: &Config,
+) -> Result<OutputProof, String> {
+    println!("[ProofService] Generating output proof...");
     
     // Extract parameters
-    let to_address = params.get("toAddress")
-        .and_then(|v| v.as_str())
-        .ok_or("Missing toAddress parameter")?;
+    let recipient = to_address_param(params, config)?;
+    let amount = output_amount_param(params, config.memo_only_value)?;
+    let memo = memo_param(params)?;
+    let ovk = ovk_param(params)?;
+    let rseed = rseed_param(params)?;
     
-    let amount: u64 = params.get("amount")
-        .and_then(|v| {
-            if let Some(s) = v.as_str() {
-                s.parse().ok()
-            } else if let Some(n) = v.as_u64() {
-                Some(n)
-            } else {
-                None
-            }
-        })
-        .ok_or("Missing or invalid amount parameter")?;
-    
-    // REAL SOLUTION: Use lightwalletd's transaction building API
-    // Output proofs require:
-    // - Payment address decoding (base58check)
-    // - Note construction with recipient, value, memo
-    // - Random seed generation
-    //
-    // The SIMPLEST viable solution is to use lightwalletd's gRPC SendTransaction
-    // which handles all of this automatically.
-    
-    Err(format!(
-        "Output proof generation requires payment address decoding.\n\
-         \n\
-         ✅ SIMPLEST SOLUTION: Use lightwalletd's transaction building API\n\
-         \n\
-         Lightwalletd can build complete transactions with real Groth16 proofs via:\n\
-         - gRPC SendTransaction method\n\
-         - Handles address decoding, note construction, and proof generation\n\
-         \n\
-         Current params: toAddress={}, amount={}\n\
-         \n\
-         See PROOF_GENERATION_SOLUTION.md for implementation guide."
-    , to_address, amount))
+    let mut rng = OsRng;
+    let note = match rseed {
+        Some(rseed) => proving::note_with_rseed(recipient, amount, rseed),
+        None => proving::new_note(recipient, amount, &mut rng),
+    };
+    let rcv = ValueCommitTrapdoor::random(&mut rng);
+    let (cv, cmu) = proving::commitments(&note, &rcv);
+    let encrypted = proving::encrypt_note(&note, &rcv, *memo.as_array(), ovk, &mut rng);
+    // The sender reads their sent mail back with the ovk, so make sure that works
+    if let Some(ovk) = &ovk {
+        proving::check_encryption(&note, memo.as_array(), &rcv, &encrypted, ovk)?;
+    }
+    
+    let Some(prover) = prover else {
+        return Ok(OutputProof { proof: vec![], cv, cmu, encrypted, description: None });
+    };
+    
+    // Proving takes seconds, so keep it off the async runtime
+    let rseed = *note.rseed();
+    let output_params = prover.output.clone();
+    let proof = web::block(move || proving::prove_output(&output_params, &note, rcv, &mut OsRng))
+        .await
+        .map_err(|e| format!("Proving failed: {}", e))?;
+    
+    // Never hand out a proof for a note other than the one requested
+    proving::check_commitment(recipient, amount, rseed, &cmu)?;
+    
+    let wants_description = params.get("description").and_then(|v| v.as_bool()).unwrap_or(false);
+    let description = wants_description.then(|| proving::output_description_v4(&cv, &cmu, &encrypted, &proof));
+    Ok(OutputProof { proof: proof.to_vec(), cv, cmu, encrypted, description })
+}
+
+/// Decode one recipient of a build request, recording every problem with it in `problems`
+fn decode_recipient(i: usize, input: RecipientInput, config: &Config, problems: &mut Validation) -> Option<Recipient> {
+    let field = |name: &str| format!("recipients[{}].{}", i, name);
+    let address = problems.check(field("address"), recipient_receiver(i, &input, config));
+    let amount = problems.check(field("amount"), recipient_amount(i, &input, config));
+    if input.memo_only && input.memo.is_empty() && input.memo_fragments.is_empty() {
+        problems.add(field("memo"), ApiError::bad_request(
+            "MISSING_MEMO",
+            format!("Recipient {} is memo_only but has no memo", i),
+        ));
+        return None;
+    }
+    if !input.memo_fragments.is_empty() {
+        if !input.memo.is_empty() || input.encrypt_memo || input.reply_to != ReplyTo::None {
+            problems.add(field("memo_fragments"), ApiError::bad_request(
+                "AMBIGUOUS_MEMO",
+                format!(
+                    "Recipient {} has memo_fragments, which replace memo, encrypt_memo and reply_to; \
+                     frame those into the fragments instead",
+                    i
+                ),
+            ));
+            return None;
+        }
+        let checked = memo::check_fragments(&input.memo_fragments)
+            .map_err(|e| ApiError::bad_request("INVALID_MEMO", format!("Recipient {}: {}", i, e)));
+        problems.check(field("memo_fragments"), checked)?;
+    } else if input.output_count() > outputs_per_transaction(config, 1).min(memo::MAX_FRAGMENTS) {
+        // A memo up to 512 bytes fills one memo field and longer ones are chunked, but only
+        // into as many fragments as one transaction can carry; say so before TOO_MANY_OUTPUTS
+        let max_fragments = outputs_per_transaction(config, 1).min(memo::MAX_FRAGMENTS);
+        problems.add(field("memo"), ApiError::bad_request(
+            "MEMO_TOO_LONG",
+            format!(
+                "Recipient {}'s memo is {} bytes and needs {} memo fields, but a transaction carries at most {} \
+                 (up to {} bytes of content). Shorten the memo or send it as an email.",
+                i, input.memo.len(), input.output_count(), max_fragments, memo::max_message_size(max_fragments)
+            ),
+        ));
+        return None;
+    }
+    Some(Recipient {
+        address: address?,
+        amount: amount?,
+        memo: input.memo,
+        memo_fragments: input.memo_fragments,
+        encrypt_memo: input.encrypt_memo,
+        reply_to: input.reply_to,
+    })
+}
+
+/// The receiver a build recipient's output goes to
+fn recipient_receiver(i: usize, input: &RecipientInput, config: &Config) -> Result<Receiver, ApiError> {
+    let decoded = keys::decode_recipient(&config.network, &input.address)
+        .map_err(|e| ApiError::bad_request("INVALID_ADDRESS", format!("Invalid address for recipient {}: {}", i, e)))?;
+    // Without Orchard support, such an address is either unpayable or only payable
+    // through its transparent receiver, which would silently drop the memo
+    let transparent = matches!(decoded, keys::RecipientAddress::Unified { transparent: Some(_), .. });
+    if decoded.orchard_only() && (input.has_memo() || !transparent) {
+        return Err(ApiError::bad_request(
+            "ORCHARD_UNSUPPORTED",
+            format!(
+                "Recipient {}'s only shielded receiver is Orchard, which this service doesn't support yet. \
+                 Use an address with a Sapling receiver.",
+                i
+            ),
+        ));
+    }
+    // Transparent receivers can't carry a memo; refuse rather than silently dropping it
+    if input.has_memo() && !decoded.has_shielded_receiver() {
+        return Err(ApiError::bad_request(
+            "MEMO_REQUIRES_SHIELDED",
+            format!(
+                "Recipient {} only has a transparent receiver, which can't carry a memo. \
+                 Messages must be sent to a shielded (Sapling or unified) address.",
+                i
+            ),
+        ));
+    }
+    let address = match decoded {
+        keys::RecipientAddress::Sapling(address)
+        | keys::RecipientAddress::Unified { sapling: Some(address), .. } => Receiver::Sapling(address),
+        keys::RecipientAddress::Transparent(address)
+        | keys::RecipientAddress::Unified { transparent: Some(address), .. } => Receiver::Transparent(address),
+        _ => {
+            return Err(ApiError::bad_request(
+                "UNSUPPORTED_RECEIVER",
+                format!(
+                    "Recipient {} has no Sapling or transparent receiver, and only those outputs are supported",
+                    i
+                ),
+            ));
+        }
+    };
+    if input.memo_only && matches!(address, Receiver::Transparent(_)) {
+        return Err(ApiError::bad_request(
+            "MEMO_REQUIRES_SHIELDED",
+            format!("Recipient {} is memo_only but only has a transparent receiver", i),
+        ));
+    }
+    Ok(address)
+}
+
+/// The value of a build recipient's output, in zatoshi
+fn recipient_amount(i: usize, input: &RecipientInput, config: &Config) -> Result<u64, ApiError> {
+    let amount: u64 = if input.memo_only && input.amount.is_empty() {
+        config.memo_only_value
+    } else {
+        input.amount.parse()
+            .map_err(|_| ApiError::bad_request("INVALID_AMOUNT", format!("Invalid amount for recipient {}: {}", i, input.amount)))?
+    };
+    if input.memo_only && amount != config.memo_only_value {
+        return Err(ApiError::bad_request(
+            "INVALID_AMOUNT",
+            format!(
+                "Recipient {} is memo_only, which carries {} zatoshi; omit amount or send a regular payment",
+                i, config.memo_only_value
+            ),
+        ));
+    }
+    if amount > consensus::MAX_MONEY {
+        return Err(ApiError::bad_request(
+            "INVALID_AMOUNT",
+            format!("Amount {} for recipient {} exceeds the maximum of {} zatoshi", amount, i, consensus::MAX_MONEY),
+        ));
+    }
+    if amount == 0 && !input.memo_only {
+        return Err(ApiError::bad_request(
+            "ZERO_AMOUNT",
+            format!(
+                "Amount for recipient {} is 0. Set memo_only to true to send a zero-value output deliberately.",
+                i
+            ),
+        ));
+    }
+    Ok(amount)
 }
 
 /// Build a complete transaction using librustzcash transaction builder
 /// This is how Ywallet works - builds transactions client-side using compact blocks
-async fn build_transaction(req: web::Json<BuildTransactionRequest>) -> ActixResult<HttpResponse> {
+#[utoipa::path(
+    post,
+    path = "/proofs/build-transaction",
+    request_body = BuildTransactionRequest,
+    responses(
+        (status = 200, description = "Built transaction", body = BuildTransactionResponse),
+        (status = 400, description = "Invalid request (`errors` lists every bad field), insufficient funds or inconsistent notes", body = ApiError),
+        (status = 403, description = "Key file given without ZMAIL_LOCAL_KEYS", body = ApiError),
+        (status = 409, description = "Single-wallet mode: a concurrent build reserved the selected notes (NOTES_PENDING)", body = ApiError),
+        (status = 503, description = "Parameters are still loading or the proving queue is full (retry after \
+                                      the Retry-After delay), or proving is disabled (PROVING_DISABLED)", body = ApiError),
+        (status = 502, description = "lightwalletd request failed", body = ApiError),
+        (status = 500, description = "Build failed", body = ApiError),
+    )
+)]
+async fn build_transaction(
+    req: web::Json<BuildTransactionRequest>,
+    config: web::Data<Config>,
+    limiter: web::Data<ProofLimiter>,
+    pending_spends: web::Data<PendingSpends>,
+) -> ActixResult<HttpResponse> {
+    build_transaction_with(req, config, limiter, pending_spends, true).await
+}
+
+/// The build-transaction handler; with `keep_reserved` false, a successful
+/// build frees its notes in single-wallet mode instead of holding them
+async fn build_transaction_with(
+    req: web::Json<BuildTransactionRequest>,
+    config: web::Data<Config>,
+    limiter: web::Data<ProofLimiter>,
+    pending_spends: web::Data<PendingSpends>,
+    keep_reserved: bool,
+) -> ActixResult<HttpResponse> {
     println!("[ProofService] Received transaction building request");
     
     // Safe string slicing - won't panic on empty strings
@@ -411,76 +1809,1794 @@ async fn build_transaction(req: web::Json<BuildTransactionRequest>) -> ActixResu
     };
     
     println!("[ProofService] From: {}...", from_preview);
-    println!("[ProofService] To: {}...", to_preview);
-    println!("[ProofService] Amount: {} zatoshi", req.amount);
+    if req.recipients.is_empty() {
+        println!("[ProofService] To: {}...", to_preview);
+        println!("[ProofService] Amount: {} zatoshi", req.amount);
+    } else {
+        println!("[ProofService] To: {} recipients", req.recipients.len());
+    }
+    let recipient_inputs = req.recipients();
     
-    // Get prover for proof generation (will be used when implementing full transaction building)
-    let _prover = match get_prover() {
-        Ok(p) => {
-            println!("[ProofService] ✅ Prover initialized");
-            p
+    // Reject oversized builds before loading params or proving anything.
+    // Every output costs a Groth16 proof and ~1KB of transaction size.
+    let output_count = recipient_inputs.iter()
+        .map(RecipientInput::output_count)
+        .sum::<usize>() + req.dummy_outputs;
+    let mut problems = Validation::default();
+    if output_count > config.max_outputs {
+        println!("[ProofService] ❌ Build needs {} outputs (limit {})", output_count, config.max_outputs);
+        problems.add("recipients", ApiError::bad_request(
+            "TOO_MANY_OUTPUTS",
+            format!(
+                "This transaction would need {} outputs, but the service allows at most {}. \
+                 Split the message into several smaller sends.",
+                output_count, config.max_outputs
+            ),
+        ));
+    }
+    // Padding fills up to the target less the change output, so that is all it can add
+    let pad_to = req.pad_outputs.unwrap_or(config.pad_outputs);
+    if pad_to.saturating_sub(1) > config.max_outputs {
+        problems.add("pad_outputs", ApiError::bad_request(
+            "INVALID_PAD_OUTPUTS",
+            format!(
+                "Padding to {} outputs exceeds the service's limit of {} outputs plus change",
+                pad_to, config.max_outputs
+            ),
+        ));
+    }
+    
+    let network = config.network;
+    // Key files keep the key off the wire, but only make sense when the service is
+    // local to the user; otherwise any client could read files on the server
+    let encoded_key = match &req.spending_key_file {
+        Some(path) => {
+            if !config.local_keys {
+                return Err(ApiError::new(
+                    StatusCode::FORBIDDEN,
+                    "LOCAL_KEYS_DISABLED",
+                    "spending_key_file is only accepted when the service runs with ZMAIL_LOCAL_KEYS=1",
+                ).into());
+            }
+            if !req.spending_key.is_empty() {
+                problems.add("spending_key_file", ApiError::bad_request(
+                    "AMBIGUOUS_SPENDING_KEY",
+                    "Give either spending_key or spending_key_file, not both",
+                ));
+                None
+            } else {
+                problems.check(
+                    "spending_key_file",
+                    keys::read_spending_key_file(Path::new(path))
+                        .map_err(|e| ApiError::bad_request("INVALID_SPENDING_KEY_FILE", e)),
+                )
+            }
         }
-        Err(e) => {
-            println!("[ProofService] ⚠️  Prover initialization failed: {}", e);
-            return Ok(HttpResponse::InternalServerError().json(BuildTransactionResponse {
+        None => Some(req.spending_key.clone()),
+    };
+    let extsk = encoded_key.and_then(|key| problems.check("spending_key", decode_spending_key(network, &key)));
+    let mut recipients = Vec::with_capacity(recipient_inputs.len());
+    // Memo-only outputs are funded from the sender's inputs like any payment
+    let mut postage = 0u64;
+    for (i, input) in recipient_inputs.into_iter().enumerate() {
+        let memo_only = input.memo_only;
+        if let Some(recipient) = decode_recipient(i, input, &config, &mut problems) {
+            if memo_only {
+                postage += recipient.amount;
+            }
+            recipients.push(recipient);
+        }
+    }
+    let target_height = problems.check(
+        "target_height",
+        match req.target_height {
+            None => Err(ApiError::bad_request("MISSING_TARGET_HEIGHT", "target_height is required")),
+            Some(height) if height >= consensus::TX_EXPIRY_HEIGHT_THRESHOLD - DEFAULT_TX_EXPIRY_DELTA => {
+                Err(ApiError::bad_request(
+                    "INVALID_TARGET_HEIGHT",
+                    format!(
+                        "target_height {} is out of range: the transaction would expire at or after height {}",
+                        height, consensus::TX_EXPIRY_HEIGHT_THRESHOLD
+                    ),
+                ))
+            }
+            Some(height) => Ok(BlockHeight::from_u32(height)),
+        },
+    );
+    let rng_seed = problems.check(
+        "rng_seed",
+        req.rng_seed.as_deref()
+            .map(|seed| notes::decode_hex_array::<32>("rng_seed", seed))
+            .transpose()
+            .map_err(|e| ApiError::bad_request("INVALID_RNG_SEED", e)),
+    );
+    let candidates = extsk.as_ref().map(|extsk| {
+        req.notes.iter()
+            .enumerate()
+            .filter_map(|(i, note)| {
+                let spend = note.to_spend(extsk)
+                    .map_err(|e| ApiError::bad_request("INVALID_NOTE", format!("{}: {}", note.label(i), e)));
+                problems.check(format!("notes[{}]", i), spend)
+            })
+            .collect::<Vec<_>>()
+    });
+    // Everything above only depends on the request itself, so report it all together
+    problems.finish()?;
+    let (Some(extsk), Some(target_height), Some(rng_seed), Some(candidates)) = (extsk, target_height, rng_seed, candidates) else {
+        unreachable!("validation passed but a field is missing");
+    };
+    
+    // Paying the same address the same amount with the same memo twice is almost always a mistake
+    let duplicates = recipients::duplicates(&recipients);
+    if !duplicates.is_empty() && !req.allow_duplicates {
+        println!("[ProofService] ❌ Duplicate recipients: {:?}", duplicates);
+        let listed = duplicates.iter()
+            .map(|(first, dup)| format!("{} repeats {}", dup, first))
+            .collect::<Vec<_>>()
+            .join(", ");
+        return Err(ApiError::bad_request(
+            "DUPLICATE_RECIPIENTS",
+            format!(
+                "Recipients are listed more than once with the same amount and memo ({}). \
+                 Remove the duplicates, or set allow_duplicates to pay them separately.",
+                listed
+            ),
+        ).into());
+    }
+    
+    // Deshielding reveals the recipient and amount on-chain and links them to this
+    // transaction, so it must be asked for explicitly
+    let deshielding = recipients.iter().any(|r| matches!(r.address, Receiver::Transparent(_)));
+    if deshielding {
+        if !req.acknowledge_privacy_loss {
+            println!("[ProofService] ❌ Transparent recipient without acknowledge_privacy_loss");
+            return Err(ApiError::bad_request(
+                "PRIVACY_ACK_REQUIRED",
+                "Sending to a transparent address publishes the recipient and amount on-chain. \
+                 Set acknowledge_privacy_loss to true to deshield anyway.",
+            ).into());
+        }
+        println!("[ProofService] ⚠️  Deshielding to a transparent recipient (privacy loss acknowledged)");
+    }
+    
+    let amount = recipients.iter()
+        .try_fold(0u64, |total, r| total.checked_add(r.amount))
+        .filter(|total| *total <= consensus::MAX_MONEY)
+        .ok_or_else(|| {
+            ApiError::bad_request(
+                "VALUE_OVERFLOW",
+                format!("The recipient amounts add up to more than the maximum of {} zatoshi", consensus::MAX_MONEY),
+            )
+        })?;
+    // Shielding funds the transaction from transparent coins instead of notes
+    let shielding = !req.transparent_inputs.is_empty();
+    if shielding && !candidates.is_empty() {
+        return Err(ApiError::bad_request(
+            "MIXED_INPUTS",
+            "Give either notes or transparent_inputs, not both. Shield the coins first, then spend the notes.",
+        ).into());
+    }
+    let transparent_spends = if shielding {
+        let encoded = req.transparent_key.as_deref()
+            .ok_or_else(|| ApiError::bad_request("MISSING_TRANSPARENT_KEY", "transparent_inputs require transparent_key"))?;
+        let key = transparent::decode_secret_key(&network, encoded)
+            .map_err(|e| ApiError::bad_request("INVALID_TRANSPARENT_KEY", e))?;
+        req.transparent_inputs.iter()
+            .enumerate()
+            .map(|(i, utxo)| utxo.to_spend(key).map_err(|e| format!("Transparent input {}: {}", i, e)))
+            .collect::<Result<Vec<_>, String>>()
+            .map_err(|e| ApiError::bad_request("INVALID_TRANSPARENT_INPUT", e))?
+    } else {
+        Vec::new()
+    };
+    
+    // An empty wallet is a different problem from too small a balance
+    if !shielding && candidates.iter().all(|c| c.value() == 0) {
+        println!("[ProofService] ❌ No spendable notes supplied");
+        return Err(ApiError::bad_request(
+            "NO_SPENDABLE_FUNDS",
+            "The wallet has no spendable notes. Receive funds to this key before sending.",
+        ).into());
+    }
+    
+    // All spends share the transaction's one anchor; mixed witnesses would only fail at broadcast
+    if let Err(e) = notes::check_common_anchor(&candidates) {
+        println!("[ProofService] ❌ {}", e);
+        return Err(ApiError::bad_request("ANCHOR_MISMATCH", e).into());
+    }
+    
+    // A pinned anchor is checked against the chain: the block must still have the
+    // expected hash, and every witness must be at that block's tree
+    match (req.anchor_height, &req.anchor_hash) {
+        (None, None) => {}
+        (Some(anchor_height), Some(anchor_hash)) => {
+            if anchor_height >= u32::from(target_height) {
+                return Err(ApiError::bad_request(
+                    "INVALID_ANCHOR",
+                    format!("anchor_height {} must be below target_height {}", anchor_height, u32::from(target_height)),
+                ).into());
+            }
+            let endpoints = req.endpoints(&config);
+            if endpoints.is_empty() {
+                return Err(ApiError::bad_request(
+                    "MISSING_LIGHTWALLETD_ENDPOINT",
+                    "anchor_height requires lightwalletd_endpoint or ZMAIL_LIGHTWALLETD_ENDPOINTS",
+                ).into());
+            }
+            println!("[ProofService] 🔍 Checking witnesses against the anchor at height {}", anchor_height);
+            
+            let tree = async {
+                let mut client = LightwalletdClient::connect(&endpoints).await?;
+                client.tree_state(anchor_height as u64).await
+            }
+            .await
+            .map_err(|e| ApiError::bad_gateway("LIGHTWALLETD_ERROR", e))?;
+            
+            if let Err(e) = notes::check_anchor(&candidates, &tree, anchor_hash) {
+                println!("[ProofService] ❌ Anchor check failed: {}", e);
+                return Err(ApiError::bad_request("ANCHOR_MISMATCH", e).into());
+            }
+        }
+        _ => {
+            return Err(ApiError::bad_request(
+                "INVALID_ANCHOR",
+                "anchor_height and anchor_hash must be given together",
+            ).into());
+        }
+    }
+    
+    // Optionally make sure none of the notes were spent since the client last synced,
+    // rather than finding out when the broadcast is rejected
+    if req.check_spent {
+        let endpoints = req.endpoints(&config);
+        if endpoints.is_empty() {
+            return Err(ApiError::bad_request(
+                "MISSING_LIGHTWALLETD_ENDPOINT",
+                "check_spent requires lightwalletd_endpoint or ZMAIL_LIGHTWALLETD_ENDPOINTS",
+            ).into());
+        }
+        println!("[ProofService] 🔍 Checking {} note nullifiers against lightwalletd", candidates.len());
+        
+        let tip = u32::from(target_height).saturating_sub(1);
+        let spent = async {
+            let mut client = LightwalletdClient::connect(&endpoints).await?;
+            notes::find_spent(&mut client, &candidates, tip).await
+        }
+        .await
+        .map_err(|e| ApiError::bad_gateway("LIGHTWALLETD_ERROR", e))?;
+        
+        if !spent.is_empty() {
+            println!("[ProofService] ❌ Notes already spent: {:?}", spent);
+            return Err(ApiError::bad_request(
+                "NOTE_ALREADY_SPENT",
+                format!(
+                    "Notes {:?} have already been spent on-chain. Resync the wallet and retry without them.",
+                    spent
+                ),
+            ).into());
+        }
+    }
+    
+    // Plan outputs: each recipient (one output per memo fragment, in sequence
+    // order, value on the first), then any requested dummy outputs and padding
+    let dfvk = extsk.to_diversifiable_full_viewing_key();
+    let ovk = Some(dfvk.fvk().ovk);
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as u32)
+        .unwrap_or(0);
+    if rng_seed.is_some() {
+        println!("[ProofService] ⚠️  Building from the request's rng_seed: reproducible, and linkable to any other build from that seed");
+    }
+    let mut rng = rng::build_rng(&config, rng_seed);
+    let mut outputs = Vec::new();
+    for recipient in &recipients {
+        // Transparent outputs have no memo field, so they take a single plain output
+        let address = match recipient.address {
+            Receiver::Sapling(address) => address,
+            Receiver::Transparent(_) => {
+                outputs.push(PlannedOutput {
+                    kind: OutputKind::Recipient,
+                    ovk: None,
+                    address: recipient.address,
+                    value: recipient.amount,
+                    memo: MemoBytes::empty(),
+                });
+                continue;
+            }
+        };
+        let reply_to = match recipient.reply_to {
+            ReplyTo::None => None,
+            ReplyTo::Default => Some(dfvk.default_address().1.to_bytes()),
+            ReplyTo::Fresh => Some(keys::random_address(&dfvk, &mut rng).to_bytes()),
+        };
+        let fragments = if !recipient.memo_fragments.is_empty() {
+            recipient.memo_fragments.clone()
+        } else if recipient.encrypt_memo && !recipient.memo.is_empty() {
+            let encrypted = attachment::encrypt(&recipient.memo, &mut rng)
+                .map_err(|e| ApiError::internal("ENCRYPTION_FAILED", e))?;
+            memo::encrypted_fragments(&encrypted.key, &encrypted.ciphertext, timestamp, reply_to.as_ref())
+        } else {
+            memo::fragments(&recipient.memo, timestamp, reply_to.as_ref())
+        };
+        for (i, fragment) in fragments.iter().enumerate() {
+            let memo = MemoBytes::from_bytes(fragment)
+                .map_err(|e| ApiError::bad_request("INVALID_MEMO", format!("Invalid memo: {:?}", e)))?;
+            outputs.push(PlannedOutput {
+                kind: OutputKind::Recipient,
+                ovk,
+                address: Receiver::Sapling(address),
+                value: if i == 0 { recipient.amount } else { 0 },
+                memo,
+            });
+        }
+    }
+    for _ in 0..req.dummy_outputs {
+        outputs.push(transaction::dummy_output(&mut rng));
+    }
+    
+    // Padding leaves room for the change output, which padded builds always get
+    let planned_sapling_outputs = outputs.iter()
+        .filter(|o| matches!(o.address, Receiver::Sapling(_)))
+        .count();
+    let padding = pad_to.saturating_sub(planned_sapling_outputs + 1);
+    if pad_to > 0 && planned_sapling_outputs + 1 > pad_to {
+        println!(
+            "[ProofService] ⚠️  Build has {} Sapling outputs with change, more than the padding target {}; not padded",
+            planned_sapling_outputs + 1, pad_to
+        );
+    }
+    for _ in 0..padding {
+        outputs.push(transaction::dummy_output(&mut rng));
+    }
+    
+    // In single-wallet mode, notes an earlier build spent stay off limits until its transaction expires
+    let (candidates, reserved): (Vec<_>, Vec<_>) = candidates
+        .into_iter()
+        .partition(|c| !config.single_wallet || !pending_spends.is_pending(&c.nullifier, u32::from(target_height)));
+    let reserved_value = reserved.iter().fold(0u64, |sum, c| sum.saturating_add(c.value()));
+    if !reserved.is_empty() {
+        println!("[ProofService] Leaving out {} notes spent by pending transactions", reserved.len());
+    }
+    
+    // Select notes; the ZIP-317 fee counts every output, dummies included
+    let transparent_outputs = outputs.iter()
+        .filter(|o| matches!(o.address, Receiver::Transparent(_)))
+        .count();
+    let sapling_outputs = outputs.len() - transparent_outputs;
+    let selection = if shielding {
+        transparent::select(&transparent_spends, amount, sapling_outputs, transparent_outputs, req.fee, pad_to > 0)
+    } else {
+        notes::select_notes(
+            candidates,
+            amount,
+            sapling_outputs,
+            transparent_outputs,
+            u32::from(target_height),
+            req.min_confirmations,
+            req.fee,
+            pad_to > 0,
+        )
+    };
+    let selection = match selection {
+        Ok(selection) => selection,
+        Err(SelectionError::InsufficientFunds { available, required, pending }) => {
+            println!(
+                "[ProofService] ❌ Insufficient funds: {} available, {} required, {} pending",
+                available, required, pending
+            );
+            let mut message = format!(
+                "Insufficient funds: {} zatoshi available, {} zatoshi required (including fee)",
+                available, required
+            );
+            if pending > 0 {
+                message += &format!(
+                    ". A further {} zatoshi is in notes with fewer than {} confirmations",
+                    pending, req.min_confirmations
+                );
+            }
+            if reserved_value > 0 {
+                message += &format!(
+                    ". A further {} zatoshi is in notes spent by earlier builds that haven't been mined or expired yet",
+                    reserved_value
+                );
+            }
+            return Err(ApiError::bad_request("INSUFFICIENT_FUNDS", message).into());
+        }
+        Err(SelectionError::ValueOverflow) => {
+            println!("[ProofService] ❌ Note or amount values overflow MAX_MONEY");
+            return Err(ApiError::bad_request(
+                "VALUE_OVERFLOW",
+                "The amount or the total value of the supplied notes exceeds the 21 million ZEC maximum",
+            ).into());
+        }
+    };
+    println!(
+        "[ProofService] Selected {} notes, {} transparent inputs, {} outputs ({} dummy, {} padding), fee {} zatoshi",
+        selection.notes.len(), transparent_spends.len(), outputs.len(), req.dummy_outputs, padding, selection.fee
+    );
+    
+    // Held until the build succeeds; dropping it on any other return frees the notes again
+    let reservation = if config.single_wallet {
+        let nullifiers: Vec<[u8; 32]> = selection.notes.iter().map(|c| c.nullifier).collect();
+        let height = u32::from(target_height);
+        match pending_spends.reserve(&nullifiers, height, height.saturating_add(DEFAULT_TX_EXPIRY_DELTA)) {
+            Ok(reservation) => Some(reservation),
+            Err(taken) => {
+                println!("[ProofService] ❌ Notes {:?} were reserved by a concurrent build", taken);
+                return Err(ApiError::new(
+                    StatusCode::CONFLICT,
+                    "NOTES_PENDING",
+                    "A concurrent build spent some of the selected notes. Retry to select others.",
+                ).into());
+            }
+        }
+    } else {
+        None
+    };
+    let build_nonce = transaction::build_nonce(
+        network,
+        target_height,
+        &selection.notes,
+        &transparent_spends,
+        &recipients,
+        selection.fee,
+    );
+    
+    if let Some(change) = selection.change {
+        outputs.push(PlannedOutput {
+            kind: OutputKind::Change,
+            ovk,
+            address: Receiver::Sapling(dfvk.default_address().1),
+            value: change,
+            memo: MemoBytes::empty(),
+        });
+    }
+    
+    // Recipients first and change last would reveal which output is change.
+    // Memo fragments carry their own sequence numbers, so order doesn't matter to them.
+    // The Sapling builder also permutes outputs with its build RNG; shuffling here
+    // keeps the order random regardless of the builder used.
+    if req.shuffle_outputs {
+        outputs.shuffle(&mut rng);
+    }
+    
+    // A fee below the conventional fee may never be mined, so require an explicit opt-in
+    let fee = selection.fee;
+    let fee_override = req.fee;
+    let conventional_fee = fees::zip317_fee_with_transparent(
+        transparent_spends.len(),
+        transparent_outputs,
+        selection.notes.len(),
+        outputs.len() - transparent_outputs,
+    );
+    if fee_override.is_some() && fee < conventional_fee && !req.allow_low_fee {
+        println!("[ProofService] ❌ Fee {} is below the conventional {}", fee, conventional_fee);
+        return Err(ApiError::bad_request(
+            "FEE_TOO_LOW",
+            format!(
+                "A fee of {} zatoshi is below the ZIP-317 conventional fee of {} zatoshi for this transaction \
+                 and may not be relayed or mined. Set allow_low_fee to use it anyway.",
+                fee, conventional_fee
+            ),
+        ).into());
+    }
+    
+    // Checked once the spends and change are known: they count towards the size too
+    let sapling_output_count = outputs.len() - transparent_outputs;
+    let max_sapling_outputs = consensus::max_sapling_outputs(selection.notes.len());
+    if sapling_output_count > max_sapling_outputs {
+        println!("[ProofService] ❌ Build has {} Sapling outputs (size limit {})", sapling_output_count, max_sapling_outputs);
+        return Err(ApiError::bad_request(
+            "TRANSACTION_TOO_LARGE",
+            format!(
+                "Spending {} notes, this transaction would have {} Sapling outputs (change included), but at most {} \
+                 fit in the {} bytes nodes relay. Split the message into several smaller sends, or plan it with /email/plan.",
+                selection.notes.len(), sapling_output_count, max_sapling_outputs, consensus::MAX_STANDARD_TX_SIZE
+            ),
+        ).into());
+    }
+    
+    // From exactly what the builder gets, so it matches the transaction
+    let value_breakdown = ValueBreakdown::of_build(&selection.notes, &transparent_spends, &outputs, fee);
+    
+    let result = match req.proofs {
+        ProofMode::Defer => {
+            println!("[ProofService] Deferring proofs to an external prover");
+            web::block(move || {
+                transaction::build_deferred(
+                    network,
+                    target_height,
+                    &extsk,
+                    transparent_spends,
+                    selection.notes,
+                    outputs,
+                    fee_override,
+                    rng,
+                )
+                    .map(|(built, inputs)| (built, Some(inputs)))
+            })
+            .await
+        }
+        ProofMode::Inline => {
+            // Get prover for proof generation. Builds other than shielding spend at
+            // least one note, so the spend params are needed.
+            if config.no_params {
+                return Ok(proving_disabled_response());
+            }
+            let _release_spend = if config.low_memory { Some(params::ReleaseSpend) } else { None };
+            let prover = match try_get_prover(ParamSet::Full) {
+                None => return Ok(params_loading_response()),
+                Some(Ok(p)) => {
+                    println!("[ProofService] ✅ Prover initialized");
+                    p
+                }
+                Some(Err(e)) => {
+                    println!("[ProofService] ⚠️  Prover initialization failed: {}", e);
+                    return Ok(HttpResponse::InternalServerError().json(BuildTransactionResponse {
+                        raw_transaction: vec![],
+                        txid: None,
+                        privacy: None,
+                        fee: None,
+                        postage: None,
+                        build_nonce: None,
+                        proving_inputs: None,
+                        value_breakdown: None,
+                        error: Some(format!("Prover initialization failed: {}", e)),
+                    }));
+                }
+            };
+            let Some(_permit) = limiter.acquire().await else {
+                return Ok(prover_busy_response());
+            };
+            
+            // Building generates a Groth16 proof per spend and output, so keep it off the async runtime
+            web::block(move || {
+                transaction::build(
+                    network,
+                    target_height,
+                    transparent_spends,
+                    selection.notes,
+                    outputs,
+                    &prover,
+                    fee_override,
+                    rng,
+                )
+                    .map(|built| (built, None))
+            })
+            .await
+        }
+    };
+    
+    match result {
+        Ok(Ok((built, proving_inputs))) => {
+            println!("[ProofService] ✅ Built transaction {} ({} bytes)", built.txid, built.raw.len());
+            if let Some(reservation) = reservation.filter(|_| keep_reserved) {
+                reservation.built(&built.txid);
+            }
+            // A deshielding build counts as transparent even though it also has
+            // shielded components: what it reveals is what the user must see
+            let privacy = if deshielding { Privacy::Transparent } else { built.privacy };
+            Ok(HttpResponse::Ok().json(BuildTransactionResponse {
+                raw_transaction: built.raw,
+                txid: Some(built.txid),
+                privacy: Some(privacy),
+                fee: Some(fee),
+                postage: Some(postage),
+                build_nonce: Some(build_nonce),
+                proving_inputs,
+                value_breakdown: Some(value_breakdown),
+                error: None,
+            }))
+        }
+        Ok(Err(e)) => {
+            println!("[ProofService] ❌ {}", e);
+            Ok(HttpResponse::InternalServerError().json(BuildTransactionResponse {
                 raw_transaction: vec![],
                 txid: None,
-                error: Some(format!("Prover initialization failed: {}", e)),
-            }));
+                privacy: None,
+                fee: None,
+                postage: None,
+                build_nonce: None,
+                proving_inputs: None,
+                value_breakdown: None,
+                error: Some(e),
+            }))
         }
+        Err(e) => Err(ApiError::internal("BUILD_FAILED", e.to_string()).into()),
+    }
+}
+
+/// Build, prove and broadcast a transaction in one call
+///
+/// The build goes through /proofs/build-transaction, whose error responses are
+/// passed on unchanged. A transaction that was built but not accepted comes back
+/// with a 502 and its raw bytes, so the client can broadcast it again without
+/// rebuilding; in single-wallet mode its notes stay reserved for that retry.
+/// A `dry_run` build is never broadcast, so it leaves its notes free.
+#[utoipa::path(
+    post,
+    path = "/tx/send",
+    request_body = SendTransactionRequest,
+    responses(
+        (status = 200, description = "Built and broadcast (or built only, with `dry_run`)", body = SendTransactionResponse),
+        (status = 400, description = "Invalid build request, a deferred-proof build, or no lightwalletd endpoint to broadcast to", body = ApiError),
+        (status = 502, description = "Built, but the broadcast failed; `build.raw_transaction` holds the transaction", body = SendTransactionResponse),
+    )
+)]
+async fn send_transaction(
+    req: web::Json<SendTransactionRequest>,
+    config: web::Data<Config>,
+    limiter: web::Data<ProofLimiter>,
+    pending_spends: web::Data<PendingSpends>,
+) -> ActixResult<HttpResponse> {
+    let SendTransactionRequest { build, dry_run } = req.into_inner();
+    
+    // Checked before building, so a send that can't be broadcast doesn't cost any proving
+    let endpoints = build.endpoints(&config);
+    if !dry_run {
+        if build.proofs == ProofMode::Defer {
+            return Err(ApiError::bad_request(
+                "PROOFS_DEFERRED",
+                "A build with deferred proofs can't be broadcast; prove it first, or set dry_run",
+            ).into());
+        }
+        if endpoints.is_empty() {
+            return Err(ApiError::bad_request(
+                "MISSING_LIGHTWALLETD_ENDPOINT",
+                "Provide lightwalletd_endpoint or run the service with ZMAIL_LIGHTWALLETD_ENDPOINTS",
+            ).into());
+        }
+    }
+    
+    let response = build_transaction_with(web::Json(build), config, limiter, pending_spends, !dry_run).await?;
+    if !response.status().is_success() {
+        return Ok(response);
+    }
+    let body = body::to_bytes(response.into_body())
+        .await
+        .map_err(|e| ApiError::internal("BUILD_FAILED", format!("Failed to read the build response: {}", e)))?;
+    let built: serde_json::Value = serde_json::from_slice(&body)
+        .map_err(|e| ApiError::internal("BUILD_FAILED", format!("Invalid build response: {}", e)))?;
+    let txid = built["txid"].as_str().map(str::to_string);
+    if dry_run {
+        return Ok(HttpResponse::Ok().json(SendTransactionResponse {
+            txid,
+            broadcast: BroadcastStatus::Skipped,
+            broadcast_error: None,
+            build: built,
+        }));
+    }
+    
+    let raw = hex::decode(built["raw_transaction"].as_str().unwrap_or_default())
+        .map_err(|e| ApiError::internal("BUILD_FAILED", format!("Build returned no transaction: {}", e)))?;
+    let sent = match LightwalletdClient::connect(&endpoints).await {
+        Ok(mut client) => client.send_transaction(&raw).await,
+        Err(e) => Err(e),
+    };
+    match sent {
+        Ok(()) => {
+            println!("[ProofService] ✅ Broadcast transaction {}", txid.as_deref().unwrap_or_default());
+            Ok(HttpResponse::Ok().json(SendTransactionResponse {
+                txid,
+                broadcast: BroadcastStatus::Sent,
+                broadcast_error: None,
+                build: built,
+            }))
+        }
+        Err(e) => {
+            println!("[ProofService] ❌ Built transaction {} but broadcast failed: {}", txid.as_deref().unwrap_or_default(), e);
+            Ok(HttpResponse::BadGateway().json(SendTransactionResponse {
+                txid,
+                broadcast: BroadcastStatus::Failed,
+                broadcast_error: Some(e),
+                build: built,
+            }))
+        }
+    }
+}
+
+/// Estimate how long proving a build will take on this host, so the UI can set expectations
+#[utoipa::path(
+    post,
+    path = "/proofs/estimate-time",
+    request_body = EstimateTimeRequest,
+    responses(
+        (status = 200, description = "Estimated proving time", body = estimate::Estimate),
+        (status = 503, description = "Proving hasn't been calibrated yet; retry after the Retry-After delay", body = ApiError),
+    )
+)]
+async fn estimate_time(req: web::Json<EstimateTimeRequest>) -> ActixResult<HttpResponse> {
+    match estimate::estimate(req.spends, req.outputs) {
+        Some(estimate) => Ok(HttpResponse::Ok().json(estimate)),
+        None => Ok(HttpResponse::ServiceUnavailable()
+            .insert_header((header::RETRY_AFTER, PARAMS_LOADING_RETRY_AFTER.to_string()))
+            .json(ApiError::new(
+                StatusCode::SERVICE_UNAVAILABLE,
+                "NOT_CALIBRATED",
+                "Proving time hasn't been measured yet; the output parameters may still be loading or missing.",
+            ))),
+    }
+}
+
+/// Outputs one transaction spending `spends` notes may have besides change: the configured
+/// limit, or fewer if that many would push it over the relay size limit
+fn outputs_per_transaction(config: &Config, spends: usize) -> usize {
+    config.max_outputs
+        .min(consensus::max_sapling_outputs(spends).saturating_sub(1))
+        .max(1)
+}
+
+/// Plan an outbound email: the outputs, fees and proving time of the builds that will send it
+///
+/// Nothing is proven or signed, so the plan can be reviewed before sending.
+/// Each planned transaction is one build: a recipient's outputs in it, in
+/// order, are that recipient's `memo_fragments`, with the first output's value
+/// as its amount.
+#[utoipa::path(
+    post,
+    path = "/email/plan",
+    request_body = EmailPlanRequest,
+    responses(
+        (status = 200, description = "Outputs, fee and proving estimate per transaction", body = EmailPlanResponse),
+        (status = 400, description = "Invalid recipients or attachments (`errors` lists each), \
+                                      or attachments over ZMAIL_MAX_ATTACHMENT_BYTES", body = ApiError),
+    )
+)]
+async fn plan_email(
+    req: web::Json<EmailPlanRequest>,
+    config: web::Data<Config>,
+) -> ActixResult<HttpResponse> {
+    println!(
+        "[ProofService] Planning email to {} recipients with {} attachments",
+        req.recipients.len(), req.attachments.len()
+    );
+    
+    let mut problems = Validation::default();
+    if req.recipients.is_empty() {
+        problems.add("recipients", ApiError::bad_request("MISSING_RECIPIENTS", "An email needs at least one recipient"));
+    }
+    let mut recipients = Vec::with_capacity(req.recipients.len());
+    for (i, input) in req.recipients.iter().enumerate() {
+        // The email travels in memos, so every recipient needs a Sapling receiver
+        let address = match keys::decode_recipient(&config.network, &input.address) {
+            Ok(keys::RecipientAddress::Sapling(_))
+            | Ok(keys::RecipientAddress::Unified { sapling: Some(_), .. }) => Some(input.address.clone()),
+            Ok(_) => {
+                problems.add(format!("recipients[{}].address", i), ApiError::bad_request(
+                    "MEMO_REQUIRES_SHIELDED",
+                    format!("Recipient {} has no Sapling receiver, so it can't be sent an email", i),
+                ));
+                None
+            }
+            Err(e) => {
+                problems.add(format!("recipients[{}].address", i), ApiError::bad_request(
+                    "INVALID_ADDRESS",
+                    format!("Invalid address for recipient {}: {}", i, e),
+                ));
+                None
+            }
+        };
+        let value = if input.amount.is_empty() {
+            Some(config.memo_only_value)
+        } else {
+            let value = input.amount.parse::<u64>()
+                .ok()
+                .filter(|amount| *amount <= consensus::MAX_MONEY)
+                .ok_or_else(|| ApiError::bad_request("INVALID_AMOUNT", format!("Invalid amount for recipient {}: {}", i, input.amount)))
+                .and_then(|amount| match amount {
+                    0 => Err(ApiError::bad_request(
+                        "ZERO_AMOUNT",
+                        format!("Amount for recipient {} is 0. Omit it to send the email without funds.", i),
+                    )),
+                    amount => Ok(amount),
+                });
+            problems.check(format!("recipients[{}].amount", i), value)
+        };
+        if let (Some(address), Some(value)) = (address, value) {
+            recipients.push(email::EmailRecipient { address, value });
+        }
+    }
+    let attachments: Vec<email::Attachment> = req.attachments.iter()
+        .enumerate()
+        .filter_map(|(i, attachment)| {
+            let data = BASE64.decode(&attachment.data)
+                .map_err(|e| ApiError::bad_request("INVALID_ATTACHMENT", format!("Attachment {} is not valid base64: {}", i, e)));
+            problems.check(format!("attachments[{}].data", i), data).map(|data| email::Attachment {
+                name: attachment.name.clone(),
+                content_type: attachment.content_type.clone(),
+                data,
+            })
+        })
+        .collect();
+    problems.finish()?;
+    
+    let content = email::encode(&req.subject, &req.body, &attachments)
+        .map_err(|e| ApiError::bad_request("EMAIL_TOO_LARGE", e))?;
+    let per_transaction = outputs_per_transaction(&config, req.spends);
+    let pad_to = req.pad_outputs.unwrap_or(config.pad_outputs);
+    // Every 504 bytes is another output for each recipient, so a large file quietly
+    // turns into thousands of outputs; say what it would have cost
+    let attachment_bytes = attachments.iter().map(|a| a.data.len()).sum::<usize>();
+    if attachment_bytes > config.max_attachment_bytes {
+        let outputs = email::fragment_count(content.len()) * recipients.len();
+        let (transactions, fee) = email::cost(outputs, per_transaction, req.spends, pad_to);
+        println!(
+            "[ProofService] ❌ Attachments are {} bytes (limit {}): {} transactions, fee {} zatoshi",
+            attachment_bytes, config.max_attachment_bytes, transactions, fee
+        );
+        return Err(ApiError::bad_request(
+            "ATTACHMENT_TOO_LARGE",
+            format!(
+                "The attachments total {} bytes, but the service allows at most {}. \
+                 Sending them would take {} outputs in {} transactions, with fees of {} zatoshi.",
+                attachment_bytes, config.max_attachment_bytes, outputs, transactions, fee
+            ),
+        ).into());
+    }
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as u32)
+        .unwrap_or(0);
+    let fragments = email::fragments(&content, timestamp);
+    if fragments.len() > memo::MAX_FRAGMENTS {
+        return Err(ApiError::bad_request(
+            "EMAIL_TOO_LARGE",
+            format!(
+                "The email needs {} memo fragments, but a message has at most {}",
+                fragments.len(), memo::MAX_FRAGMENTS
+            ),
+        ).into());
+    }
+    
+    let transactions = email::plan(&recipients, &fragments, per_transaction, req.spends, pad_to);
+    let output_count = transactions.iter().map(|tx| tx.outputs.len()).sum::<usize>();
+    let padding = transactions.iter().map(|tx| tx.padding).sum::<usize>();
+    let fee = transactions.iter().map(|tx| tx.fee).sum();
+    let amount = recipients.iter().map(|r| r.value).sum();
+    // Change and padding outputs are proven like any other
+    let proving = estimate::estimate(
+        (req.spends * transactions.len()) as u64,
+        (output_count + padding + transactions.len()) as u64,
+    );
+    println!(
+        "[ProofService] Email plan: {} bytes in {} fragments, {} outputs in {} transactions, fee {} zatoshi",
+        content.len(), fragments.len(), output_count, transactions.len(), fee
+    );
+    
+    Ok(HttpResponse::Ok().json(EmailPlanResponse {
+        content_bytes: content.len(),
+        fragments: fragments.len(),
+        outputs_per_transaction: per_transaction,
+        transaction_count: transactions.len(),
+        transactions,
+        output_count,
+        amount,
+        fee,
+        proving,
+    }))
+}
+
+/// Compute the sighash of a transaction for external (e.g. hardware) signers
+#[utoipa::path(
+    post,
+    path = "/tx/sighash",
+    request_body = SighashRequest,
+    responses(
+        (status = 200, description = "Shielded sighash", body = SighashResponse),
+        (status = 400, description = "Invalid transaction", body = ApiError),
+    )
+)]
+async fn tx_sighash(
+    req: web::Json<SighashRequest>,
+    config: web::Data<Config>,
+) -> ActixResult<HttpResponse> {
+    let branch_id = consensus::branch_id_for_height(config.network, BlockHeight::from_u32(req.target_height));
+    println!(
+        "[ProofService] Computing sighash for {} byte transaction (branch {:?})",
+        req.raw_transaction.len(), branch_id
+    );
+    
+    let sighash = transaction::shielded_sighash(&req.raw_transaction, branch_id)
+        .map_err(|e| ApiError::bad_request("INVALID_TRANSACTION", e))?;
+    
+    Ok(HttpResponse::Ok().json(SighashResponse {
+        sighash: hex::encode(sighash),
+        consensus_branch_id: format!("{:08x}", u32::from(branch_id)),
+    }))
+}
+
+/// Verify a spend authorization signature against rk and the sighash it signs
+#[utoipa::path(
+    post,
+    path = "/proofs/verify",
+    request_body = VerifyRequest,
+    responses(
+        (status = 200, description = "Whether the signature is valid, and why not", body = VerifyResponse),
+        (status = 400, description = "A field isn't hex of the right length", body = ApiError),
+    )
+)]
+async fn verify_proof(req: web::Json<VerifyRequest>) -> ActixResult<HttpResponse> {
+    let mut problems = Validation::default();
+    let invalid = |e| ApiError::bad_request("INVALID_HEX", e);
+    let rk = problems.check("rk", notes::decode_hex_array::<32>("rk", &req.rk).map_err(invalid));
+    let sighash = problems.check("sighash", notes::decode_hex_array::<32>("sighash", &req.sighash).map_err(invalid));
+    let spend_auth_sig = problems.check(
+        "spend_auth_sig",
+        notes::decode_hex_array::<64>("spend_auth_sig", &req.spend_auth_sig).map_err(invalid),
+    );
+    problems.finish()?;
+    let (Some(rk), Some(sighash), Some(spend_auth_sig)) = (rk, sighash, spend_auth_sig) else {
+        unreachable!("validation passed but a field is missing");
+    };
+    
+    let result = proving::verify_spend_auth_sig(&rk, &sighash, &spend_auth_sig);
+    match &result {
+        Ok(()) => println!("[ProofService] ✅ spendAuthSig verified"),
+        Err(e) => println!("[ProofService] ❌ {}", e),
+    }
+    Ok(HttpResponse::Ok().json(VerifyResponse {
+        valid: result.is_ok(),
+        error: result.err(),
+    }))
+}
+
+/// Decode a transaction and check that its value balance is consistent, before broadcasting it
+#[utoipa::path(
+    post,
+    path = "/tx/decode",
+    request_body = DecodeTransactionRequest,
+    responses(
+        (status = 200, description = "Decoded transaction; `balanced` is false if the values don't add up", body = DecodedTransaction),
+        (status = 400, description = "Invalid transaction", body = ApiError),
+    )
+)]
+async fn tx_decode(
+    req: web::Json<DecodeTransactionRequest>,
+    config: web::Data<Config>,
+) -> ActixResult<HttpResponse> {
+    let branch_id = consensus::branch_id_for_height(config.network, BlockHeight::from_u32(req.target_height));
+    let decoded = transaction::decode(&req.raw_transaction, branch_id, &req.transparent_input_values, req.expected_fee)
+        .map_err(|e| ApiError::bad_request("INVALID_TRANSACTION", e))?;
+    
+    if decoded.balanced == Some(false) {
+        println!("[ProofService] ⚠️  Transaction {} is unbalanced: {:?}", decoded.txid, decoded.issues);
+    }
+    Ok(HttpResponse::Ok().json(decoded))
+}
+
+/// Check that a witness hashes up to an anchor, catching stale or corrupt witnesses before a build
+#[utoipa::path(
+    post,
+    path = "/witness/validate",
+    request_body = ValidateWitnessRequest,
+    responses(
+        (status = 200, description = "Whether the witness reaches the anchor", body = ValidateWitnessResponse),
+        (status = 400, description = "Malformed commitment, path or anchor", body = ApiError),
+    )
+)]
+async fn validate_witness(req: web::Json<ValidateWitnessRequest>) -> ActixResult<HttpResponse> {
+    let anchor = hex::decode(&req.anchor)
+        .map_err(|e| ApiError::bad_request("INVALID_ANCHOR", format!("anchor is not valid hex: {}", e)))?;
+    let root = notes::witness_root(&req.cmu, &req.auth_path, req.position)
+        .map_err(|e| ApiError::bad_request("INVALID_WITNESS", e))?
+        .to_bytes();
+    
+    let valid = anchor == root;
+    if !valid {
+        println!("[ProofService] ⚠️  Witness at position {} does not reach the given anchor", req.position);
+    }
+    Ok(HttpResponse::Ok().json(ValidateWitnessResponse {
+        valid,
+        root: hex::encode(root),
+    }))
+}
+
+/// The latest block lightwalletd knows of
+#[derive(Clone, Serialize, ToSchema)]
+struct ChainTipResponse {
+    /// Height of the tip; builds target the height above it
+    height: u64,
+    /// Block hash, hex in display order (as `anchor_hash` takes it)
+    hash: String,
+    /// Block time, seconds since the Unix epoch
+    time: u32,
+}
+
+/// Last tip fetched and when, shared by all requests
+static CHAIN_TIP: Mutex<Option<(Instant, ChainTipResponse)>> = Mutex::new(None);
+
+/// Current chain tip from lightwalletd, cached for ZMAIL_CHAIN_TIP_CACHE_SECS
+///
+/// GetLatestBlock only has the height, so the hash and time come from the
+/// tip's tree state.
+#[utoipa::path(
+    get,
+    path = "/chain/tip",
+    responses(
+        (status = 200, description = "Height, hash and time of the chain tip", body = ChainTipResponse),
+        (status = 400, description = "The service runs without ZMAIL_LIGHTWALLETD_ENDPOINTS", body = ApiError),
+        (status = 502, description = "lightwalletd unreachable", body = ApiError),
+    )
+)]
+async fn chain_tip(config: web::Data<Config>) -> ActixResult<HttpResponse> {
+    let cached = CHAIN_TIP.lock().unwrap_or_else(|e| e.into_inner()).clone();
+    if let Some((fetched, tip)) = cached {
+        if fetched.elapsed() < config.chain_tip_cache {
+            return Ok(HttpResponse::Ok().json(tip));
+        }
+    }
+    if config.lightwalletd_endpoints.is_empty() {
+        return Err(ApiError::bad_request(
+            "MISSING_LIGHTWALLETD_ENDPOINT",
+            "Run the service with ZMAIL_LIGHTWALLETD_ENDPOINTS to query the chain tip",
+        ).into());
+    }
+    
+    let state = async {
+        let mut client = LightwalletdClient::connect(&config.lightwalletd_endpoints).await?;
+        let height = client.latest_block().await?.height;
+        client.tree_state(height).await
+    }
+    .await
+    .map_err(|e| ApiError::bad_gateway("LIGHTWALLETD_ERROR", e))?;
+    let tip = ChainTipResponse {
+        height: state.height,
+        hash: state.hash,
+        time: state.time,
     };
+    *CHAIN_TIP.lock().unwrap_or_else(|e| e.into_inner()) = Some((Instant::now(), tip.clone()));
+    Ok(HttpResponse::Ok().json(tip))
+}
+
+/// Build witnesses for notes by resuming the Sapling tree from a checkpoint
+/// Only blocks after the checkpoint are streamed from lightwalletd, so the
+/// checkpoint must predate the notes being witnessed.
+#[utoipa::path(
+    post,
+    path = "/witness/build",
+    request_body = BuildWitnessesRequest,
+    responses(
+        (status = 200, description = "Witnesses at the tip, or anchor_depth blocks below the chain tip", body = BuildWitnessesResponse),
+        (status = 400, description = "Malformed commitments or checkpoint, or no checkpoint or lightwalletd endpoint", body = ApiError),
+        (status = 409, description = "The checkpoint's block was reorganized away; resume from an earlier checkpoint", body = ApiError),
+        (status = 502, description = "lightwalletd unreachable or returned bad blocks, or a range with missing heights", body = ApiError),
+    )
+)]
+async fn build_witnesses(
+    req: web::Json<BuildWitnessesRequest>,
+    config: web::Data<Config>,
+) -> ActixResult<HttpResponse> {
+    let wanted = witness::parse_commitments(&req.note_commitments)
+        .map_err(|e| ApiError::bad_request("INVALID_NOTE_COMMITMENT", e))?;
+    let checkpoint = match (&req.checkpoint, &config.tree_checkpoint) {
+        (Some(input), _) => Checkpoint::from_input(input),
+        (None, Some(path)) => Checkpoint::load(path),
+        (None, None) => {
+            return Err(ApiError::bad_request(
+                "MISSING_CHECKPOINT",
+                "Provide checkpoint or run the service with ZMAIL_TREE_CHECKPOINT",
+            ).into());
+        }
+    }
+    .map_err(|e| ApiError::bad_request("INVALID_CHECKPOINT", e))?;
+    let endpoints = lightwalletd_endpoints(req.lightwalletd_endpoint.as_deref(), &config);
+    if endpoints.is_empty() {
+        return Err(ApiError::bad_request(
+            "MISSING_LIGHTWALLETD_ENDPOINT",
+            "Provide lightwalletd_endpoint or run the service with ZMAIL_LIGHTWALLETD_ENDPOINTS",
+        ).into());
+    }
+    let mut client = LightwalletdClient::connect(&endpoints)
+        .await
+        .map_err(|e| ApiError::bad_gateway("LIGHTWALLETD_ERROR", e))?;
     
-    // For now, return a helpful error explaining what needs to be implemented
-    // The full implementation requires:
-    // 1. Getting compact blocks from lightwalletd
-    // 2. Building note commitment tree from blocks
-    // 3. Finding notes for the spending key
-    // 4. Using zcash_primitives::transaction::builder::Builder to build transaction
-    // 5. Serializing and returning the raw transaction
-    
-    let error_msg = format!(
-        "Transaction building is being implemented.\n\
-         \n\
-         This will use the same approach as Ywallet:\n\
-         1. Get compact blocks from lightwalletd\n\
-         2. Build note commitment tree from blocks\n\
-         3. Find notes for spending key\n\
-         4. Use librustzcash Builder API to build transaction\n\
-         5. Return raw transaction ready to broadcast\n\
-         \n\
-         Current request:\n\
-         - Spending key: {} chars\n\
-         - From address: {}\n\
-         - To address: {}\n\
-         - Amount: {} zatoshi\n\
-         - Memo: {} bytes\n\
-         \n\
-         Implementation in progress...",
-        req.spending_key.len(),
-        req.from_address,
-        req.to_address,
-        req.amount,
-        req.memo.len()
+    let tip = match req.tip {
+        Some(tip) => u64::from(tip),
+        None => {
+            let chain_tip = client.latest_block()
+                .await
+                .map_err(|e| ApiError::bad_gateway("LIGHTWALLETD_ERROR", e))?
+                .height;
+            chain_tip.saturating_sub(u64::from(req.anchor_depth.unwrap_or(config.anchor_depth)))
+        }
+    };
+    if tip <= checkpoint.height {
+        return Err(ApiError::bad_request(
+            "INVALID_TIP",
+            format!("tip {} must be above the checkpoint height {}", tip, checkpoint.height),
+        ).into());
+    }
+    println!(
+        "[ProofService] 🔍 Building {} witnesses from block {} to {}",
+        wanted.len(), checkpoint.height, tip
     );
     
-    Ok(HttpResponse::NotImplemented().json(BuildTransactionResponse {
-        raw_transaction: vec![],
-        txid: None,
-        error: Some(error_msg),
+    // Resuming from a block that is no longer on the chain would build witnesses for
+    // a tree that doesn't exist; the client has to rescan from before the fork
+    if checkpoint.hash.is_some() {
+        let state = client.tree_state(checkpoint.height)
+            .await
+            .map_err(|e| ApiError::bad_gateway("LIGHTWALLETD_ERROR", e))?;
+        if !checkpoint.matches(&state.hash) {
+            println!("[ProofService] ❌ Checkpoint block {} was reorganized away", checkpoint.height);
+            return Err(ApiError::new(
+                StatusCode::CONFLICT,
+                "CHECKPOINT_REORGED",
+                format!(
+                    "Block {} on the chain is now {}, not the checkpoint's block. \
+                     The chain was reorganized; resume from an earlier checkpoint.",
+                    checkpoint.height, state.hash
+                ),
+            ).into());
+        }
+    }
+    let (tree, witnesses) = witness::build(&mut client, checkpoint, tip, wanted)
+        .await
+        .map_err(|e| ApiError::bad_gateway("LIGHTWALLETD_ERROR", e))?;
+    
+    let missing = witnesses.iter().filter(|w| w.is_none()).count();
+    if missing > 0 {
+        println!("[ProofService] ⚠️  {} note commitments not found after the checkpoint", missing);
+    }
+    Ok(HttpResponse::Ok().json(BuildWitnessesResponse {
+        height: tree.height,
+        anchor: hex::encode(tree.anchor().to_bytes()),
+        sapling_tree: tree.to_hex(),
+        hash: tree.hash,
+        witnesses,
+    }))
+}
+
+/// Derive the default address and full viewing key for an account from a mnemonic or seed
+/// The spending key is deliberately not returned; nothing is logged or stored.
+#[utoipa::path(
+    post,
+    path = "/keys/derive-address",
+    request_body = DeriveAddressRequest,
+    responses(
+        (status = 200, description = "Default address and full viewing key", body = DeriveAddressResponse),
+        (status = 400, description = "Invalid mnemonic or seed", body = ApiError),
+    )
+)]
+async fn derive_address(
+    req: web::Json<DeriveAddressRequest>,
+    config: web::Data<Config>,
+) -> ActixResult<HttpResponse> {
+    let seed = match (&req.mnemonic, &req.seed) {
+        (Some(phrase), _) => keys::seed_from_mnemonic(phrase, &req.passphrase)
+            .map(|seed| seed.to_vec())
+            .map_err(|e| ApiError::bad_request("INVALID_MNEMONIC", e))?,
+        (None, Some(seed)) => hex::decode(seed)
+            .map_err(|e| ApiError::bad_request("INVALID_SEED", format!("seed is not valid hex: {}", e)))?,
+        (None, None) => {
+            return Err(ApiError::bad_request("MISSING_SEED", "Provide either mnemonic or seed").into());
+        }
+    };
+    
+    let extsk = keys::account_spending_key(&config.network, &seed, req.account)
+        .map_err(|e| ApiError::bad_request("INVALID_SEED", e))?;
+    #[allow(deprecated)]
+    let extfvk = extsk.to_extended_full_viewing_key();
+    let (_, address) = extsk.default_address();
+    
+    Ok(HttpResponse::Ok().json(DeriveAddressResponse {
+        address: keys::encode_payment_address(&config.network, &address),
+        full_viewing_key: keys::encode_extended_full_viewing_key(&config.network, &extfvk),
     }))
 }
 
+/// Export the extended full viewing key of a spending key, for watch-only setups
+/// Nothing is logged or stored.
+#[utoipa::path(
+    post,
+    path = "/keys/export-fvk",
+    request_body = ExportFvkRequest,
+    responses(
+        (status = 200, description = "Extended full viewing key", body = ExportFvkResponse),
+        (status = 400, description = "Invalid spending key, or one for the other network", body = ApiError),
+    )
+)]
+async fn export_fvk(
+    req: web::Json<ExportFvkRequest>,
+    config: web::Data<Config>,
+) -> ActixResult<HttpResponse> {
+    let extsk = decode_spending_key(config.network, &req.spending_key)?;
+    #[allow(deprecated)]
+    let extfvk = extsk.to_extended_full_viewing_key();
+    
+    Ok(HttpResponse::Ok().json(ExportFvkResponse {
+        full_viewing_key: keys::encode_extended_full_viewing_key(&config.network, &extfvk),
+    }))
+}
+
+/// Decrypt outputs with the sender's outgoing viewing key, for a "Sent" folder
+#[utoipa::path(
+    post,
+    path = "/messages/sent",
+    request_body = SentMailRequest,
+    responses(
+        (status = 200, description = "The note and memo of every output this ovk sent", body = SentMailResponse),
+        (status = 400, description = "Malformed ovk or output fields; `errors` lists each", body = ApiError),
+    )
+)]
+async fn decrypt_sent(
+    req: web::Json<SentMailRequest>,
+    config: web::Data<Config>,
+) -> ActixResult<HttpResponse> {
+    let mut problems = Validation::default();
+    let invalid = |e| ApiError::bad_request("INVALID_OUTPUT", e);
+    let ovk = problems.check(
+        "ovk",
+        notes::decode_hex_array("ovk", &req.ovk).map(OutgoingViewingKey).map_err(|e| ApiError::bad_request("INVALID_OVK", e)),
+    );
+    let mut outputs = Vec::with_capacity(req.outputs.len());
+    for (i, output) in req.outputs.iter().enumerate() {
+        let field = |name: &str| format!("outputs[{}].{}", i, name);
+        let cv = problems.check(
+            field("cv"),
+            notes::decode_hex_array("cv", &output.cv).map_err(invalid).and_then(|cv| {
+                Option::from(ValueCommitment::from_bytes_not_small_order(&cv))
+                    .ok_or_else(|| invalid("cv is not a valid value commitment".to_string()))
+            }),
+        );
+        let cmu = problems.check(field("cmu"), notes::decode_hex_array("cmu", &output.cmu).map_err(invalid));
+        let epk = problems.check(field("epk"), notes::decode_hex_array("epk", &output.epk).map_err(invalid));
+        let enc_ciphertext = problems.check(
+            field("enc_ciphertext"),
+            notes::decode_hex_array("enc_ciphertext", &output.enc_ciphertext).map_err(invalid),
+        );
+        let out_ciphertext = problems.check(
+            field("out_ciphertext"),
+            notes::decode_hex_array("out_ciphertext", &output.out_ciphertext).map_err(invalid),
+        );
+        if let (Some(cv), Some(cmu), Some(epk), Some(enc_ciphertext), Some(out_ciphertext)) =
+            (cv, cmu, epk, enc_ciphertext, out_ciphertext)
+        {
+            outputs.push((cv, cmu, proving::EncryptedNote { epk, enc_ciphertext, out_ciphertext }));
+        }
+    }
+    problems.finish()?;
+    let Some(ovk) = ovk else {
+        unreachable!("validation passed but a field is missing");
+    };
+    
+    // An output another key sent is expected in a transaction's list, not an error
+    let outputs: Vec<SentOutput> = outputs
+        .iter()
+        .map(|(cv, cmu, encrypted)| match proving::recover_output(&ovk, cv, *cmu, encrypted) {
+            Some((note, address, memo)) => SentOutput {
+                decrypted: true,
+                address: Some(keys::encode_payment_address(&config.network, &address)),
+                value: Some(note.value().inner()),
+                memo: Some(hex::encode(memo)),
+            },
+            None => SentOutput { decrypted: false, address: None, value: None, memo: None },
+        })
+        .collect();
+    println!(
+        "[ProofService] Decrypted {} of {} outputs with the sender's ovk",
+        outputs.iter().filter(|o| o.decrypted).count(), outputs.len()
+    );
+    Ok(HttpResponse::Ok().json(SentMailResponse { outputs }))
+}
+
+/// Export the outgoing viewing keys of an account, so a sender can decrypt their own sent mail
+/// Builds here encrypt every out_ciphertext, change included, to `ovk`; other wallets
+/// use `internal_ovk` for change. Nothing is logged or stored.
+#[utoipa::path(
+    post,
+    path = "/keys/export-ovk",
+    request_body = ExportOvkRequest,
+    responses(
+        (status = 200, description = "External and internal outgoing viewing keys", body = ExportOvkResponse),
+        (status = 400, description = "Neither or both keys given, or an invalid key", body = ApiError),
+    )
+)]
+async fn export_ovk(
+    req: web::Json<ExportOvkRequest>,
+    config: web::Data<Config>,
+) -> ActixResult<HttpResponse> {
+    let dfvk = match (&req.spending_key, &req.full_viewing_key) {
+        (Some(spending_key), None) => decode_spending_key(config.network, spending_key)?.to_diversifiable_full_viewing_key(),
+        (None, Some(fvk)) => keys::decode_extended_full_viewing_key(&config.network, fvk)
+            .map_err(|e| ApiError::bad_request("INVALID_VIEWING_KEY", format!("Invalid full viewing key: {}", e)))?
+            .to_diversifiable_full_viewing_key(),
+        _ => {
+            return Err(ApiError::bad_request(
+                "MISSING_KEY",
+                "Provide either spending_key or full_viewing_key",
+            ).into());
+        }
+    };
+    
+    Ok(HttpResponse::Ok().json(ExportOvkResponse {
+        ovk: hex::encode(dfvk.to_ovk(zcash_primitives::zip32::Scope::External).0),
+        internal_ovk: hex::encode(dfvk.to_ovk(zcash_primitives::zip32::Scope::Internal).0),
+    }))
+}
+
+/// Decrypt a message sent with encrypt_memo, given the memos of all its fragments
+#[utoipa::path(
+    post,
+    path = "/messages/decrypt",
+    request_body = DecryptMessageRequest,
+    responses(
+        (status = 200, description = "Decrypted content, or only the ids of a message in seen_ids", body = DecryptMessageResponse),
+        (status = 400, description = "Missing fragments, wrong key, or provenance not matching the fragments", body = ApiError),
+    )
+)]
+async fn decrypt_message(
+    req: web::Json<DecryptMessageRequest>,
+    config: web::Data<Config>,
+) -> ActixResult<HttpResponse> {
+    let req = req.into_inner();
+    let (fragments, provenance): (Vec<Vec<u8>>, Vec<NoteProvenance>) = if req.provenance.is_empty() {
+        (req.fragments, Vec::new())
+    } else {
+        if req.provenance.len() != req.fragments.len() {
+            return Err(ApiError::bad_request(
+                "INVALID_PROVENANCE",
+                format!("Got {} fragments but provenance for {}", req.fragments.len(), req.provenance.len()),
+            ).into());
+        }
+        // A note found again by a rescan is the same fragment, not a second copy of it
+        let mut seen = HashSet::new();
+        req.fragments.into_iter()
+            .zip(req.provenance)
+            .filter(|(_, source)| seen.insert((source.txid.to_lowercase(), source.output_index)))
+            .unzip()
+    };
+    let parsed = memo::parse_encrypted(&fragments)
+        .map_err(|e| ApiError::bad_request("INVALID_FRAGMENTS", e))?;
+    
+    // List sources in sequence order; parse_encrypted checked every fragment's header
+    let mut sources: Vec<_> = fragments.iter().map(|fragment| memo::sequence(fragment)).zip(provenance).collect();
+    sources.sort_by_key(|(sequence, _)| *sequence);
+    let provenance: Vec<NoteProvenance> = sources.into_iter().map(|(_, source)| source).collect();
+    let first = provenance.first();
+    let message_id = attachment::message_id(&parsed.key);
+    let note_id = first.map(|source| format!("{}:{}", source.txid.to_lowercase(), source.output_index));
+    let height = first.map(|source| source.height);
+    
+    let seen = |id: &str| req.seen_ids.iter().any(|seen| seen.eq_ignore_ascii_case(id));
+    if seen(&message_id) || note_id.as_deref().is_some_and(seen) {
+        return Ok(HttpResponse::Ok().json(DecryptMessageResponse {
+            content: Vec::new(),
+            reply_to: None,
+            message_id,
+            note_id,
+            duplicate: true,
+            height,
+            fragments: provenance,
+        }));
+    }
+    
+    let content = attachment::decrypt(&parsed.key, &parsed.ciphertext)
+        .map_err(|e| ApiError::bad_request("DECRYPTION_FAILED", e))?;
+    let reply_to = match parsed.reply_to {
+        Some(bytes) => {
+            let address = PaymentAddress::from_bytes(&bytes).ok_or_else(|| {
+                ApiError::bad_request("INVALID_REPLY_TO", "The message's reply-to is not a valid Sapling address")
+            })?;
+            Some(keys::encode_payment_address(&config.network, &address))
+        }
+        None => None,
+    };
+    
+    Ok(HttpResponse::Ok().json(DecryptMessageResponse {
+        content,
+        reply_to,
+        message_id,
+        note_id,
+        duplicate: false,
+        height,
+        fragments: provenance,
+    }))
+}
+
+/// Tell the user what kind of key they pasted, without echoing it back
+#[utoipa::path(
+    post,
+    path = "/keys/validate",
+    request_body = ValidateKeyRequest,
+    responses(
+        (status = 200, description = "What kind of key was given", body = keys::KeyClassification),
+    )
+)]
+async fn validate_key(req: web::Json<ValidateKeyRequest>) -> ActixResult<HttpResponse> {
+    let classification = keys::classify_key(&req.key);
+    println!("[ProofService] Classified key as {:?}", classification.kind);
+    Ok(HttpResponse::Ok().json(classification))
+}
+
+/// Describe what this build supports, so clients can adapt their UI
+#[utoipa::path(
+    get,
+    path = "/capabilities",
+    responses(
+        (status = 200, description = "Supported features", body = CapabilitiesResponse),
+    )
+)]
+async fn capabilities(config: web::Data<Config>) -> ActixResult<HttpResponse> {
+    Ok(HttpResponse::Ok().json(CapabilitiesResponse {
+        version: env!("CARGO_PKG_VERSION"),
+        network: consensus::network_name(config.network),
+        supported_networks: vec!["main", "test"],
+        proof_types: vec!["spend", "output"],
+        proving: !config.no_params,
+        orchard: false,
+        transparent: true,
+        multiple_recipients: true,
+        max_outputs: config.max_outputs,
+        memo_size: memo::MEMO_SIZE,
+        response_formats: vec!["application/json", msgpack::CONTENT_TYPE],
+        proving_calibration: estimate::calibration(),
+    }))
+}
+
+/// Report the memo encoding limits, so the frontend's composition limits match the server
+#[utoipa::path(
+    get,
+    path = "/limits",
+    responses(
+        (status = 200, description = "Memo and message size limits", body = LimitsResponse),
+    )
+)]
+async fn limits(config: web::Data<Config>) -> ActixResult<HttpResponse> {
+    // Every fragment is its own output, in a transaction spending at least one note
+    let max_fragments = outputs_per_transaction(&config, 1).min(memo::MAX_FRAGMENTS);
+    
+    Ok(HttpResponse::Ok().json(LimitsResponse {
+        memo_size: memo::MEMO_SIZE,
+        header_size: memo::HEADER_SIZE,
+        payload_size: memo::PAYLOAD_SIZE,
+        max_outputs: config.max_outputs,
+        max_fragments,
+        max_message_size: memo::max_message_size(max_fragments),
+        memo_only_value: config.memo_only_value,
+        max_attachment_bytes: config.max_attachment_bytes,
+        max_batch_items: config.max_batch_items,
+        pad_outputs: config.pad_outputs,
+    }))
+}
+
+/// Liveness check
+#[utoipa::path(
+    get,
+    path = "/health",
+    responses(
+        (status = 200, description = "The service is up", body = String),
+    )
+)]
+async fn health() -> ActixResult<HttpResponse> {
+    Ok(HttpResponse::Ok().json("OK"))
+}
+
+/// Which proving parameters have loaded and verified
+#[derive(Serialize, ToSchema)]
+struct ReadinessResponse {
+    /// Output proofs can be generated
+    ready: bool,
+    /// The output parameters have loaded
+    output: bool,
+    /// The spend parameters have loaded, so spends and full transactions can be proved too
+    spend: bool,
+}
+
+/// Readiness check: 200 once output proofs can be generated, 503 until then
+///
+/// Instances started with ZMAIL_NO_PARAMS never load parameters and are ready immediately.
+#[utoipa::path(
+    get,
+    path = "/health/ready",
+    responses(
+        (status = 200, description = "The service can generate proofs", body = ReadinessResponse),
+        (status = 503, description = "The proving parameters haven't loaded yet", body = ReadinessResponse),
+    )
+)]
+async fn health_ready(config: web::Data<Config>) -> ActixResult<HttpResponse> {
+    let output = params_ready(ParamSet::OutputOnly);
+    let spend = params_ready(ParamSet::Full);
+    let ready = config.no_params || output;
+    let response = ReadinessResponse { ready, output, spend };
+    Ok(if ready {
+        HttpResponse::Ok().json(response)
+    } else {
+        HttpResponse::ServiceUnavailable().json(response)
+    })
+}
+
+/// Report proving backpressure, so load balancers and dashboards see saturation
+/// before requests start getting PROVER_BUSY
+#[utoipa::path(
+    get,
+    path = "/health/stats",
+    responses(
+        (status = 200, description = "Proofs in progress and queued, with the configured limits", body = ProofLoad),
+    )
+)]
+async fn health_stats(limiter: web::Data<ProofLimiter>) -> ActixResult<HttpResponse> {
+    Ok(HttpResponse::Ok().json(limiter.load()))
+}
+
+/// Prometheus metrics: proving load and the proof times measured at startup
+#[utoipa::path(
+    get,
+    path = "/metrics",
+    responses(
+        (status = 200, description = "Metrics in the Prometheus text format", body = String, content_type = "text/plain"),
+    )
+)]
+async fn metrics(limiter: web::Data<ProofLimiter>) -> ActixResult<HttpResponse> {
+    let load = limiter.load();
+    let calibration = estimate::calibration();
+    
+    let mut body = String::new();
+    let mut gauge = |name: &str, help: &str, value: f64| {
+        body += &format!("# HELP {} {}\n# TYPE {} gauge\n{} {}\n", name, help, name, name, value);
+    };
+    gauge("zmail_proofs_in_progress", "Proofs being generated", load.busy as f64);
+    gauge("zmail_proofs_max_concurrent", "Proofs that may be generated at once", load.max_concurrent as f64);
+    gauge("zmail_proofs_queued", "Requests waiting for a proving slot", load.queued as f64);
+    gauge("zmail_proofs_max_queued", "Requests that may wait for a proving slot", load.max_queued as f64);
+    if let Some(us) = calibration.output_proof_us {
+        gauge("zmail_output_proof_seconds", "Time of one output proof, measured at startup", us as f64 / 1e6);
+    }
+    if let Some(us) = calibration.spend_proof_us {
+        gauge("zmail_spend_proof_seconds", "Time of one spend proof, measured at startup", us as f64 / 1e6);
+    }
+    
+    Ok(HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(body))
+}
+
+/// Progress of parameter downloads started by ZMAIL_AUTO_DOWNLOAD (only routed when ZMAIL_DEBUG=1)
+async fn debug_download_status() -> ActixResult<HttpResponse> {
+    Ok(HttpResponse::Ok().json(download::status()))
+}
+
+/// Report where the parameters are and whether they check out (only routed when ZMAIL_DEBUG=1)
+async fn debug_params() -> ActixResult<HttpResponse> {
+    let dir = find_params_dir(ParamSet::Full).or_else(|| find_params_dir(ParamSet::OutputOnly));
+    // Hashes ~50MB of files
+    let report = web::block(move || params::inspect(dir.as_deref()))
+        .await
+        .map_err(|e| ApiError::internal("PARAMS_CHECK_FAILED", e.to_string()))?;
+    Ok(HttpResponse::Ok().json(report))
+}
+
+/// Report the consensus branch active at a height (only routed when ZMAIL_DEBUG=1)
+async fn consensus_branch_id(
+    query: web::Query<BranchIdQuery>,
+    config: web::Data<Config>,
+) -> ActixResult<HttpResponse> {
+    let branch_id = consensus::branch_id_for_height(config.network, BlockHeight::from_u32(query.height));
+    
+    Ok(HttpResponse::Ok().json(BranchIdResponse {
+        network: consensus::network_name(config.network),
+        height: query.height,
+        branch: format!("{:?}", branch_id),
+        consensus_branch_id: format!("{:08x}", u32::from(branch_id)),
+    }))
+}
+
+/// Benchmark output proving on this host (only routed when ZMAIL_DEBUG=1)
+/// Times parameter loading separately from N proofs of a fixed output note,
+/// so operators can tell slow param loading apart from slow proving.
+async fn debug_bench(req: web::Json<BenchRequest>, config: web::Data<Config>) -> ActixResult<HttpResponse> {
+    if config.no_params {
+        return Ok(proving_disabled_response());
+    }
+    let iterations = req.iterations.clamp(1, MAX_BENCH_ITERATIONS);
+    println!("[ProofService] Running output proof benchmark ({} iterations)", iterations);
+    
+    let result = web::block(move || -> Result<BenchResponse, String> {
+        let load_start = Instant::now();
+        let prover = get_prover(ParamSet::OutputOnly)?;
+        let param_load_ms = load_start.elapsed().as_millis() as u64;
+        
+        let mut rng = OsRng;
+        let mut timings: Vec<u64> = (0..iterations)
+            .map(|_| {
+                let note = proving::bench_note(&mut rng);
+                let start = Instant::now();
+                let rcv = ValueCommitTrapdoor::random(&mut rng);
+                proving::prove_output(&prover.output, &note, rcv, &mut rng);
+                start.elapsed().as_millis() as u64
+            })
+            .collect();
+        timings.sort_unstable();
+        
+        Ok(BenchResponse {
+            iterations,
+            param_load_ms,
+            min_ms: timings[0],
+            median_ms: timings[iterations / 2],
+            max_ms: timings[iterations - 1],
+        })
+    })
+    .await;
+    
+    match result {
+        Ok(Ok(bench)) => {
+            println!(
+                "[ProofService] ✅ Benchmark done: load={}ms min={}ms median={}ms max={}ms",
+                bench.param_load_ms, bench.min_ms, bench.median_ms, bench.max_ms
+            );
+            Ok(HttpResponse::Ok().json(bench))
+        }
+        Ok(Err(e)) => Err(ApiError::internal("PROVER_UNAVAILABLE", e).into()),
+        Err(e) => Err(ApiError::internal("BENCH_FAILED", e.to_string()).into()),
+    }
+}
+
+/// Every API route, under `base_path` (empty for the root)
+fn routes(base_path: &str, config: &Config) -> Scope {
+    web::scope(base_path)
+        .route("/proofs/generate", web::post().to(generate_proof))
+        .route("/proofs/generate-batch", web::post().to(generate_proof_batch))
+        .route("/proofs/build-transaction", web::post().to(build_transaction))
+        .route("/proofs/estimate-time", web::post().to(estimate_time))
+        .route("/proofs/verify", web::post().to(verify_proof))
+        .service(
+            // Leave room for base64 attachments somewhat over the limit, so they get
+            // ATTACHMENT_TOO_LARGE with their cost rather than PAYLOAD_TOO_LARGE
+            web::resource("/email/plan")
+                .app_data(
+                    web::JsonConfig::default()
+                        .limit(config.max_attachment_bytes.saturating_mul(2).saturating_add(64 * 1024))
+                        .error_handler(error::json_error_handler),
+                )
+                .route(web::post().to(plan_email)),
+        )
+        .route("/tx/send", web::post().to(send_transaction))
+        .route("/tx/sighash", web::post().to(tx_sighash))
+        .route("/tx/decode", web::post().to(tx_decode))
+        .route("/witness/validate", web::post().to(validate_witness))
+        .route("/witness/build", web::post().to(build_witnesses))
+        .route("/chain/tip", web::get().to(chain_tip))
+        .route("/keys/derive-address", web::post().to(derive_address))
+        .route("/keys/validate", web::post().to(validate_key))
+        .route("/keys/export-fvk", web::post().to(export_fvk))
+        .route("/keys/export-ovk", web::post().to(export_ovk))
+        .route("/messages/decrypt", web::post().to(decrypt_message))
+        .route("/messages/sent", web::post().to(decrypt_sent))
+        .route("/capabilities", web::get().to(capabilities))
+        .route("/limits", web::get().to(limits))
+        .route("/health", web::get().to(health))
+        .route("/health/ready", web::get().to(health_ready))
+        .route("/health/stats", web::get().to(health_stats))
+        .route("/metrics", web::get().to(metrics))
+        .configure(|cfg| {
+            // Debug endpoints are only routed when explicitly enabled
+            if config.debug {
+                cfg.route("/debug/bench", web::post().to(debug_bench))
+                    .route("/debug/download-status", web::get().to(debug_download_status))
+                    .route("/debug/params", web::get().to(debug_params))
+                    .route("/consensus/branch-id", web::get().to(consensus_branch_id));
+            }
+        })
+}
+
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
+    // `send` builds and broadcasts a single transaction instead of starting the server
+    let args: Vec<String> = env::args().collect();
+    if args.get(1).map(String::as_str) == Some("send") {
+        std::process::exit(cli::send(&args[2..]).await);
+    }
+    
     println!("========================================");
     println!("  Zcash Proof Generation Service");
     println!("========================================");
     println!("");
     println!("Starting server on http://127.0.0.1:8080");
     println!("Endpoint: POST /proofs/generate");
+    println!("API docs: http://127.0.0.1:8080/docs/ (OpenAPI at /openapi.json)");
     println!("");
     
-    HttpServer::new(|| {
+    let config = web::Data::new(Config::from_env());
+    println!("[ProofService] Max outputs per transaction: {}", config.max_outputs);
+    println!("[ProofService] Max attachment size per email: {} bytes", config.max_attachment_bytes);
+    println!("[ProofService] Max proof requests per batch: {}", config.max_batch_items);
+    if config.pad_outputs > config.max_outputs + 1 {
+        println!(
+            "[ProofService] ⚠️  ZMAIL_PAD_OUTPUTS={} is above ZMAIL_MAX_OUTPUTS plus change; builds that don't set pad_outputs will be rejected",
+            config.pad_outputs
+        );
+    } else if config.pad_outputs > 0 {
+        println!("[ProofService] Padding builds to {} Sapling outputs", config.pad_outputs);
+    }
+    if config.low_memory {
+        println!("[ProofService] Low-memory mode: spend parameters are reloaded for each spend request");
+    }
+    if !config.lightwalletd_endpoints.is_empty() {
+        println!("[ProofService] lightwalletd endpoints (in failover order): {}", config.lightwalletd_endpoints.join(", "));
+    }
+    if config.debug {
+        println!("[ProofService] ⚠️  ZMAIL_DEBUG is set: /debug endpoints are enabled");
+    }
+    if config.single_wallet {
+        println!("[ProofService] Single-wallet mode: notes spent by pending builds are left out of later builds");
+    }
+    if config.no_params {
+        println!("[ProofService] ZMAIL_NO_PARAMS is set: proving parameters are never loaded, proving endpoints are disabled");
+    }
+    if config.local_keys {
+        println!("[ProofService] ⚠️  ZMAIL_LOCAL_KEYS is set: requests may read key files from this machine");
+    }
+    #[cfg(feature = "deterministic")]
+    if let Some(seed) = config.rng_seed {
+        println!("[ProofService] ⚠️  ZMAIL_RNG_SEED={} is set: transactions are built from a fixed seed and are NOT private", seed);
+    }
+    
+    // Fetch missing parameters in the background; the server starts regardless
+    // and proof requests fail with the usual "not downloaded" error until it's done
+    if config.auto_download && !config.no_params && find_params_dir(ParamSet::Full).is_none() {
+        match dirs::home_dir() {
+            Some(home) => {
+                let dir = home.join(".zcash-params");
+                println!("[ProofService] Downloading missing parameters to {:?}", dir);
+                actix_web::rt::spawn(download::download_missing(dir));
+            }
+            None => println!("[ProofService] ⚠️  No home directory, cannot auto-download parameters"),
+        }
+    }
+    
+    // Load (and calibrate with) whatever parameters are present, then keep checking
+    // for the rest, so parameters that finish downloading later need no restart
+    if !config.no_params {
+        println!("[ProofService] Checking for proving parameters every {:?} until loaded", config.params_watch_interval);
+        actix_web::rt::spawn(watch_params(config.low_memory, config.params_watch_interval));
+    }
+    
+    // Behind a proxy on a subpath, every route moves under it; the OpenAPI document
+    // names the prefix as its server so "Try it out" in the docs reaches the API
+    let base_path = config.base_path.clone();
+    let mut api_doc = openapi::ApiDoc::openapi();
+    if !base_path.is_empty() {
+        println!("[ProofService] Serving every route under {}", base_path);
+        api_doc.servers = Some(vec![utoipa::openapi::Server::new(base_path.clone())]);
+    }
+    
+    // Slow or idle clients would otherwise hold connections open indefinitely.
+    // Timeouts only cover receiving the request, not the (slow) proving that follows;
+    // actix answers a request that doesn't arrive in time with 408.
+    let client_request_timeout = config.client_request_timeout;
+    let client_disconnect_timeout = config.client_disconnect_timeout;
+    let max_connections = config.max_connections;
+    let keep_alive = if config.keep_alive.is_zero() { KeepAlive::Disabled } else { KeepAlive::Timeout(config.keep_alive) };
+    println!(
+        "[ProofService] Client request timeout {:?}, disconnect timeout {:?}, keep-alive {:?}, max {} connections per worker",
+        client_request_timeout, client_disconnect_timeout, config.keep_alive, max_connections
+    );
+    
+    let tls = match (&config.tls_cert, &config.tls_key) {
+        (Some(cert), Some(key)) => {
+            let tls = tls::load_config(cert, key).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+            println!("[ProofService] ✅ TLS enabled: serving https://127.0.0.1:8080, HTTP/2 negotiated via ALPN");
+            Some(tls)
+        }
+        (None, None) => None,
+        _ => {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "ZMAIL_TLS_CERT and ZMAIL_TLS_KEY must be set together",
+            ))
+        }
+    };
+    
+    // Shared by all workers, so the proof cap is global rather than per worker
+    let limiter = web::Data::new(ProofLimiter::new(config.max_concurrent_proofs, config.max_queued_proofs));
+    let pending_spends = web::Data::new(PendingSpends::load(config.pending_spends_file.clone()));
+    println!(
+        "[ProofService] Max {} concurrent proofs, {} queued",
+        config.max_concurrent_proofs, config.max_queued_proofs
+    );
+    
+    let server = HttpServer::new(move || {
         // Enable CORS for browser requests
         let cors = Cors::default()
             .allow_any_origin()
@@ -489,13 +3605,111 @@ async fn main() -> std::io::Result<()> {
             .max_age(3600);
         
         App::new()
+            .app_data(config.clone())
+            .app_data(limiter.clone())
+            .app_data(pending_spends.clone())
+            .app_data(web::JsonConfig::default().error_handler(error::json_error_handler))
+            // Registered before CORS so panic responses still get CORS headers
+            .wrap_fn(|req, srv| error::catch_panics(req, srv))
+            // Outside catch_panics so panic responses are re-encoded too
+            .wrap_fn(|req, srv| msgpack::negotiate(req, srv))
             .wrap(cors)
-            .route("/proofs/generate", web::post().to(generate_proof))
-            .route("/proofs/build-transaction", web::post().to(build_transaction))
-            .route("/health", web::get().to(|| async { HttpResponse::Ok().json("OK") }))
+            // Outside the scope: the UI is told where the document is, so it needs the full path
+            .service(
+                SwaggerUi::new(format!("{}/docs/{{_:.*}}", base_path))
+                    .url(format!("{}/openapi.json", base_path), api_doc.clone()),
+            )
+            .service(routes(&base_path, &config))
     })
-    .bind("127.0.0.1:8080")?
-    .run()
-    .await
+    .client_request_timeout(client_request_timeout)
+    .client_disconnect_timeout(client_disconnect_timeout)
+    .keep_alive(keep_alive)
+    .max_connections(max_connections);
+    
+    let server = match tls {
+        Some(tls) => server.bind_rustls_021("127.0.0.1:8080", tls)?,
+        None => server.bind("127.0.0.1:8080")?,
+    };
+    server.run().await
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::{call_and_read_body_json, call_service, init_service, TestRequest};
+    use serde_json::json;
+
+    const MAX_MONEY: u64 = consensus::MAX_MONEY;
+
+    #[test]
+    fn amount_param_accepts_the_note_value_range() {
+        assert_eq!(amount_param(&json!({ "amount": 0 })), Ok(0));
+        assert_eq!(amount_param(&json!({ "amount": MAX_MONEY })), Ok(MAX_MONEY));
+        assert_eq!(amount_param(&json!({ "amount": MAX_MONEY.to_string() })), Ok(MAX_MONEY));
+    }
+
+    #[test]
+    fn amount_param_rejects_values_outside_it() {
+        assert!(amount_param(&json!({ "amount": -1 })).is_err());
+        assert!(amount_param(&json!({ "amount": "-1" })).is_err());
+        assert!(amount_param(&json!({ "amount": MAX_MONEY + 1 })).is_err());
+        assert!(amount_param(&json!({ "amount": u64::MAX })).is_err());
+        assert!(amount_param(&json!({ "amount": 1.5 })).is_err());
+        assert!(amount_param(&json!({})).is_err());
+    }
+
+    #[test]
+    fn output_amount_param_needs_memo_only_for_zero() {
+        assert_eq!(output_amount_param(&json!({ "amount": MAX_MONEY }), 1), Ok(MAX_MONEY));
+        assert!(output_amount_param(&json!({ "amount": 0 }), 1).is_err());
+        assert!(output_amount_param(&json!({ "amount": -1 }), 1).is_err());
+        assert!(output_amount_param(&json!({ "amount": MAX_MONEY + 1 }), 1).is_err());
+    }
+
+    #[test]
+    fn memo_only_outputs_carry_the_memo_only_value() {
+        assert_eq!(output_amount_param(&json!({ "memoOnly": true, "memo": "hi" }), 1), Ok(1));
+        assert_eq!(output_amount_param(&json!({ "memoOnly": true, "memo": "hi", "amount": 1 }), 1), Ok(1));
+        assert!(output_amount_param(&json!({ "memoOnly": true, "memo": "hi", "amount": 5 }), 1).is_err());
+        assert!(output_amount_param(&json!({ "memoOnly": true }), 1).is_err());
+    }
+
+    #[actix_web::test]
+    async fn routes_without_params_still_answer_metadata_requests() {
+        let config = web::Data::new(Config { network: Network::TestNetwork, no_params: true, ..Config::from_env() });
+        let app = init_service(
+            App::new()
+                .app_data(config.clone())
+                .app_data(web::Data::new(ProofLimiter::new(1, 1)))
+                .service(routes("", &config)),
+        )
+        .await;
+        
+        let mnemonic = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about";
+        let request = TestRequest::post()
+            .uri("/keys/derive-address")
+            .set_json(json!({ "mnemonic": mnemonic }))
+            .to_request();
+        let derived: serde_json::Value = call_and_read_body_json(&app, request).await;
+        let address = derived["address"].as_str().unwrap().to_string();
+        assert!(address.starts_with("ztestsapling1"), "{}", address);
+        
+        let limits = call_service(&app, TestRequest::get().uri("/limits").to_request()).await;
+        assert_eq!(limits.status(), StatusCode::OK);
+        
+        let request = TestRequest::get().uri("/capabilities").to_request();
+        let capabilities: serde_json::Value = call_and_read_body_json(&app, request).await;
+        assert_eq!(capabilities["proving"], json!(false));
+        
+        // Output commitments need no parameters; the proof itself does
+        let output = |proof: bool| {
+            TestRequest::post()
+                .uri("/proofs/generate")
+                .set_json(json!({ "type": "output", "params": { "toAddress": address, "amount": 1000, "proof": proof } }))
+                .to_request()
+        };
+        assert_eq!(call_service(&app, output(false)).await.status(), StatusCode::OK);
+        assert_eq!(call_service(&app, output(true)).await.status(), StatusCode::SERVICE_UNAVAILABLE);
+    }
+}
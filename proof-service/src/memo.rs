@@ -0,0 +1,332 @@
+//! zMail memo framing
+//!
+//! Mirrors the frontend's memo protocol (lib/zcash/memo.ts):
+//! [Version:1][MessageType:1][Sequence:2][Timestamp:4][Payload:504]
+//!
+//! Encrypted messages start with a TYPE_ENCRYPTED fragment whose payload
+//! begins with [ContentKey:32][CiphertextLength:4], followed by ciphertext.
+//!
+//! A shielded output reveals nothing about its sender, so a message may name
+//! where to reply: FLAG_REPLY_TO on the first fragment's type means its
+//! payload starts with [ReplyTo:43], a raw Sapling payment address.
+
+/// Size of a Zcash Sapling memo field
+pub const MEMO_SIZE: usize = 512;
+
+/// zMail header: version + type + sequence + timestamp
+pub const HEADER_SIZE: usize = 8;
+
+/// Usable payload bytes per memo fragment
+pub const PAYLOAD_SIZE: usize = MEMO_SIZE - HEADER_SIZE;
+
+/// Most fragments one message can be split into (the sequence number is 16 bits)
+pub const MAX_FRAGMENTS: usize = u16::MAX as usize + 1;
+
+/// Number of outputs needed to carry a memo of `memo_len` bytes
+///
+/// A memo that fits in a single memo field is sent as-is. Longer content, or
+/// any content with a reply-to address, is chunked into zMail fragments
+/// carrying PAYLOAD_SIZE bytes each.
+pub fn fragment_count(memo_len: usize, reply_to: bool) -> usize {
+    if !reply_to && memo_len <= MEMO_SIZE {
+        1
+    } else {
+        (reply_to_size(reply_to) + memo_len).div_ceil(PAYLOAD_SIZE)
+    }
+}
+
+/// Largest message that fits in `max_fragments` fragments
+pub fn max_message_size(max_fragments: usize) -> usize {
+    if max_fragments <= 1 {
+        MEMO_SIZE
+    } else {
+        max_fragments * PAYLOAD_SIZE
+    }
+}
+
+/// Protocol version written into the header
+pub const VERSION: u8 = 1;
+
+/// Message type codes (see `messageTypeToCode` in the frontend)
+pub const TYPE_TEXT: u8 = 1;
+pub const TYPE_CONTINUATION: u8 = 2;
+/// First fragment of an encrypted message (3 is `receipt`)
+pub const TYPE_ENCRYPTED: u8 = 4;
+/// First fragment of an email (see `email`)
+pub const TYPE_EMAIL: u8 = 5;
+
+/// Set on the first fragment's type when its payload starts with a reply-to address
+pub const FLAG_REPLY_TO: u8 = 0x80;
+
+/// Raw Sapling payment address carried by FLAG_REPLY_TO
+pub const REPLY_TO_SIZE: usize = 43;
+
+fn reply_to_size(reply_to: bool) -> usize {
+    if reply_to {
+        REPLY_TO_SIZE
+    } else {
+        0
+    }
+}
+
+/// `reply_to` followed by `content`, and the first fragment's type
+fn with_reply_to(message_type: u8, reply_to: Option<&[u8; REPLY_TO_SIZE]>, content: &[u8]) -> (u8, Vec<u8>) {
+    match reply_to {
+        Some(address) => {
+            let mut prefixed = Vec::with_capacity(REPLY_TO_SIZE + content.len());
+            prefixed.extend_from_slice(address);
+            prefixed.extend_from_slice(content);
+            (message_type | FLAG_REPLY_TO, prefixed)
+        }
+        None => (message_type, content.to_vec()),
+    }
+}
+
+/// Content key plus ciphertext length, at the start of a TYPE_ENCRYPTED payload
+pub const ENCRYPTED_HEADER_SIZE: usize = 32 + 4;
+
+fn frame(message_type: u8, sequence: usize, timestamp: u32, payload: &[u8]) -> Vec<u8> {
+    let mut fragment = Vec::with_capacity(MEMO_SIZE);
+    fragment.push(VERSION);
+    fragment.push(message_type);
+    fragment.extend_from_slice(&(sequence as u16).to_be_bytes());
+    fragment.extend_from_slice(&timestamp.to_be_bytes());
+    fragment.extend_from_slice(payload);
+    fragment
+}
+
+/// Split a memo into the memo fields carried by its outputs
+///
+/// Memos up to MEMO_SIZE bytes without a reply-to address are passed through
+/// unchanged (the frontend already frames them). Otherwise the content is
+/// framed as one `text` fragment followed by `continuation` fragments,
+/// sequenced so the receiver can reassemble them in order.
+pub fn fragments(memo: &[u8], timestamp: u32, reply_to: Option<&[u8; REPLY_TO_SIZE]>) -> Vec<Vec<u8>> {
+    if reply_to.is_none() && memo.len() <= MEMO_SIZE {
+        return vec![memo.to_vec()];
+    }
+
+    let (first_type, content) = with_reply_to(TYPE_TEXT, reply_to, memo);
+    chunk(&content, first_type, timestamp)
+}
+
+/// Frame content as a `message_type` fragment and its continuations, however short
+pub fn framed(message_type: u8, content: &[u8], timestamp: u32) -> Vec<Vec<u8>> {
+    chunk(content, message_type, timestamp)
+}
+
+fn chunk(content: &[u8], first_type: u8, timestamp: u32) -> Vec<Vec<u8>> {
+    content
+        .chunks(PAYLOAD_SIZE)
+        .enumerate()
+        .map(|(sequence, payload)| {
+            let message_type = if sequence == 0 { first_type } else { TYPE_CONTINUATION };
+            frame(message_type, sequence, timestamp, payload)
+        })
+        .collect()
+}
+
+/// Check memo fields the client framed itself: one message, in sequence order
+///
+/// Each must fit a memo field and carry a zMail header, with consecutive
+/// sequence numbers so the receiver reassembles them as sent. They needn't
+/// start at 0: a message too long for one transaction continues in the next.
+pub fn check_fragments(fragments: &[Vec<u8>]) -> Result<(), String> {
+    if fragments.len() > MAX_FRAGMENTS {
+        return Err(format!("A message has at most {} fragments", MAX_FRAGMENTS));
+    }
+    let mut first = 0;
+    for (i, fragment) in fragments.iter().enumerate() {
+        if fragment.len() > MEMO_SIZE {
+            return Err(format!("Fragment {} is {} bytes; memos hold at most {}", i, fragment.len(), MEMO_SIZE));
+        }
+        if fragment.len() < HEADER_SIZE || fragment[0] != VERSION {
+            return Err(format!("Fragment {} is not a zMail fragment", i));
+        }
+        let sequence = u16::from_be_bytes([fragment[2], fragment[3]]) as usize;
+        if i == 0 {
+            first = sequence;
+        }
+        if sequence != first + i {
+            return Err(format!("Fragment {} has sequence number {}; list fragments in order", i, sequence));
+        }
+    }
+    Ok(())
+}
+
+/// Sequence number of a zMail fragment, or `None` if it has no zMail header
+pub fn sequence(fragment: &[u8]) -> Option<usize> {
+    if fragment.len() < HEADER_SIZE || fragment[0] != VERSION {
+        return None;
+    }
+    Some(u16::from_be_bytes([fragment[2], fragment[3]]) as usize)
+}
+
+/// Number of outputs needed for an encrypted message of `ciphertext_len` bytes
+pub fn encrypted_fragment_count(ciphertext_len: usize, reply_to: bool) -> usize {
+    (reply_to_size(reply_to) + ENCRYPTED_HEADER_SIZE + ciphertext_len)
+        .div_ceil(PAYLOAD_SIZE)
+        .max(1)
+}
+
+/// Frame an encrypted message, carrying its content key in the first fragment
+pub fn encrypted_fragments(
+    key: &[u8; 32],
+    ciphertext: &[u8],
+    timestamp: u32,
+    reply_to: Option<&[u8; REPLY_TO_SIZE]>,
+) -> Vec<Vec<u8>> {
+    let mut content = Vec::with_capacity(ENCRYPTED_HEADER_SIZE + ciphertext.len());
+    content.extend_from_slice(key);
+    content.extend_from_slice(&(ciphertext.len() as u32).to_be_bytes());
+    content.extend_from_slice(ciphertext);
+
+    let (first_type, content) = with_reply_to(TYPE_ENCRYPTED, reply_to, &content);
+    chunk(&content, first_type, timestamp)
+}
+
+/// The parts of an encrypted message
+pub struct EncryptedContent {
+    pub reply_to: Option<[u8; REPLY_TO_SIZE]>,
+    pub key: [u8; 32],
+    pub ciphertext: Vec<u8>,
+}
+
+/// Reassemble the fragments of an encrypted message into its content key and ciphertext
+///
+/// Fragments may arrive in any order and may carry trailing zero padding, as
+/// memos read from the chain are always MEMO_SIZE bytes.
+pub fn parse_encrypted(fragments: &[Vec<u8>]) -> Result<EncryptedContent, String> {
+    let mut ordered = Vec::with_capacity(fragments.len());
+    for fragment in fragments {
+        if fragment.len() < HEADER_SIZE || fragment[0] != VERSION {
+            return Err("Not a zMail fragment".to_string());
+        }
+        let sequence = u16::from_be_bytes([fragment[2], fragment[3]]) as usize;
+        ordered.push((sequence, fragment[1], &fragment[HEADER_SIZE..]));
+    }
+    ordered.sort_by_key(|(sequence, _, _)| *sequence);
+
+    let mut content = Vec::new();
+    let mut has_reply_to = false;
+    for (i, (sequence, mut message_type, payload)) in ordered.into_iter().enumerate() {
+        if i == 0 {
+            has_reply_to = message_type & FLAG_REPLY_TO != 0;
+            message_type &= !FLAG_REPLY_TO;
+        }
+        let expected_type = if i == 0 { TYPE_ENCRYPTED } else { TYPE_CONTINUATION };
+        if sequence != i || message_type != expected_type {
+            return Err(format!("Fragment {} is missing or out of place", i));
+        }
+        content.extend_from_slice(payload);
+    }
+
+    let reply_to = if has_reply_to {
+        if content.len() < REPLY_TO_SIZE {
+            return Err("Reply-to address is truncated".to_string());
+        }
+        let address: [u8; REPLY_TO_SIZE] = content[..REPLY_TO_SIZE].try_into().expect("length checked above");
+        content.drain(..REPLY_TO_SIZE);
+        Some(address)
+    } else {
+        None
+    };
+
+    if content.len() < ENCRYPTED_HEADER_SIZE {
+        return Err("Encrypted message header is truncated".to_string());
+    }
+    let (header, rest) = content.split_at(ENCRYPTED_HEADER_SIZE);
+    let key: [u8; 32] = header[..32].try_into().expect("header holds 32 key bytes");
+    let len = u32::from_be_bytes(header[32..].try_into().expect("header holds 4 length bytes")) as usize;
+    if rest.len() < len {
+        return Err(format!("Expected {} ciphertext bytes, found {}", len, rest.len()));
+    }
+    Ok(EncryptedContent {
+        reply_to,
+        key,
+        ciphertext: rest[..len].to_vec(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keys;
+    use crate::testing::account;
+    use rand_chacha::{rand_core::SeedableRng, ChaCha20Rng};
+    use sapling::PaymentAddress;
+
+    /// A fresh diversified address of the test account, as a sender would reveal it
+    fn sender_address() -> [u8; REPLY_TO_SIZE] {
+        let dfvk = account().to_diversifiable_full_viewing_key();
+        keys::random_address(&dfvk, &mut ChaCha20Rng::seed_from_u64(1)).to_bytes()
+    }
+
+    /// Pad a fragment to a full memo field, as it is read back from the chain
+    fn padded(fragment: &[u8]) -> Vec<u8> {
+        let mut memo = fragment.to_vec();
+        memo.resize(MEMO_SIZE, 0);
+        memo
+    }
+
+    #[test]
+    fn short_memo_without_reply_to_is_sent_as_is() {
+        assert_eq!(fragments(b"hi", 7, None), vec![b"hi".to_vec()]);
+    }
+
+    #[test]
+    fn reply_to_is_framed_ahead_of_the_text() {
+        let reply_to = sender_address();
+        let memo = vec![b'a'; 600];
+        let fragments = fragments(&memo, 7, Some(&reply_to));
+        assert_eq!(fragments.len(), fragment_count(memo.len(), true));
+        check_fragments(&fragments).unwrap();
+
+        let first = &fragments[0];
+        assert_eq!(first[1], TYPE_TEXT | FLAG_REPLY_TO);
+        assert_eq!(sequence(first), Some(0));
+        assert_eq!(first[4..HEADER_SIZE], 7u32.to_be_bytes());
+        assert_eq!(first[HEADER_SIZE..HEADER_SIZE + REPLY_TO_SIZE], reply_to);
+        assert_eq!(fragments[1][1], TYPE_CONTINUATION);
+
+        let content: Vec<u8> = fragments.iter().flat_map(|fragment| fragment[HEADER_SIZE..].to_vec()).collect();
+        assert_eq!(content[REPLY_TO_SIZE..], memo);
+    }
+
+    #[test]
+    fn reply_to_frames_even_a_short_memo() {
+        let fragments = fragments(b"hi", 7, Some(&sender_address()));
+        assert_eq!(fragments.len(), 1);
+        assert_eq!(fragments[0][1], TYPE_TEXT | FLAG_REPLY_TO);
+        assert_eq!(fragments[0].len(), HEADER_SIZE + REPLY_TO_SIZE + 2);
+    }
+
+    #[test]
+    fn encrypted_message_round_trips_with_reply_to() {
+        let reply_to = sender_address();
+        let key = [5; 32];
+        let ciphertext: Vec<u8> = (0..700).map(|i| i as u8).collect();
+        let fragments = encrypted_fragments(&key, &ciphertext, 7, Some(&reply_to));
+        assert_eq!(fragments.len(), encrypted_fragment_count(ciphertext.len(), true));
+        assert_eq!(fragments[0][1], TYPE_ENCRYPTED | FLAG_REPLY_TO);
+
+        let received: Vec<_> = fragments.iter().rev().map(|fragment| padded(fragment)).collect();
+        let content = parse_encrypted(&received).unwrap();
+        assert_eq!(content.key, key);
+        assert_eq!(content.ciphertext, ciphertext);
+
+        // The address read back belongs to the sender's account, so a reply reaches them
+        let address = PaymentAddress::from_bytes(&content.reply_to.unwrap()).unwrap();
+        let dfvk = account().to_diversifiable_full_viewing_key();
+        assert!(dfvk.decrypt_diversifier(&address).is_some());
+    }
+
+    #[test]
+    fn encrypted_message_without_reply_to_has_none() {
+        let fragments = encrypted_fragments(&[5; 32], b"secret", 7, None);
+        assert_eq!(fragments[0][1], TYPE_ENCRYPTED);
+        let content = parse_encrypted(&fragments).unwrap();
+        assert_eq!(content.reply_to, None);
+        assert_eq!(content.ciphertext, b"secret");
+    }
+}
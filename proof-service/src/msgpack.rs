@@ -0,0 +1,68 @@
+//! MessagePack responses for clients that ask for them
+//!
+//! Mobile clients batching proofs spend much of their bandwidth on JSON
+//! text. A request sent with `Accept: application/msgpack` gets the same
+//! response body encoded as MessagePack, with the same field names. Request
+//! bodies and every other client stay on JSON.
+
+use actix_web::{
+    body::{self, BoxBody, MessageBody},
+    dev::{Service, ServiceRequest, ServiceResponse},
+    error::ErrorInternalServerError,
+    http::header::{self, HeaderMap, HeaderValue},
+};
+use std::future::Future;
+
+pub const CONTENT_TYPE: &str = "application/msgpack";
+
+/// Whether the Accept header lists MessagePack (under its registered or legacy name)
+fn wants_msgpack(headers: &HeaderMap) -> bool {
+    headers
+        .get_all(header::ACCEPT)
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|range| range.split(';').next().unwrap_or("").trim())
+        .any(|mime| mime.eq_ignore_ascii_case(CONTENT_TYPE) || mime.eq_ignore_ascii_case("application/x-msgpack"))
+}
+
+/// Middleware (for `App::wrap_fn`) re-encoding JSON responses as MessagePack when asked to
+///
+/// Handlers keep responding with JSON; the body is converted here, so every
+/// endpoint supports both formats without knowing about them.
+pub fn negotiate<S, B>(
+    req: ServiceRequest,
+    srv: &S,
+) -> impl Future<Output = Result<ServiceResponse<BoxBody>, actix_web::Error>>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error>,
+    B: MessageBody + 'static,
+{
+    let msgpack = wants_msgpack(req.headers());
+    let response = srv.call(req);
+
+    async move {
+        let mut response = response.await?.map_into_boxed_body();
+        response
+            .headers_mut()
+            .append(header::VARY, HeaderValue::from_static("Accept"));
+        let is_json = response
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| value.starts_with("application/json"));
+        if !msgpack || !is_json {
+            return Ok(response);
+        }
+
+        let (req, res) = response.into_parts();
+        let (mut res, json) = res.into_parts();
+        let json = body::to_bytes(json)
+            .await
+            .map_err(|e| ErrorInternalServerError(e.to_string()))?;
+        let value: serde_json::Value = serde_json::from_slice(&json).map_err(ErrorInternalServerError)?;
+        let packed = rmp_serde::to_vec(&value).map_err(ErrorInternalServerError)?;
+        res.headers_mut()
+            .insert(header::CONTENT_TYPE, HeaderValue::from_static(CONTENT_TYPE));
+        Ok(ServiceResponse::new(req, res.set_body(BoxBody::new(packed))))
+    }
+}
@@ -0,0 +1,436 @@
+//! Client-supplied spendable notes and note selection
+//!
+//! The service doesn't scan the chain itself, so clients send the notes they
+//! want to spend together with their witnesses (Merkle authentication paths).
+
+use incrementalmerkletree::Position;
+use std::collections::HashSet;
+use sapling::{
+    note::ExtractedNoteCommitment, value::NoteValue, zip32::ExtendedSpendingKey, Anchor,
+    Diversifier, MerklePath, Node, Note, Rseed,
+};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::consensus::MAX_MONEY;
+use crate::fees;
+use crate::lightwalletd::{LightwalletdClient, TreeState};
+use crate::witness::Checkpoint;
+
+/// Depth of the Sapling note commitment tree
+pub const TREE_DEPTH: usize = 32;
+
+/// Confirmations a note needs before it is selected, unless the request overrides it
+pub const DEFAULT_MIN_CONFIRMATIONS: u32 = 10;
+
+/// Which of the account's key trees a note was received under (ZIP 32)
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum NoteScope {
+    /// Addresses given out to senders
+    #[default]
+    External,
+    /// Change addresses
+    Internal,
+}
+
+/// A received note and its witness, as clients send it to spend the note
+///
+/// The one shape for notes across the spend side of the API: build requests
+/// take a list of them to select from, and spend proof requests one as their
+/// "note" param. The witness fields match what `/witness/build` returns.
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct SpendableNote {
+    /// Note value in zatoshi
+    pub value: u64,
+    /// Diversifier of the address the note was received at (11 bytes, hex)
+    pub diversifier: String,
+    /// Key tree the receiving address belongs to (default external)
+    #[serde(default)]
+    pub scope: NoteScope,
+    /// Note rseed (32 bytes, hex)
+    pub rseed: String,
+    /// Position of the note commitment in the Sapling tree
+    pub position: u64,
+    /// Merkle authentication path, leaf to root (32 nodes, hex)
+    pub auth_path: Vec<String>,
+    /// Height of the block containing the note
+    pub height: u32,
+    /// Transaction the note was received in (hex, display order); only used in messages
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub txid: Option<String>,
+    /// Index of the note's output in that transaction; only used in messages
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output_index: Option<u32>,
+}
+
+/// A decoded note together with its witness, ready to be spent
+pub struct SpendCandidate {
+    /// Key the note was received under (the account key, or its internal key for change)
+    pub extsk: ExtendedSpendingKey,
+    pub note: Note,
+    pub merkle_path: MerklePath,
+    pub height: u32,
+    pub nullifier: [u8; 32],
+}
+
+impl SpendCandidate {
+    pub fn value(&self) -> u64 {
+        self.note.value().inner()
+    }
+
+    /// Confirmations as of a transaction targeting `target_height` (the tip is one below it)
+    pub fn confirmations(&self, target_height: u32) -> u32 {
+        target_height.saturating_sub(self.height)
+    }
+
+    /// The anchor this note's witness commits to
+    pub fn anchor(&self) -> Anchor {
+        Anchor::from(self.merkle_path.root(Node::from_cmu(&self.note.cmu())))
+    }
+}
+
+/// Decode `value` as exactly N bytes of hex, naming `field` in errors
+pub fn decode_hex_array<const N: usize>(field: &str, value: &str) -> Result<[u8; N], String> {
+    let bytes = hex::decode(value).map_err(|e| format!("{} is not valid hex: {}", field, e))?;
+    bytes
+        .try_into()
+        .map_err(|_| format!("{} must be {} bytes", field, N))
+}
+
+impl SpendableNote {
+    /// How errors refer to the note: its index in the request, and where it was received if known
+    pub fn label(&self, index: usize) -> String {
+        match (&self.txid, self.output_index) {
+            (Some(txid), Some(output_index)) => format!("Note {} ({}:{})", index, txid, output_index),
+            _ => format!("Note {}", index),
+        }
+    }
+
+    /// Reconstruct the note under `account` and decode its witness
+    ///
+    /// Any diversifier works, so notes received at several diversified
+    /// addresses of one account can be spent together. Each note keeps the
+    /// key for its scope, since internal notes have their own nullifier and
+    /// proof generation keys.
+    pub fn to_spend(&self, account: &ExtendedSpendingKey) -> Result<SpendCandidate, String> {
+        let diversifier = Diversifier(decode_hex_array("diversifier", &self.diversifier)?);
+        let rseed = Rseed::AfterZip212(decode_hex_array("rseed", &self.rseed)?);
+        let extsk = match self.scope {
+            NoteScope::External => account.clone(),
+            NoteScope::Internal => account.derive_internal(),
+        };
+
+        let dfvk = extsk.to_diversifiable_full_viewing_key();
+        let recipient = dfvk
+            .fvk()
+            .vk
+            .to_payment_address(diversifier)
+            .ok_or("diversifier does not produce a valid address for this key")?;
+        let note = Note::from_parts(recipient, NoteValue::from_raw(self.value), rseed);
+        let nullifier = note.nf(&dfvk.fvk().vk.nk, self.position).0;
+        let merkle_path = decode_merkle_path(&self.auth_path, self.position)?;
+
+        Ok(SpendCandidate {
+            extsk,
+            note,
+            merkle_path,
+            height: self.height,
+            nullifier,
+        })
+    }
+}
+
+/// Find candidates whose nullifier already appears on-chain
+///
+/// Scans compact blocks from the oldest candidate's height up to `tip` and
+/// returns the indices of candidates that have been spent.
+pub async fn find_spent(
+    client: &mut LightwalletdClient,
+    candidates: &[SpendCandidate],
+    tip: u32,
+) -> Result<Vec<usize>, String> {
+    let Some(start) = candidates.iter().map(|c| c.height).min() else {
+        return Ok(vec![]);
+    };
+    let nullifiers: HashSet<&[u8]> = candidates.iter().map(|c| &c.nullifier[..]).collect();
+
+    let mut seen = HashSet::new();
+    let mut blocks = client.block_range(start as u64, tip as u64).await?;
+    while let Some(block) = blocks
+        .message()
+        .await
+        .map_err(|e| format!("Failed to read compact block: {}", e))?
+    {
+        for spend in block.vtx.iter().flat_map(|tx| tx.spends.iter()) {
+            if nullifiers.contains(&spend.nf[..]) {
+                seen.insert(spend.nf.clone());
+            }
+        }
+    }
+
+    Ok(candidates
+        .iter()
+        .enumerate()
+        .filter(|(_, c)| seen.contains(&c.nullifier[..]))
+        .map(|(i, _)| i)
+        .collect())
+}
+
+/// Check that every witness commits to the same anchor
+///
+/// A transaction has a single Sapling anchor, so spends whose witnesses were
+/// updated to different blocks can't go in one. The builder would take the
+/// first note's anchor and the other spends' proofs would fail verification
+/// at broadcast. Fails with the notes grouped by the anchor they are at.
+pub fn check_common_anchor(candidates: &[SpendCandidate]) -> Result<(), String> {
+    let mut anchors: Vec<(Anchor, Vec<usize>)> = Vec::new();
+    for (i, candidate) in candidates.iter().enumerate() {
+        let anchor = candidate.anchor();
+        match anchors.iter_mut().find(|(a, _)| *a == anchor) {
+            Some((_, notes)) => notes.push(i),
+            None => anchors.push((anchor, vec![i])),
+        }
+    }
+    if anchors.len() <= 1 {
+        return Ok(());
+    }
+    let groups = anchors
+        .iter()
+        .map(|(anchor, notes)| {
+            let notes = notes.iter().map(|i| i.to_string()).collect::<Vec<_>>().join(", ");
+            format!("notes {} at anchor {}", notes, hex::encode(anchor.to_bytes()))
+        })
+        .collect::<Vec<_>>()
+        .join("; ");
+    Err(format!(
+        "The witnesses are at {} different anchors ({}); re-fetch all of them at a common anchor, \
+         e.g. with /witness/build against one tip",
+        anchors.len(),
+        groups
+    ))
+}
+
+/// Check that every witness commits to the Sapling tree as of a pinned block
+///
+/// `tree` is the chain's tree state at the pinned height and `block_hash` the
+/// hash the client expects there, so a reorg since the client synced is
+/// reported instead of producing a transaction with an unknown anchor.
+pub fn check_anchor(
+    candidates: &[SpendCandidate],
+    tree: &TreeState,
+    block_hash: &str,
+) -> Result<(), String> {
+    if !tree.hash.eq_ignore_ascii_case(block_hash) {
+        return Err(format!(
+            "Block {} has hash {}, not {}; the chain may have reorganized",
+            tree.height, tree.hash, block_hash
+        ));
+    }
+
+    let anchor = Checkpoint::from_tree_state(tree)
+        .map_err(|e| format!("lightwalletd returned an invalid tree state: {}", e))?
+        .anchor();
+
+    for (i, candidate) in candidates.iter().enumerate() {
+        if u64::from(candidate.height) > tree.height {
+            return Err(format!(
+                "Note {} was mined at height {}, after the anchor height {}",
+                i, candidate.height, tree.height
+            ));
+        }
+        if candidate.anchor() != anchor {
+            return Err(format!(
+                "Note {}'s witness is not at the anchor of block {}; update the witnesses to that block",
+                i, tree.height
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Decode a hex-encoded authentication path (leaf to root) for the leaf at `position`
+pub fn decode_merkle_path(auth_path: &[String], position: u64) -> Result<MerklePath, String> {
+    if auth_path.len() != TREE_DEPTH {
+        return Err(format!(
+            "auth_path must have {} nodes, found {}",
+            TREE_DEPTH,
+            auth_path.len()
+        ));
+    }
+    let auth_path = auth_path
+        .iter()
+        .map(|node| {
+            let bytes = decode_hex_array("auth_path node", node)?;
+            Option::from(Node::from_bytes(bytes))
+                .ok_or_else(|| "auth_path node is not a valid field element".to_string())
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+    MerklePath::from_parts(auth_path, Position::from(position))
+        .map_err(|_| "invalid Merkle path".to_string())
+}
+
+/// The root a witness for the note commitment `cmu` (hex) hashes up to
+pub fn witness_root(cmu: &str, auth_path: &[String], position: u64) -> Result<Anchor, String> {
+    let cmu = Option::from(ExtractedNoteCommitment::from_bytes(&decode_hex_array("cmu", cmu)?))
+        .ok_or("cmu is not a valid note commitment")?;
+    let merkle_path = decode_merkle_path(auth_path, position)?;
+    Ok(Anchor::from(merkle_path.root(Node::from_cmu(&cmu))))
+}
+
+/// Notes chosen to fund a transaction
+pub struct Selection {
+    pub notes: Vec<SpendCandidate>,
+    pub fee: u64,
+    /// Change returned to the sender, if a change output is needed
+    pub change: Option<u64>,
+}
+
+#[derive(Debug)]
+pub enum SelectionError {
+    /// `pending` is the value of notes excluded for having too few confirmations
+    InsufficientFunds { available: u64, required: u64, pending: u64 },
+    /// A value or running total exceeded MAX_MONEY
+    ValueOverflow,
+}
+
+/// Select notes largest-first until they cover `target` plus the fee
+///
+/// Notes with fewer than `min_confirmations` as of `target_height` are
+/// skipped, since a reorg could still remove them. `outputs` is the number
+/// of non-change Sapling outputs and `transparent_outputs` the number of
+/// transparent ones. Unless `fee_override` fixes it, the fee is the
+/// ZIP-317 fee, which grows with the number of spends, so it is recomputed
+/// after every note is added.
+///
+/// With `always_change` an exact match still gets a (zero-value) change
+/// output, for builds padded to a fixed output count that must not depend on
+/// the notes selected.
+///
+/// All sums are checked: crafted note values that would overflow or exceed
+/// MAX_MONEY fail with `ValueOverflow` instead of wrapping.
+#[allow(clippy::too_many_arguments)]
+pub fn select_notes(
+    candidates: Vec<SpendCandidate>,
+    target: u64,
+    outputs: usize,
+    transparent_outputs: usize,
+    target_height: u32,
+    min_confirmations: u32,
+    fee_override: Option<u64>,
+    always_change: bool,
+) -> Result<Selection, SelectionError> {
+    if target > MAX_MONEY {
+        return Err(SelectionError::ValueOverflow);
+    }
+    let required = |fee: u64| target.checked_add(fee).ok_or(SelectionError::ValueOverflow);
+    let fee_for = |spends: usize, outputs: usize| {
+        fee_override
+            .unwrap_or_else(|| fees::zip317_fee_with_transparent(0, transparent_outputs, spends, outputs))
+    };
+
+    let (mut candidates, too_recent): (Vec<_>, Vec<_>) = candidates
+        .into_iter()
+        .partition(|c| c.confirmations(target_height) >= min_confirmations);
+    let pending = too_recent
+        .iter()
+        .fold(0u64, |sum, c| sum.saturating_add(c.value()));
+    candidates.sort_by_key(|c| std::cmp::Reverse(c.value()));
+
+    let mut selected = Vec::new();
+    let mut total = 0u64;
+    for candidate in candidates {
+        total = total
+            .checked_add(candidate.value())
+            .filter(|total| *total <= MAX_MONEY)
+            .ok_or(SelectionError::ValueOverflow)?;
+        selected.push(candidate);
+
+        // Exact match: no change output needed
+        let fee = fee_for(selected.len(), outputs);
+        if !always_change && total == required(fee)? {
+            return Ok(Selection { notes: selected, fee, change: None });
+        }
+
+        let fee = fee_for(selected.len(), outputs + 1);
+        let needed = required(fee)?;
+        if total >= needed {
+            return Ok(Selection {
+                notes: selected,
+                fee,
+                change: Some(total - needed),
+            });
+        }
+    }
+
+    Err(SelectionError::InsufficientFunds {
+        available: total,
+        required: required(fee_for(selected.len().max(1), outputs + 1))?,
+        pending,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{account, received_notes};
+
+    /// Notes of `values` at the account's default address
+    fn candidates(values: &[u64]) -> Vec<SpendCandidate> {
+        let account = account();
+        let address = account.default_address().1;
+        let notes: Vec<_> = values.iter().map(|value| (NoteScope::External, address, *value)).collect();
+        received_notes(&notes, 1)
+            .iter()
+            .map(|note| note.to_spend(&account).unwrap())
+            .collect()
+    }
+
+    fn select(candidates: Vec<SpendCandidate>, target: u64) -> Result<Selection, SelectionError> {
+        select_notes(candidates, target, 1, 0, 100, 1, None, false)
+    }
+
+    #[test]
+    fn selects_notes_with_change() {
+        let selection = select(candidates(&[30_000, 50_000]), 30_000).unwrap();
+        assert_eq!(selection.notes.len(), 1);
+        assert_eq!(selection.fee, 10_000);
+        assert_eq!(selection.change, Some(10_000));
+    }
+
+    #[test]
+    fn values_summing_past_max_money_overflow() {
+        // Each note is within range on its own, but not the two together
+        let result = select(candidates(&[MAX_MONEY, MAX_MONEY]), MAX_MONEY);
+        assert!(matches!(result, Err(SelectionError::ValueOverflow)));
+    }
+
+    #[test]
+    fn values_that_would_wrap_a_naive_sum_overflow() {
+        let half = u64::MAX / 2 + 1;
+        let result = select(candidates(&[half, half]), 1);
+        assert!(matches!(result, Err(SelectionError::ValueOverflow)));
+    }
+
+    #[test]
+    fn target_above_max_money_overflows() {
+        let result = select(candidates(&[50_000]), MAX_MONEY + 1);
+        assert!(matches!(result, Err(SelectionError::ValueOverflow)));
+    }
+    #[test]
+    fn witnesses_in_one_tree_share_an_anchor() {
+        assert!(check_common_anchor(&candidates(&[30_000, 50_000])).is_ok());
+    }
+
+    #[test]
+    fn witnesses_at_different_anchors_are_rejected() {
+        let account = account();
+        let address = account.default_address().1;
+        let mut notes = received_notes(&[(NoteScope::External, address, 30_000)], 1);
+        notes.extend(received_notes(&[(NoteScope::External, address, 50_000)], 2));
+        let candidates: Vec<_> = notes.iter().map(|note| note.to_spend(&account).unwrap()).collect();
+
+        let error = check_common_anchor(&candidates).unwrap_err();
+        assert!(error.contains("2 different anchors"), "{}", error);
+    }
+}
@@ -0,0 +1,120 @@
+//! OpenAPI 3 description of the HTTP API
+//!
+//! Served at `/openapi.json`, with Swagger UI at `/docs/`. Schemas are derived
+//! from the request and response types and each handler carries its own
+//! `#[utoipa::path]`, so the document follows the code. The `/debug/*`
+//! endpoints are left out since they are only routed with ZMAIL_DEBUG=1.
+
+use utoipa::OpenApi;
+
+use crate::{
+    deferred, email,
+    error::{ApiError, FieldError},
+    estimate, keys, notes, proof_limit, recipients, transaction, transparent, witness,
+};
+
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "zMail proof service",
+        description = "Sapling proving, transaction building and key utilities for zMail"
+    ),
+    paths(
+        crate::generate_proof,
+        crate::generate_proof_batch,
+        crate::build_transaction,
+        crate::estimate_time,
+        crate::verify_proof,
+        crate::plan_email,
+        crate::send_transaction,
+        crate::tx_sighash,
+        crate::tx_decode,
+        crate::validate_witness,
+        crate::build_witnesses,
+        crate::chain_tip,
+        crate::derive_address,
+        crate::validate_key,
+        crate::export_fvk,
+        crate::export_ovk,
+        crate::decrypt_message,
+        crate::decrypt_sent,
+        crate::capabilities,
+        crate::limits,
+        crate::health,
+        crate::health_ready,
+        crate::health_stats,
+        crate::metrics,
+    ),
+    components(schemas(
+        ApiError,
+        FieldError,
+        crate::ProofRequest,
+        crate::ProofResponse,
+        crate::BatchProofRequest,
+        crate::BatchProofResponse,
+        crate::BatchProofResult,
+        crate::Encoding,
+        crate::Bytes,
+        crate::SpendAuthorization,
+        crate::VerifyRequest,
+        crate::VerifyResponse,
+        crate::BuildTransactionRequest,
+        crate::BuildTransactionResponse,
+        crate::SendTransactionRequest,
+        crate::BroadcastStatus,
+        crate::SendTransactionResponse,
+        crate::ProofMode,
+        crate::EstimateTimeRequest,
+        crate::EmailRecipientInput,
+        crate::AttachmentInput,
+        crate::EmailPlanRequest,
+        crate::EmailPlanResponse,
+        email::EmailOutput,
+        email::PlannedTransaction,
+        estimate::Estimate,
+        estimate::Calibration,
+        crate::SighashRequest,
+        crate::SighashResponse,
+        crate::DecodeTransactionRequest,
+        crate::ValidateWitnessRequest,
+        crate::ValidateWitnessResponse,
+        crate::BuildWitnessesRequest,
+        crate::BuildWitnessesResponse,
+        crate::ChainTipResponse,
+        witness::CheckpointInput,
+        witness::WitnessOutput,
+        crate::DeriveAddressRequest,
+        crate::DeriveAddressResponse,
+        crate::ValidateKeyRequest,
+        crate::ExportFvkRequest,
+        crate::ExportFvkResponse,
+        crate::ExportOvkRequest,
+        crate::ExportOvkResponse,
+        crate::DecryptMessageRequest,
+        crate::DecryptMessageResponse,
+        crate::NoteProvenance,
+        crate::SentMailRequest,
+        crate::SentOutputInput,
+        crate::SentMailResponse,
+        crate::SentOutput,
+        crate::CapabilitiesResponse,
+        crate::LimitsResponse,
+        crate::ReadinessResponse,
+        recipients::RecipientInput,
+        recipients::ReplyTo,
+        notes::SpendableNote,
+        notes::NoteScope,
+        transaction::Privacy,
+        transaction::DecodedTransaction,
+        transaction::ValueBreakdown,
+        transaction::ZecValue,
+        transparent::UtxoInput,
+        deferred::ProvingInputs,
+        deferred::SpendWitness,
+        deferred::OutputWitness,
+        keys::KeyKind,
+        keys::KeyClassification,
+        proof_limit::ProofLoad,
+    ))
+)]
+pub struct ApiDoc;
@@ -0,0 +1,248 @@
+//! Lazily loaded Sapling proving parameters
+//!
+//! Output proofs only need the small output parameters; the spend parameters
+//! (~47MB) are loaded the first time a spend is actually proven. Each file is
+//! read and hash-checked at most once, then shared by every request.
+//!
+//! In low-memory mode the spend parameters are dropped again once no request
+//! is using them (see `ReleaseSpend`). The resident set then only holds the
+//! output parameters between spends, at the cost of re-reading and
+//! re-verifying the spend file (a second or more) for every spend request.
+
+use sapling::circuit::{OutputParameters, SpendParameters};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use zcash_proofs::{hashreader::HashReader, SAPLING_OUTPUT_HASH, SAPLING_SPEND_HASH};
+
+pub const SPEND_PARAMS: &str = "sapling-spend.params";
+pub const OUTPUT_PARAMS: &str = "sapling-output.params";
+
+/// The Sapling parameter files have fixed sizes, so a wrong size means a wrong or partial file
+const SPEND_PARAMS_SIZE: u64 = 47_958_396;
+const OUTPUT_PARAMS_SIZE: u64 = 3_592_860;
+
+/// Which parameters an operation needs
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ParamSet {
+    /// Output proofs only
+    OutputOnly,
+    /// Spend and output proofs
+    Full,
+}
+
+impl ParamSet {
+    /// Parameter files that must be present for this set
+    pub fn files(self) -> &'static [&'static str] {
+        match self {
+            ParamSet::OutputOnly => &[OUTPUT_PARAMS],
+            ParamSet::Full => &[SPEND_PARAMS, OUTPUT_PARAMS],
+        }
+    }
+
+    /// Whether `dir` contains every file this set needs
+    pub fn present_in(self, dir: &Path) -> bool {
+        self.files().iter().all(|file| dir.join(file).exists())
+    }
+}
+
+/// Describe `path` if it's a symlink whose target is missing
+///
+/// `Path::exists` follows links, so such a file is treated as absent even
+/// though `ls` lists it, and "not downloaded" errors would be misleading.
+pub fn broken_symlink(path: &Path) -> Option<String> {
+    let link = std::fs::symlink_metadata(path).ok()?;
+    if !link.file_type().is_symlink() || path.metadata().is_ok() {
+        return None;
+    }
+    let target = std::fs::read_link(path)
+        .map(|target| format!("{:?}", target))
+        .unwrap_or_else(|_| "a target that can't be read".to_string());
+    Some(format!("params symlink is broken: {:?} points to {}, which doesn't exist", path, target))
+}
+
+/// Loaded parameters; `spend` is only present when `ParamSet::Full` was requested
+#[derive(Clone)]
+pub struct Provers {
+    pub output: Arc<OutputParameters>,
+    pub spend: Option<Arc<SpendParameters>>,
+}
+
+static OUTPUT: Mutex<Option<Arc<OutputParameters>>> = Mutex::new(None);
+static SPEND: Mutex<Option<Arc<SpendParameters>>> = Mutex::new(None);
+
+/// Return the cached parameters, reading and verifying `path` on first use
+///
+/// The lock is held while loading so concurrent first requests don't read
+/// the same file twice. The size is checked before parsing, since parsing a
+/// file from another parameter set fails with an unhelpful error.
+fn load_cached<T>(
+    cache: &Mutex<Option<Arc<T>>>,
+    path: &Path,
+    expected_size: u64,
+    expected_hash: &str,
+    read: impl FnOnce(&mut HashReader<BufReader<File>>) -> io::Result<T>,
+) -> Result<Arc<T>, String> {
+    let mut cached = cache.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(params) = cached.as_ref() {
+        return Ok(params.clone());
+    }
+
+    let start = Instant::now();
+    let file = File::open(path).map_err(|e| format!("Failed to open {:?}: {}", path, e))?;
+    let size = file
+        .metadata()
+        .map_err(|e| format!("Failed to read {:?}: {}", path, e))?
+        .len();
+    if size != expected_size {
+        return Err(format!(
+            "{:?} is {} bytes, but the Sapling parameters are exactly {} bytes. \
+             The file appears to be the wrong version or incomplete; download it again.",
+            path, size, expected_size
+        ));
+    }
+    let mut reader = HashReader::new(BufReader::new(file));
+    let params = read(&mut reader).map_err(|e| format!("Failed to read {:?}: {}", path, e))?;
+
+    // The hash covers the whole file, including anything the reader didn't consume
+    io::copy(&mut reader, &mut io::sink())
+        .map_err(|e| format!("Failed to read {:?}: {}", path, e))?;
+    let hash = reader.into_hash();
+    if hash != expected_hash {
+        return Err(format!(
+            "{:?} is corrupt or the wrong file (hash {}, expected {}). Download it again.",
+            path, hash, expected_hash
+        ));
+    }
+
+    println!(
+        "[ProofService] ✅ Loaded {:?} in {}ms",
+        path,
+        start.elapsed().as_millis()
+    );
+    let params = Arc::new(params);
+    *cached = Some(params.clone());
+    Ok(params)
+}
+
+/// The parameters in `set` if they're already loaded, without waiting on a load in progress
+pub fn cached(set: ParamSet) -> Option<Provers> {
+    let output = OUTPUT.try_lock().ok()?.clone()?;
+    let spend = match set {
+        ParamSet::OutputOnly => None,
+        ParamSet::Full => Some(SPEND.try_lock().ok()?.clone()?),
+    };
+    Some(Provers { output, spend })
+}
+
+/// Drops the cached spend parameters when dropped, unless a request is still using them
+///
+/// Create it before taking the `Provers` it covers, so they are dropped first.
+/// Whichever request finishes last then frees the parameters.
+pub struct ReleaseSpend;
+
+impl Drop for ReleaseSpend {
+    fn drop(&mut self) {
+        // A request holding the lock is loading them, so they are about to be used
+        let Ok(mut spend) = SPEND.try_lock() else {
+            return;
+        };
+        if spend.as_ref().is_some_and(|params| Arc::strong_count(params) == 1) {
+            *spend = None;
+            println!("[ProofService] Released the spend parameters (low-memory mode)");
+        }
+    }
+}
+
+/// State of one parameter file on disk
+#[derive(Serialize)]
+pub struct ParamFileStatus {
+    pub file: &'static str,
+    pub path: Option<PathBuf>,
+    pub present: bool,
+    pub size: Option<u64>,
+    pub expected_size: u64,
+    pub sha256: Option<String>,
+    /// Size and BLAKE2b hash both match the published Sapling parameters
+    pub valid: bool,
+    pub error: Option<String>,
+}
+
+/// Parameter status for `/debug/params`
+#[derive(Serialize)]
+pub struct ParamsReport {
+    /// Directory the service would load from, if any has the files
+    pub directory: Option<PathBuf>,
+    pub output_loaded: bool,
+    pub spend_loaded: bool,
+    pub files: Vec<ParamFileStatus>,
+}
+
+fn inspect_file(dir: Option<&Path>, file: &'static str, expected_size: u64, expected_hash: &str) -> ParamFileStatus {
+    let path = dir.map(|dir| dir.join(file));
+    let mut status = ParamFileStatus {
+        file,
+        path: path.clone(),
+        present: path.as_ref().is_some_and(|p| p.exists()),
+        size: None,
+        expected_size,
+        sha256: None,
+        valid: false,
+        error: None,
+    };
+    let Some(path) = path.filter(|_| status.present) else {
+        status.error = status.path.as_deref().and_then(broken_symlink);
+        return status;
+    };
+
+    // One pass computes both the SHA-256 for humans and the BLAKE2b zcash_proofs checks
+    let result = File::open(&path).and_then(|file| {
+        let size = file.metadata()?.len();
+        let mut reader = HashReader::new(BufReader::new(file));
+        let mut sha256 = Sha256::new();
+        io::copy(&mut reader, &mut sha256)?;
+        Ok((size, hex::encode(sha256.finalize()), reader.into_hash()))
+    });
+    match result {
+        Ok((size, sha256, blake2b)) => {
+            status.size = Some(size);
+            status.sha256 = Some(sha256);
+            status.valid = size == expected_size && blake2b == expected_hash;
+        }
+        Err(e) => status.error = Some(format!("Failed to read {:?}: {}", path, e)),
+    }
+    status
+}
+
+/// Check the parameter files in `dir` against the published sizes and hashes
+///
+/// Reads both files in full, so this takes a moment.
+pub fn inspect(dir: Option<&Path>) -> ParamsReport {
+    ParamsReport {
+        directory: dir.map(Path::to_path_buf),
+        output_loaded: OUTPUT.try_lock().is_ok_and(|p| p.is_some()),
+        spend_loaded: SPEND.try_lock().is_ok_and(|p| p.is_some()),
+        files: vec![
+            inspect_file(dir, OUTPUT_PARAMS, OUTPUT_PARAMS_SIZE, SAPLING_OUTPUT_HASH),
+            inspect_file(dir, SPEND_PARAMS, SPEND_PARAMS_SIZE, SAPLING_SPEND_HASH),
+        ],
+    }
+}
+
+/// Load (or reuse) the parameters in `set` from `dir`
+pub fn load(dir: &Path, set: ParamSet) -> Result<Provers, String> {
+    let output = load_cached(&OUTPUT, &dir.join(OUTPUT_PARAMS), OUTPUT_PARAMS_SIZE, SAPLING_OUTPUT_HASH, |r| {
+        OutputParameters::read(r, false)
+    })?;
+    let spend = match set {
+        ParamSet::OutputOnly => None,
+        ParamSet::Full => Some(load_cached(&SPEND, &dir.join(SPEND_PARAMS), SPEND_PARAMS_SIZE, SAPLING_SPEND_HASH, |r| {
+            SpendParameters::read(r, false)
+        })?),
+    };
+    Ok(Provers { output, spend })
+}
@@ -0,0 +1,145 @@
+//! Notes spent by built but unconfirmed transactions (single-wallet mode)
+//!
+//! Clients send their notes with every build, and a note spent by a
+//! transaction that hasn't been mined yet still looks unspent to them. A
+//! wallet sending twice in quick succession would have the second build
+//! select the same notes, producing a double-spend the network rejects.
+//!
+//! With ZMAIL_SINGLE_WALLET=1 every build reserves the nullifiers it spends,
+//! and later builds leave those notes out until the transaction's expiry
+//! height has passed: by then it was either mined, and the client no longer
+//! sends the note, or it can never be. Reservations are kept in memory and,
+//! with ZMAIL_PENDING_SPENDS_FILE, in a JSON file that survives restarts.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Mutex, MutexGuard};
+
+#[derive(Serialize, Deserialize)]
+struct PendingSpend {
+    /// Transaction spending the note, once built
+    txid: Option<String>,
+    /// Last height the transaction can be mined at
+    expiry_height: u32,
+}
+
+/// Reserved nullifiers (hex) and the transactions spending them
+pub struct PendingSpends {
+    file: Option<PathBuf>,
+    spends: Mutex<HashMap<String, PendingSpend>>,
+}
+
+impl PendingSpends {
+    /// An empty record, or the one saved in `file`
+    pub fn load(file: Option<PathBuf>) -> Self {
+        let spends = file
+            .as_ref()
+            .and_then(|path| match std::fs::read(path) {
+                Ok(json) => match serde_json::from_slice(&json) {
+                    Ok(spends) => Some(spends),
+                    Err(e) => {
+                        println!("[ProofService] ⚠️  Ignoring unreadable pending spends in {:?}: {}", path, e);
+                        None
+                    }
+                },
+                Err(_) => None,
+            })
+            .unwrap_or_default();
+        PendingSpends { file, spends: Mutex::new(spends) }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<String, PendingSpend>> {
+        self.spends.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Forget reservations whose transaction can no longer be mined at `height`
+    fn prune(spends: &mut HashMap<String, PendingSpend>, height: u32) {
+        spends.retain(|_, spend| spend.expiry_height >= height);
+    }
+
+    fn save(&self, spends: &HashMap<String, PendingSpend>) {
+        let Some(path) = &self.file else { return };
+        // Write then rename, so a crash can't leave a truncated file behind
+        let tmp = path.with_extension("tmp");
+        let result = serde_json::to_vec(spends)
+            .map_err(|e| e.to_string())
+            .and_then(|json| std::fs::write(&tmp, json).map_err(|e| e.to_string()))
+            .and_then(|()| std::fs::rename(&tmp, path).map_err(|e| e.to_string()));
+        if let Err(e) = result {
+            println!("[ProofService] ⚠️  Failed to save pending spends to {:?}: {}", path, e);
+        }
+    }
+
+    /// Whether a transaction that may still be mined at `height` spends `nullifier`
+    pub fn is_pending(&self, nullifier: &[u8; 32], height: u32) -> bool {
+        let mut spends = self.lock();
+        Self::prune(&mut spends, height);
+        spends.contains_key(&hex::encode(nullifier))
+    }
+
+    /// Reserve `nullifiers` for a transaction built at `height` that expires after `expiry_height`
+    ///
+    /// Fails with the nullifiers another build reserved since this one
+    /// selected its notes, leaving nothing reserved. The reservation is given
+    /// back when dropped, unless the build succeeded.
+    pub fn reserve(
+        &self,
+        nullifiers: &[[u8; 32]],
+        height: u32,
+        expiry_height: u32,
+    ) -> Result<Reservation<'_>, Vec<String>> {
+        let mut spends = self.lock();
+        Self::prune(&mut spends, height);
+        let taken: Vec<String> = nullifiers
+            .iter()
+            .map(hex::encode)
+            .filter(|n| spends.contains_key(n))
+            .collect();
+        if !taken.is_empty() {
+            return Err(taken);
+        }
+        for nullifier in nullifiers {
+            spends.insert(hex::encode(nullifier), PendingSpend { txid: None, expiry_height });
+        }
+        self.save(&spends);
+        Ok(Reservation { spends: self, nullifiers: nullifiers.to_vec(), built: false })
+    }
+
+    fn release(&self, nullifiers: &[[u8; 32]]) {
+        let mut spends = self.lock();
+        for nullifier in nullifiers {
+            spends.remove(&hex::encode(nullifier));
+        }
+        self.save(&spends);
+    }
+}
+
+/// Notes reserved by one build, released on drop unless the build succeeded
+pub struct Reservation<'a> {
+    spends: &'a PendingSpends,
+    nullifiers: Vec<[u8; 32]>,
+    built: bool,
+}
+
+impl Reservation<'_> {
+    /// Keep the notes reserved for `txid` until it expires
+    pub fn built(mut self, txid: &str) {
+        let mut spends = self.spends.lock();
+        for nullifier in &self.nullifiers {
+            if let Some(spend) = spends.get_mut(&hex::encode(nullifier)) {
+                spend.txid = Some(txid.to_string());
+            }
+        }
+        self.spends.save(&spends);
+        self.built = true;
+    }
+}
+
+impl Drop for Reservation<'_> {
+    fn drop(&mut self) {
+        if !self.built {
+            self.spends.release(&self.nullifiers);
+        }
+    }
+}
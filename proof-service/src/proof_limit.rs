@@ -0,0 +1,80 @@
+//! Cap on simultaneous Groth16 proofs
+//!
+//! Each proof holds its circuit assignment and the prover's working memory,
+//! so enough of them at once can exhaust memory well before the HTTP
+//! connection limits are reached. Proving takes a permit first; requests
+//! beyond the cap wait in a bounded queue and are turned away once it's full.
+
+use serde::Serialize;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use tokio::sync::{Semaphore, SemaphorePermit};
+use utoipa::ToSchema;
+
+pub struct ProofLimiter {
+    permits: Semaphore,
+    max_concurrent: usize,
+    waiting: AtomicUsize,
+    max_waiting: usize,
+}
+
+/// Current proving load against the configured limits
+#[derive(Serialize, ToSchema)]
+pub struct ProofLoad {
+    /// Proofs being generated now
+    pub busy: usize,
+    pub max_concurrent: usize,
+    /// Requests waiting for a proving slot
+    pub queued: usize,
+    pub max_queued: usize,
+    /// Whether new proof requests are being turned away
+    pub saturated: bool,
+}
+
+/// Counts a request as waiting until it gets a permit or gives up (e.g. the client disconnects)
+struct Waiting<'a>(&'a AtomicUsize);
+
+impl Drop for Waiting<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl ProofLimiter {
+    pub fn new(max_concurrent: usize, max_waiting: usize) -> Self {
+        let max_concurrent = max_concurrent.max(1);
+        ProofLimiter {
+            permits: Semaphore::new(max_concurrent),
+            max_concurrent,
+            waiting: AtomicUsize::new(0),
+            max_waiting,
+        }
+    }
+
+    /// A snapshot of the load; the counts may be stale by the time they're read
+    pub fn load(&self) -> ProofLoad {
+        let busy = self.max_concurrent - self.permits.available_permits().min(self.max_concurrent);
+        let queued = self.waiting.load(Ordering::SeqCst);
+        ProofLoad {
+            busy,
+            max_concurrent: self.max_concurrent,
+            queued,
+            max_queued: self.max_waiting,
+            saturated: busy == self.max_concurrent && queued >= self.max_waiting,
+        }
+    }
+
+    /// Wait for a proving slot, or `None` if `max_waiting` requests are already waiting
+    ///
+    /// Proving may go ahead for as long as the returned permit is held.
+    pub async fn acquire(&self) -> Option<SemaphorePermit<'_>> {
+        if let Ok(permit) = self.permits.try_acquire() {
+            return Some(permit);
+        }
+        if self.waiting.fetch_add(1, Ordering::SeqCst) >= self.max_waiting {
+            self.waiting.fetch_sub(1, Ordering::SeqCst);
+            return None;
+        }
+        let _waiting = Waiting(&self.waiting);
+        Some(self.permits.acquire().await.expect("the proof semaphore is never closed"))
+    }
+}
@@ -0,0 +1,450 @@
+//! Sapling proof generation helpers
+
+use incrementalmerkletree::{Hashable, Position};
+use rand::{CryptoRng, RngCore};
+use sapling::{
+    circuit::{OutputParameters, SpendParameters},
+    keys::{OutgoingViewingKey, SpendAuthorizingKey},
+    note_encryption::{sapling_note_encryption, SaplingDomain, Zip212Enforcement},
+    prover::{OutputProver, SpendProver},
+    value::{NoteValue, ValueCommitTrapdoor, ValueCommitment},
+    zip32::ExtendedSpendingKey,
+    MerklePath, Node, Note, PaymentAddress, Rseed,
+};
+use zcash_note_encryption::{
+    try_output_recovery_with_ovk, Domain, EphemeralKeyBytes, ShieldedOutput, ENC_CIPHERTEXT_SIZE,
+};
+
+use crate::notes::{SpendCandidate, TREE_DEPTH};
+
+/// A new output note to `recipient` with a random rseed
+pub fn new_note<R: RngCore>(recipient: PaymentAddress, value: u64, rng: &mut R) -> Note {
+    let mut rseed = [0u8; 32];
+    rng.fill_bytes(&mut rseed);
+    note_with_rseed(recipient, value, rseed)
+}
+
+/// The output note to `recipient` with the given (ZIP 212) rseed
+pub fn note_with_rseed(recipient: PaymentAddress, value: u64, rseed: [u8; 32]) -> Note {
+    Note::from_parts(recipient, NoteValue::from_raw(value), Rseed::AfterZip212(rseed))
+}
+
+/// Value commitment (under trapdoor `rcv`) and note commitment of `note`
+pub fn commitments(note: &Note, rcv: &ValueCommitTrapdoor) -> ([u8; 32], [u8; 32]) {
+    let cv = ValueCommitment::derive(note.value(), rcv.clone());
+    (cv.to_bytes(), note.cmu().to_bytes())
+}
+
+/// Check that `cmu` commits to a note to `recipient` of `value` with `rseed`
+///
+/// Rebuilds the note from the intended parts rather than reusing the note
+/// that was proven, so a bug in note construction can't also hide here.
+pub fn check_commitment(
+    recipient: PaymentAddress,
+    value: u64,
+    rseed: Rseed,
+    cmu: &[u8; 32],
+) -> Result<(), String> {
+    let expected = Note::from_parts(recipient, NoteValue::from_raw(value), rseed)
+        .cmu()
+        .to_bytes();
+    if &expected != cmu {
+        return Err(format!(
+            "note commitment {} does not match the intended note (expected {})",
+            hex::encode(cmu),
+            hex::encode(expected)
+        ));
+    }
+    Ok(())
+}
+
+/// The parts of an output description that come from note encryption
+pub struct EncryptedNote {
+    pub epk: [u8; 32],
+    pub enc_ciphertext: [u8; 580],
+    pub out_ciphertext: [u8; 80],
+}
+
+/// Encrypt `note` and `memo` to the recipient, and to `ovk` if given
+///
+/// The esk is derived from the note's rseed (ZIP 212), so `epk` matches the
+/// esk that `prove_output` commits to in the proof.
+pub fn encrypt_note<R: RngCore + CryptoRng>(
+    note: &Note,
+    rcv: &ValueCommitTrapdoor,
+    memo: [u8; 512],
+    ovk: Option<OutgoingViewingKey>,
+    rng: &mut R,
+) -> EncryptedNote {
+    let encryptor = sapling_note_encryption(ovk, note.clone(), memo, rng);
+    let cv = ValueCommitment::derive(note.value(), rcv.clone());
+    EncryptedNote {
+        epk: SaplingDomain::epk_bytes(encryptor.epk()).0,
+        enc_ciphertext: encryptor.encrypt_note_plaintext(),
+        out_ciphertext: encryptor.encrypt_outgoing_plaintext(&cv, &note.cmu(), rng),
+    }
+}
+
+/// An encrypted note as the decryption API sees an output
+struct EncryptedOutput<'a> {
+    cmu: [u8; 32],
+    encrypted: &'a EncryptedNote,
+}
+
+impl ShieldedOutput<SaplingDomain, ENC_CIPHERTEXT_SIZE> for EncryptedOutput<'_> {
+    fn ephemeral_key(&self) -> EphemeralKeyBytes {
+        EphemeralKeyBytes(self.encrypted.epk)
+    }
+
+    fn cmstar_bytes(&self) -> [u8; 32] {
+        self.cmu
+    }
+
+    fn enc_ciphertext(&self) -> &[u8; ENC_CIPHERTEXT_SIZE] {
+        &self.encrypted.enc_ciphertext
+    }
+}
+
+/// Decrypt an output with the sender's `ovk`: the note, its recipient and memo
+///
+/// Recovery decrypts out_ciphertext for the recipient key and esk, then
+/// enc_ciphertext with them. None if `ovk` didn't send the output.
+pub fn recover_output(
+    ovk: &OutgoingViewingKey,
+    cv: &ValueCommitment,
+    cmu: [u8; 32],
+    encrypted: &EncryptedNote,
+) -> Option<(Note, PaymentAddress, [u8; 512])> {
+    let domain = SaplingDomain::new(Zip212Enforcement::On);
+    let output = EncryptedOutput { cmu, encrypted };
+    try_output_recovery_with_ovk(&domain, ovk, &output, cv, &encrypted.out_ciphertext)
+}
+
+/// Check that the sender can recover `note` and `memo` from `encrypted` with `ovk`,
+/// the same way a wallet reads its sent mail
+pub fn check_encryption(
+    note: &Note,
+    memo: &[u8; 512],
+    rcv: &ValueCommitTrapdoor,
+    encrypted: &EncryptedNote,
+    ovk: &OutgoingViewingKey,
+) -> Result<(), String> {
+    let cv = ValueCommitment::derive(note.value(), rcv.clone());
+    let (recovered, _, recovered_memo) = recover_output(ovk, &cv, note.cmu().to_bytes(), encrypted)
+        .ok_or("note encryption failed: the output can't be decrypted with the ovk")?;
+    if recovered != *note || recovered_memo != *memo {
+        return Err("note encryption failed: the decrypted note or memo differs from the one encrypted".to_string());
+    }
+    Ok(())
+}
+
+/// Size of an output description in the v4 transaction layout
+pub const OUTPUT_DESCRIPTION_SIZE: usize = 32 + 32 + 32 + 580 + 80 + 192;
+
+/// Serialize an output description in the v4 transaction layout:
+/// cv || cmu || epk || enc_ciphertext || out_ciphertext || zkproof
+///
+/// v5 transactions store each field in its own array, but a v4 description
+/// is one contiguous block that clients can copy into a transaction as is.
+pub fn output_description_v4(
+    cv: &[u8; 32],
+    cmu: &[u8; 32],
+    encrypted: &EncryptedNote,
+    proof: &[u8; 192],
+) -> Vec<u8> {
+    let mut description = Vec::with_capacity(OUTPUT_DESCRIPTION_SIZE);
+    description.extend_from_slice(cv);
+    description.extend_from_slice(cmu);
+    description.extend_from_slice(&encrypted.epk);
+    description.extend_from_slice(&encrypted.enc_ciphertext);
+    description.extend_from_slice(&encrypted.out_ciphertext);
+    description.extend_from_slice(proof);
+    description
+}
+
+/// Generate a Groth16 output proof for `note`, binding the value commitment under `rcv`
+pub fn prove_output<R: RngCore + CryptoRng>(
+    params: &OutputParameters,
+    note: &Note,
+    rcv: ValueCommitTrapdoor,
+    rng: &mut R,
+) -> [u8; 192] {
+    let esk = note.generate_or_derive_esk(rng);
+
+    let circuit = OutputParameters::prepare_circuit(
+        esk,
+        note.recipient(),
+        note.rcm(),
+        note.value(),
+        rcv,
+    );
+    let proof = params.create_proof(circuit, rng);
+
+    OutputParameters::encode_proof(proof)
+}
+
+/// Generate a Groth16 spend proof for `spend`, randomized by `alpha` and binding the value commitment under `rcv`
+///
+/// `alpha` must be the randomizer the spend authorization signature uses.
+pub fn prove_spend<R: RngCore + CryptoRng>(
+    params: &SpendParameters,
+    spend: &SpendCandidate,
+    alpha: jubjub::Fr,
+    rcv: ValueCommitTrapdoor,
+    rng: &mut R,
+) -> Result<[u8; 192], String> {
+    let note = &spend.note;
+    let anchor = spend.merkle_path.root(Node::from_cmu(&note.cmu()));
+
+    let circuit = SpendParameters::prepare_circuit(
+        spend.extsk.expsk.proof_generation_key(),
+        *note.recipient().diversifier(),
+        *note.rseed(),
+        note.value(),
+        alpha,
+        rcv,
+        anchor.into(),
+        spend.merkle_path.clone(),
+    )
+    .ok_or("the note's diversifier does not give a valid address")?;
+    let proof = params.create_proof(circuit, rng);
+
+    Ok(SpendParameters::encode_proof(proof))
+}
+
+/// Generate a spend proof for a zero-value note under a throwaway key, for timing
+///
+/// The note sits at position 0 of a tree of empty leaves, so the proof is
+/// for an anchor that never appears on chain and can't be used in a transaction.
+pub fn prove_dummy_spend<R: RngCore + CryptoRng>(params: &SpendParameters, rng: &mut R) -> [u8; 192] {
+    let mut seed = [0u8; 32];
+    rng.fill_bytes(&mut seed);
+    let extsk = ExtendedSpendingKey::master(&seed);
+    let (_, recipient) = extsk.default_address();
+    let note = new_note(recipient, 0, rng);
+    let merkle_path = MerklePath::from_parts(vec![Node::empty_leaf(); TREE_DEPTH], Position::from(0))
+        .expect("the path has TREE_DEPTH nodes");
+    let anchor = merkle_path.root(Node::from_cmu(&note.cmu()));
+    let alpha = random_alpha(rng);
+
+    let circuit = SpendParameters::prepare_circuit(
+        extsk.expsk.proof_generation_key(),
+        *recipient.diversifier(),
+        *note.rseed(),
+        note.value(),
+        alpha,
+        ValueCommitTrapdoor::random(&mut *rng),
+        anchor.into(),
+        merkle_path,
+    )
+    .expect("the note's address is valid");
+    let proof = params.create_proof(circuit, rng);
+
+    SpendParameters::encode_proof(proof)
+}
+
+/// A random spend authorization randomizer (alpha)
+pub fn random_alpha<R: RngCore>(rng: &mut R) -> jubjub::Fr {
+    let mut alpha = [0u8; 64];
+    rng.fill_bytes(&mut alpha);
+    jubjub::Fr::from_bytes_wide(&alpha)
+}
+
+/// The re-randomized key and signature that authorize one spend
+pub struct SignedSpend {
+    pub rk: [u8; 32],
+    pub spend_auth_sig: [u8; 64],
+}
+
+/// What a spendAuthSig signs: rk || sighash, as in the transaction builder
+fn spend_auth_message(rk: &[u8; 32], sighash: &[u8; 32]) -> [u8; 64] {
+    let mut message = [0u8; 64];
+    message[..32].copy_from_slice(rk);
+    message[32..].copy_from_slice(sighash);
+    message
+}
+
+/// rk, the spend validating key of `ask` randomized by `alpha`
+pub fn randomized_key(ask: &SpendAuthorizingKey, alpha: &jubjub::Fr) -> [u8; 32] {
+    redjubjub::VerificationKey::from(&ask.randomize(alpha)).into()
+}
+
+/// Sign `sighash` with `ask` randomized by `alpha`
+///
+/// `alpha` must be the randomizer the spend proof was made with, or the proof
+/// and signature commit to different rk values and the spend is invalid.
+pub fn sign_spend<R: RngCore + CryptoRng>(
+    ask: &SpendAuthorizingKey,
+    alpha: &jubjub::Fr,
+    sighash: &[u8; 32],
+    rng: &mut R,
+) -> SignedSpend {
+    let rsk = ask.randomize(alpha);
+    let rk = <[u8; 32]>::from(redjubjub::VerificationKey::from(&rsk));
+    let signature = rsk.sign(&mut *rng, &spend_auth_message(&rk, sighash));
+    SignedSpend { rk, spend_auth_sig: signature.into() }
+}
+
+/// Check that `spend_auth_sig` is a signature over `sighash` by the key `rk`
+pub fn verify_spend_auth_sig(rk: &[u8; 32], sighash: &[u8; 32], spend_auth_sig: &[u8; 64]) -> Result<(), String> {
+    let key = redjubjub::VerificationKey::<redjubjub::SpendAuth>::try_from(*rk)
+        .map_err(|_| "rk is not a valid spend validating key".to_string())?;
+    key.verify(&spend_auth_message(rk, sighash), &redjubjub::Signature::from(*spend_auth_sig))
+        .map_err(|_| "spendAuthSig does not verify against rk and the sighash".to_string())
+}
+
+/// A fixed output note used for benchmarking (always the same recipient and value)
+pub fn bench_note<R: RngCore>(rng: &mut R) -> Note {
+    let (_, recipient) = ExtendedSpendingKey::master(&[0u8; 32]).default_address();
+    new_note(recipient, 10_000, rng)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::account;
+    use rand_chacha::{rand_core::SeedableRng, ChaCha20Rng};
+    use sapling::keys::PreparedIncomingViewingKey;
+    use zcash_note_encryption::try_note_decryption;
+
+    /// The account's ovk, and a note with a memo to the account encrypted to it
+    fn sent_note(rng: &mut ChaCha20Rng) -> (OutgoingViewingKey, Note, ValueCommitTrapdoor, [u8; 512], EncryptedNote) {
+        let account = account();
+        let ovk = account.to_diversifiable_full_viewing_key().fvk().ovk;
+        let note = new_note(account.default_address().1, 40_000, rng);
+        let rcv = ValueCommitTrapdoor::random(&mut *rng);
+        let mut memo = [0u8; 512];
+        memo[..5].copy_from_slice(b"hello");
+        let encrypted = encrypt_note(&note, &rcv, memo, Some(ovk), rng);
+        (ovk, note, rcv, memo, encrypted)
+    }
+
+    #[test]
+    fn output_description_reads_back() {
+        let mut rng = ChaCha20Rng::seed_from_u64(1);
+        let (ovk, note, rcv, memo, encrypted) = sent_note(&mut rng);
+        let (cv, cmu) = commitments(&note, &rcv);
+        let description = output_description_v4(&cv, &cmu, &encrypted, &[3; 192]);
+        assert_eq!(description.len(), OUTPUT_DESCRIPTION_SIZE);
+
+        // Split the description at the v4 field boundaries and decrypt what was read
+        let (cv_bytes, rest) = description.split_at(32);
+        let (cmu_bytes, rest) = rest.split_at(32);
+        let (epk, rest) = rest.split_at(32);
+        let (enc_ciphertext, rest) = rest.split_at(580);
+        let (out_ciphertext, proof) = rest.split_at(80);
+        let read = EncryptedNote {
+            epk: epk.try_into().unwrap(),
+            enc_ciphertext: enc_ciphertext.try_into().unwrap(),
+            out_ciphertext: out_ciphertext.try_into().unwrap(),
+        };
+        let cv = ValueCommitment::from_bytes_not_small_order(cv_bytes.try_into().unwrap()).unwrap();
+        let (recovered, _, recovered_memo) = recover_output(&ovk, &cv, cmu_bytes.try_into().unwrap(), &read).unwrap();
+        assert_eq!(recovered, note);
+        assert_eq!(recovered_memo, memo);
+        assert_eq!(proof, [3; 192]);
+    }
+
+    #[test]
+    fn spend_auth_sig_verifies_against_its_sighash() {
+        let mut rng = ChaCha20Rng::seed_from_u64(1);
+        let account = account();
+        let ask = &account.expsk.ask;
+        let alpha = random_alpha(&mut rng);
+        let signed = sign_spend(ask, &alpha, &[1; 32], &mut rng);
+
+        assert_eq!(signed.rk, randomized_key(ask, &alpha));
+        assert_eq!(verify_spend_auth_sig(&signed.rk, &[1; 32], &signed.spend_auth_sig), Ok(()));
+    }
+
+    #[test]
+    fn spend_auth_sig_is_rejected_for_another_sighash_or_key() {
+        let mut rng = ChaCha20Rng::seed_from_u64(1);
+        let account = account();
+        let ask = &account.expsk.ask;
+        let alpha = random_alpha(&mut rng);
+        let signed = sign_spend(ask, &alpha, &[1; 32], &mut rng);
+
+        assert!(verify_spend_auth_sig(&signed.rk, &[2; 32], &signed.spend_auth_sig).is_err());
+        let other_rk = randomized_key(ask, &random_alpha(&mut rng));
+        assert!(verify_spend_auth_sig(&other_rk, &[1; 32], &signed.spend_auth_sig).is_err());
+    }
+
+    #[test]
+    fn rseed_fixes_the_note_commitment_and_ephemeral_key() {
+        let recipient = account().default_address().1;
+        let describe = |seed: u64| {
+            let mut rng = ChaCha20Rng::seed_from_u64(seed);
+            let note = note_with_rseed(recipient, 40_000, [9; 32]);
+            let rcv = ValueCommitTrapdoor::random(&mut rng);
+            let (cv, cmu) = commitments(&note, &rcv);
+            let encrypted = encrypt_note(&note, &rcv, [0; 512], None, &mut rng);
+            output_description_v4(&cv, &cmu, &encrypted, &[0; 192])
+        };
+
+        // Only cv (from rcv) and out_ciphertext (random without an ovk) depend on the rng
+        let (first, second) = (describe(1), describe(2));
+        assert_ne!(first[..32], second[..32]);
+        assert_eq!(first[32..96], second[32..96]);
+        assert_eq!(first[96..676], second[96..676]);
+        assert_eq!(
+            first[32..64],
+            note_with_rseed(recipient, 40_000, [9; 32]).cmu().to_bytes()
+        );
+    }
+
+    /// Decrypt an output the way its recipient's wallet does, with the ivk of `extsk`
+    fn receive(extsk: &ExtendedSpendingKey, cmu: [u8; 32], encrypted: &EncryptedNote) -> Option<(Note, PaymentAddress, [u8; 512])> {
+        let ivk = PreparedIncomingViewingKey::new(&extsk.to_diversifiable_full_viewing_key().fvk().vk.ivk());
+        let domain = SaplingDomain::new(Zip212Enforcement::On);
+        try_note_decryption(&domain, &ivk, &EncryptedOutput { cmu, encrypted })
+    }
+
+    #[test]
+    fn recipient_decrypts_the_output_with_the_ivk() {
+        let mut rng = ChaCha20Rng::seed_from_u64(1);
+        let (ovk, note, rcv, memo, encrypted) = sent_note(&mut rng);
+        check_encryption(&note, &memo, &rcv, &encrypted, &ovk).unwrap();
+
+        let (received, address, received_memo) = receive(&account(), note.cmu().to_bytes(), &encrypted).unwrap();
+        assert_eq!(received, note);
+        assert_eq!(address, account().default_address().1);
+        assert_eq!(received_memo, memo);
+
+        let stranger = ExtendedSpendingKey::master(&[8; 32]);
+        assert!(receive(&stranger, note.cmu().to_bytes(), &encrypted).is_none());
+    }
+
+    #[test]
+    fn sender_recovers_the_output_with_the_ovk() {
+        let mut rng = ChaCha20Rng::seed_from_u64(1);
+        let (ovk, note, rcv, memo, encrypted) = sent_note(&mut rng);
+        let cv = ValueCommitment::derive(note.value(), rcv.clone());
+
+        let (recovered, address, recovered_memo) = recover_output(&ovk, &cv, note.cmu().to_bytes(), &encrypted).unwrap();
+        assert_eq!(recovered, note);
+        assert_eq!(address, note.recipient());
+        assert_eq!(recovered_memo, memo);
+    }
+
+    #[test]
+    fn other_ovks_recover_nothing() {
+        let mut rng = ChaCha20Rng::seed_from_u64(1);
+        let (_, note, rcv, memo, encrypted) = sent_note(&mut rng);
+        let cv = ValueCommitment::derive(note.value(), rcv.clone());
+        let other = OutgoingViewingKey([1; 32]);
+
+        assert!(recover_output(&other, &cv, note.cmu().to_bytes(), &encrypted).is_none());
+        assert!(check_encryption(&note, &memo, &rcv, &encrypted, &other).is_err());
+    }
+
+    #[test]
+    fn outputs_sent_without_an_ovk_are_not_recoverable() {
+        let mut rng = ChaCha20Rng::seed_from_u64(1);
+        let (ovk, note, rcv, memo, _) = sent_note(&mut rng);
+        let encrypted = encrypt_note(&note, &rcv, memo, None, &mut rng);
+        let cv = ValueCommitment::derive(note.value(), rcv.clone());
+
+        assert!(recover_output(&ovk, &cv, note.cmu().to_bytes(), &encrypted).is_none());
+    }
+}
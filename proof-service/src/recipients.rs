@@ -0,0 +1,145 @@
+//! Recipients of a build request
+//!
+//! A build pays one or more recipients, each with its own amount and memo.
+
+use sapling::PaymentAddress;
+use serde::Deserialize;
+use utoipa::ToSchema;
+use zcash_primitives::legacy::TransparentAddress;
+
+use crate::{attachment, hex_bytes, memo};
+
+/// A recipient as sent by the client
+#[derive(Clone, Deserialize, ToSchema)]
+pub struct RecipientInput {
+    pub address: String,
+    /// In zatoshi; may be omitted for a memo-only output
+    #[serde(default)]
+    pub amount: String,
+    /// Memo content (hex). Up to 512 bytes fills one memo field; longer content is
+    /// chunked into zMail fragments, one output each, up to `max_message_size` in /limits.
+    #[serde(default, with = "hex_bytes")]
+    #[schema(value_type = String, format = "hex")]
+    pub memo: Vec<u8>,
+    /// A long message already framed into memo fields (hex), in sequence order;
+    /// one output each. Replaces memo, encrypt_memo and reply_to.
+    #[serde(default, with = "hex_bytes::list")]
+    #[schema(value_type = Vec<String>)]
+    pub memo_fragments: Vec<Vec<u8>>,
+    /// Encrypt the memo content under a per-message key (see `attachment`)
+    #[serde(default)]
+    pub encrypt_memo: bool,
+    /// Tell the recipient where to reply (see `memo::FLAG_REPLY_TO`)
+    #[serde(default)]
+    pub reply_to: ReplyTo,
+    /// Send a message without funds: the output carries the memo and the
+    /// configured memo-only value
+    #[serde(default)]
+    pub memo_only: bool,
+}
+
+/// Which of the sender's addresses, if any, a message names for replies
+#[derive(Clone, Copy, Default, PartialEq, Eq, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ReplyTo {
+    #[default]
+    None,
+    /// The sending key's default address
+    Default,
+    /// A fresh diversified address, so recipients can't link messages by their reply-to
+    Fresh,
+}
+
+impl RecipientInput {
+    /// Number of outputs this recipient's memo needs
+    pub fn output_count(&self) -> usize {
+        if !self.memo_fragments.is_empty() {
+            return self.memo_fragments.len();
+        }
+        let reply_to = self.reply_to != ReplyTo::None;
+        if self.encrypt_memo && !self.memo.is_empty() {
+            memo::encrypted_fragment_count(self.memo.len() + attachment::TAG_SIZE, reply_to)
+        } else {
+            memo::fragment_count(self.memo.len(), reply_to)
+        }
+    }
+
+    /// Whether the recipient is sent a message, which needs a shielded receiver
+    pub fn has_memo(&self) -> bool {
+        !self.memo.is_empty() || !self.memo_fragments.is_empty() || self.reply_to != ReplyTo::None
+    }
+}
+
+/// The receiver an output pays
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Receiver {
+    Sapling(PaymentAddress),
+    /// Deshielding: the value leaves the shielded pool in the clear
+    Transparent(TransparentAddress),
+}
+
+/// A decoded recipient
+pub struct Recipient {
+    pub address: Receiver,
+    pub amount: u64,
+    pub memo: Vec<u8>,
+    /// Pre-framed memo fields, used verbatim instead of framing `memo`
+    pub memo_fragments: Vec<Vec<u8>>,
+    pub encrypt_memo: bool,
+    pub reply_to: ReplyTo,
+}
+
+impl Recipient {
+    /// Whether `other` pays the same address the same amount with the same message
+    fn same_payment(&self, other: &Recipient) -> bool {
+        self.address == other.address
+            && self.amount == other.amount
+            && self.memo == other.memo
+            && self.memo_fragments == other.memo_fragments
+    }
+}
+
+/// Indices of recipients that repeat an earlier recipient's address, amount and memo
+///
+/// Each entry is `(first, duplicate)`. Listing the same payment twice is
+/// almost always a composition mistake that would pay the recipient twice.
+/// Different messages to one address are not duplicates: memo-only outputs
+/// all carry the same amount, and sending several is normal.
+pub fn duplicates(recipients: &[Recipient]) -> Vec<(usize, usize)> {
+    let mut found = Vec::new();
+    for (i, recipient) in recipients.iter().enumerate() {
+        if let Some(first) = recipients[..i].iter().position(|r| r.same_payment(recipient)) {
+            found.push((first, i));
+        }
+    }
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::account;
+
+    fn recipient(amount: u64, memo: &[u8]) -> Recipient {
+        Recipient {
+            address: Receiver::Sapling(account().default_address().1),
+            amount,
+            memo: memo.to_vec(),
+            memo_fragments: vec![],
+            encrypt_memo: false,
+            reply_to: ReplyTo::None,
+        }
+    }
+
+    #[test]
+    fn repeated_payment_is_a_duplicate() {
+        let recipients = [recipient(1_000, b"hi"), recipient(2_000, b"hi"), recipient(1_000, b"hi")];
+        assert_eq!(duplicates(&recipients), vec![(0, 2)]);
+    }
+
+    #[test]
+    fn different_messages_to_one_address_are_not_duplicates() {
+        let recipients = [recipient(0, b"first message"), recipient(0, b"second message")];
+        assert!(duplicates(&recipients).is_empty());
+    }
+}
@@ -0,0 +1,114 @@
+//! Randomness for transaction building
+//!
+//! Builds draw from the OS unless given a seed. A build request's `rng_seed`
+//! makes that one build a ChaCha20 stream seeded from it, so it can be
+//! reproduced byte for byte (for audits); the seed must never be reused.
+//!
+//! With the `deterministic` feature, `ZMAIL_RNG_SEED` does the same for every
+//! build, so identical requests yield byte-identical transactions that can
+//! be compared against fixtures. Never enable that feature in a deployment:
+//! a fixed seed reuses note randomness and makes transactions linkable.
+
+use rand::{rngs::OsRng, CryptoRng, RngCore};
+use rand_chacha::{rand_core::SeedableRng, ChaCha20Rng};
+
+use crate::config::Config;
+
+/// RNG handed to one transaction build
+pub enum BuildRng {
+    Os(OsRng),
+    Seeded(ChaCha20Rng),
+}
+
+/// A fresh RNG for one build: from the request's seed, else the configured one, else the OS RNG
+pub fn build_rng(config: &Config, seed: Option<[u8; 32]>) -> BuildRng {
+    if let Some(seed) = seed {
+        return BuildRng::Seeded(ChaCha20Rng::from_seed(seed));
+    }
+    #[cfg(feature = "deterministic")]
+    if let Some(seed) = config.rng_seed {
+        return BuildRng::Seeded(ChaCha20Rng::seed_from_u64(seed));
+    }
+    let _ = config;
+    BuildRng::Os(OsRng)
+}
+
+impl RngCore for BuildRng {
+    fn next_u32(&mut self) -> u32 {
+        match self {
+            BuildRng::Os(rng) => rng.next_u32(),
+            BuildRng::Seeded(rng) => rng.next_u32(),
+        }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        match self {
+            BuildRng::Os(rng) => rng.next_u64(),
+            BuildRng::Seeded(rng) => rng.next_u64(),
+        }
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        match self {
+            BuildRng::Os(rng) => rng.fill_bytes(dest),
+            BuildRng::Seeded(rng) => rng.fill_bytes(dest),
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        match self {
+            BuildRng::Os(rng) => rng.try_fill_bytes(dest),
+            BuildRng::Seeded(rng) => rng.try_fill_bytes(dest),
+        }
+    }
+}
+
+impl CryptoRng for BuildRng {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notes::NoteScope;
+    use crate::recipients::Receiver;
+    use crate::testing::{account, activation_height, received_notes, NETWORK};
+    use crate::transaction::{self, OutputKind, PlannedOutput};
+    use sapling::zip32::ExtendedSpendingKey;
+    use zcash_primitives::{consensus::NetworkUpgrade, memo::MemoBytes};
+
+    /// Raw bytes of one payment built with randomness from `seed`
+    fn build_with_seed(seed: [u8; 32]) -> Vec<u8> {
+        let account = account();
+        let notes = received_notes(&[(NoteScope::External, account.default_address().1, 60_000)], 1);
+        let spends = notes.iter().map(|note| note.to_spend(&account).unwrap()).collect();
+        let outputs = vec![PlannedOutput {
+            kind: OutputKind::Recipient,
+            ovk: None,
+            address: Receiver::Sapling(ExtendedSpendingKey::master(&[8; 32]).default_address().1),
+            value: 50_000,
+            memo: MemoBytes::empty(),
+        }];
+        let rng = build_rng(&Config::from_env(), Some(seed));
+        let (built, _) = transaction::build_deferred(
+            NETWORK,
+            activation_height(NetworkUpgrade::Nu5),
+            &account,
+            vec![],
+            spends,
+            outputs,
+            None,
+            rng,
+        )
+        .unwrap();
+        built.raw
+    }
+
+    #[test]
+    fn one_seed_builds_identical_transactions() {
+        assert_eq!(build_with_seed([9; 32]), build_with_seed([9; 32]));
+    }
+
+    #[test]
+    fn different_seeds_build_different_transactions() {
+        assert_ne!(build_with_seed([9; 32]), build_with_seed([10; 32]));
+    }
+}
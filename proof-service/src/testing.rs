@@ -0,0 +1,83 @@
+//! Fixtures shared by the unit tests
+//!
+//! Notes are made up rather than taken from the chain: `received_notes`
+//! puts their commitments in a fresh Sapling tree, so their witnesses are
+//! valid and share that tree's anchor, which is all the builder checks.
+
+use incrementalmerkletree::witness::IncrementalWitness;
+use rand::RngCore;
+use rand_chacha::{rand_core::SeedableRng, ChaCha20Rng};
+use sapling::{
+    value::NoteValue, zip32::ExtendedSpendingKey, CommitmentTree, Node, Note, PaymentAddress, Rseed,
+};
+use zcash_primitives::{
+    consensus::{BlockHeight, Network, NetworkUpgrade, Parameters},
+    zip32::DiversifierIndex,
+};
+
+use crate::notes::{NoteScope, SpendableNote};
+
+pub const NETWORK: Network = Network::TestNetwork;
+
+/// The account every test spends from
+pub fn account() -> ExtendedSpendingKey {
+    ExtendedSpendingKey::master(&[7; 32])
+}
+
+/// A diversified address of `account` other than its default one
+pub fn diversified_address(account: &ExtendedSpendingKey) -> PaymentAddress {
+    let dfvk = account.to_diversifiable_full_viewing_key();
+    let (_, address) = dfvk
+        .find_address(DiversifierIndex::from([1; 11]))
+        .expect("some diversifier index above 1 is valid");
+    assert_ne!(address, dfvk.default_address().1);
+    address
+}
+
+/// First height of `upgrade` on the test network
+pub fn activation_height(upgrade: NetworkUpgrade) -> BlockHeight {
+    NETWORK.activation_height(upgrade).expect("the upgrade is scheduled")
+}
+
+/// Notes to `(scope, address, value)` of `account`, mined at height 1 and witnessed in one tree
+///
+/// Note randomness comes from `seed`; calls with different seeds build
+/// different trees, so their notes are at different anchors.
+pub fn received_notes(notes: &[(NoteScope, PaymentAddress, u64)], seed: u64) -> Vec<SpendableNote> {
+    let mut rng = ChaCha20Rng::seed_from_u64(seed);
+    let mut tree = CommitmentTree::empty();
+    let mut witnesses: Vec<IncrementalWitness<Node, 32>> = Vec::new();
+    let mut rseeds = Vec::new();
+    for (_, address, value) in notes {
+        let mut rseed = [0; 32];
+        rng.fill_bytes(&mut rseed);
+        let note = Note::from_parts(*address, NoteValue::from_raw(*value), Rseed::AfterZip212(rseed));
+        let node = Node::from_cmu(&note.cmu());
+        tree.append(node).expect("the tree has room");
+        for witness in witnesses.iter_mut() {
+            witness.append(node).expect("the tree has room");
+        }
+        witnesses.push(IncrementalWitness::from_tree(tree.clone()));
+        rseeds.push(rseed);
+    }
+
+    notes
+        .iter()
+        .zip(witnesses)
+        .zip(rseeds)
+        .map(|(((scope, address, value), witness), rseed)| {
+            let path = witness.path().expect("a witness with a leaf has a path");
+            SpendableNote {
+                value: *value,
+                diversifier: hex::encode(address.diversifier().0),
+                scope: *scope,
+                rseed: hex::encode(rseed),
+                position: u64::from(path.position()),
+                auth_path: path.path_elems().iter().map(|node| hex::encode(node.to_bytes())).collect(),
+                height: 1,
+                txid: None,
+                output_index: None,
+            }
+        })
+        .collect()
+}
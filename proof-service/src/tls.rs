@@ -0,0 +1,50 @@
+//! Serving over TLS, with HTTP/2 negotiated via ALPN
+//!
+//! With `ZMAIL_TLS_CERT` and `ZMAIL_TLS_KEY` set the server listens for
+//! HTTPS instead of plain HTTP. actix offers both "h2" and "http/1.1" during
+//! the handshake, so HTTP/2 clients get it without further setup.
+//!
+//! HTTP/2 matters for clients that send many small proof requests, such as
+//! one output proof per email fragment: they all multiplex over a single
+//! connection instead of each paying for a connection (and a TLS handshake)
+//! or queueing behind one another on a few HTTP/1.1 connections.
+
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+use rustls::{Certificate, PrivateKey, ServerConfig};
+use rustls_pemfile::Item;
+
+/// Read every PEM item in `path`
+fn read_pem(path: &Path) -> Result<Vec<Item>, String> {
+    let file = File::open(path).map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+    rustls_pemfile::read_all(&mut BufReader::new(file)).map_err(|e| format!("Invalid PEM in {}: {}", path.display(), e))
+}
+
+/// Server TLS configuration from a PEM certificate chain and private key
+pub fn load_config(cert_path: &Path, key_path: &Path) -> Result<ServerConfig, String> {
+    let certs: Vec<Certificate> = read_pem(cert_path)?
+        .into_iter()
+        .filter_map(|item| match item {
+            Item::X509Certificate(der) => Some(Certificate(der)),
+            _ => None,
+        })
+        .collect();
+    if certs.is_empty() {
+        return Err(format!("No certificate found in {}", cert_path.display()));
+    }
+    let key = read_pem(key_path)?
+        .into_iter()
+        .find_map(|item| match item {
+            Item::PKCS8Key(der) | Item::RSAKey(der) | Item::ECKey(der) => Some(PrivateKey(der)),
+            _ => None,
+        })
+        .ok_or_else(|| format!("No private key found in {}", key_path.display()))?;
+
+    ServerConfig::builder()
+        .with_safe_defaults()
+        .with_no_client_auth()
+        .with_single_cert(certs, key)
+        .map_err(|e| format!("Invalid TLS certificate or key: {}", e))
+}
@@ -0,0 +1,720 @@
+//! Transaction assembly with librustzcash's `Builder`
+//!
+//! The builder generates the Groth16 proofs for every spend and output and
+//! checks that inputs, outputs and the ZIP-317 fee balance exactly, so
+//! change must be planned by the caller.
+
+use rand::{CryptoRng, RngCore};
+use sapling::{
+    keys::OutgoingViewingKey,
+    prover::{OutputProver, SpendProver},
+    zip32::ExtendedSpendingKey,
+    Anchor,
+};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::convert::Infallible;
+use std::ops::Deref;
+use utoipa::ToSchema;
+use zcash_primitives::{
+    consensus::{BlockHeight, BranchId, Network},
+    legacy::TransparentAddress,
+    memo::MemoBytes,
+    transaction::{
+        builder::{BuildConfig, Builder},
+        components::amount::{Amount, NonNegativeAmount},
+        fees::{fixed, zip317},
+        sighash::{signature_hash, SignableInput},
+        txid::TxIdDigester,
+        Transaction, TxVersion,
+    },
+};
+
+use crate::consensus::{self, MAX_MONEY};
+use crate::deferred::{DeferredProver, ProvingInputs};
+use crate::notes::SpendCandidate;
+use crate::params::Provers;
+use crate::recipients::{Receiver, Recipient};
+use crate::transparent::TransparentSpend;
+
+/// Why an output is part of the transaction
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputKind {
+    Recipient,
+    Dummy,
+    Change,
+}
+
+/// An output to be added to the transaction
+///
+/// `ovk` and `memo` only apply to Sapling outputs.
+pub struct PlannedOutput {
+    pub kind: OutputKind,
+    pub ovk: Option<OutgoingViewingKey>,
+    pub address: Receiver,
+    pub value: u64,
+    pub memo: MemoBytes,
+}
+
+/// Serialized transaction ready to broadcast
+pub struct BuiltTransaction {
+    pub raw: Vec<u8>,
+    pub txid: String,
+    pub privacy: Privacy,
+}
+
+/// How much of a transaction is visible on-chain
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Privacy {
+    /// Only shielded (Sprout, Sapling or Orchard) inputs and outputs
+    Shielded,
+    /// Shielded components plus transparent inputs or outputs
+    Partial,
+    /// Only transparent inputs and outputs
+    Transparent,
+}
+
+impl Privacy {
+    /// Classify a transaction from the pools it actually touches
+    pub fn of(tx: &Transaction) -> Self {
+        let transparent = tx
+            .transparent_bundle()
+            .is_some_and(|b| !b.vin.is_empty() || !b.vout.is_empty());
+        let shielded = tx.sprout_bundle().is_some()
+            || tx.sapling_bundle().is_some()
+            || tx.orchard_bundle().is_some();
+        match (transparent, shielded) {
+            (false, _) => Privacy::Shielded,
+            (true, true) => Privacy::Partial,
+            (true, false) => Privacy::Transparent,
+        }
+    }
+}
+
+/// An amount in zatoshi, with the same amount in ZEC for people to read
+#[derive(Clone, Debug, Serialize, ToSchema)]
+pub struct ZecValue {
+    pub zatoshi: u64,
+    /// Decimal ZEC with all 8 places, e.g. "0.00010000"
+    pub zec: String,
+}
+
+impl ZecValue {
+    pub fn new(zatoshi: u64) -> Self {
+        ZecValue {
+            zatoshi,
+            zec: format!("{}.{:08}", zatoshi / 100_000_000, zatoshi % 100_000_000),
+        }
+    }
+}
+
+/// Where a transaction's value comes from and goes, so the math can be checked before broadcast
+///
+/// For a build, `inputs` is exactly `outputs` plus `change` plus `fee`.
+#[derive(Clone, Debug, Serialize, ToSchema)]
+pub struct ValueBreakdown {
+    /// Everything spent: notes and transparent coins
+    pub inputs: ZecValue,
+    /// Everything paid out other than change, memo-only outputs included
+    pub outputs: ZecValue,
+    /// Returned to the sender; unknown for a decoded transaction, where it is shielded like any output
+    pub change: Option<ZecValue>,
+    pub fee: ZecValue,
+}
+
+impl ValueBreakdown {
+    /// The breakdown of a build, from exactly what is handed to the builder
+    pub fn of_build(
+        notes: &[SpendCandidate],
+        transparent: &[TransparentSpend],
+        outputs: &[PlannedOutput],
+        fee: u64,
+    ) -> Self {
+        let inputs = notes.iter().map(SpendCandidate::value).sum::<u64>()
+            + transparent.iter().map(|spend| u64::from(spend.coin.value)).sum::<u64>();
+        let (change, paid): (Vec<_>, Vec<_>) = outputs.iter().partition(|output| output.kind == OutputKind::Change);
+        ValueBreakdown {
+            inputs: ZecValue::new(inputs),
+            outputs: ZecValue::new(paid.iter().map(|output| output.value).sum()),
+            change: Some(ZecValue::new(change.iter().map(|output| output.value).sum())),
+            fee: ZecValue::new(fee),
+        }
+    }
+}
+
+/// Zero-value output to a fresh random address
+///
+/// Dummy outputs get real proofs and encrypted notes, so on-chain they are
+/// indistinguishable from outputs to real recipients.
+pub fn dummy_output<R: RngCore>(rng: &mut R) -> PlannedOutput {
+    let mut seed = [0u8; 32];
+    rng.fill_bytes(&mut seed);
+    let (_, address) = ExtendedSpendingKey::master(&seed).default_address();
+
+    PlannedOutput {
+        kind: OutputKind::Dummy,
+        ovk: None,
+        address: Receiver::Sapling(address),
+        value: 0,
+        memo: MemoBytes::empty(),
+    }
+}
+
+/// Deterministic fingerprint of what a build spends and pays
+///
+/// Proof and note randomness make every build's bytes and txid unique, so a
+/// client retrying a request can't compare those. Builds with the same nonce
+/// spend the same notes and coins, so at most one of them can be mined, and
+/// pay the same recipients the same amounts and memos. Nothing secret goes
+/// in: nullifiers and outpoints are published when the transaction is.
+pub fn build_nonce(
+    network: Network,
+    target_height: BlockHeight,
+    spends: &[SpendCandidate],
+    transparent: &[TransparentSpend],
+    recipients: &[Recipient],
+    fee: u64,
+) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(b"zMail build nonce");
+    hasher.update(consensus::network_name(network));
+    hasher.update(u32::from(target_height).to_le_bytes());
+
+    let mut nullifiers: Vec<_> = spends.iter().map(|spend| spend.nullifier).collect();
+    nullifiers.sort();
+    hasher.update((nullifiers.len() as u64).to_le_bytes());
+    for nullifier in nullifiers {
+        hasher.update(nullifier);
+    }
+    let mut outpoints: Vec<_> = transparent
+        .iter()
+        .map(|coin| (*coin.outpoint.hash(), coin.outpoint.n()))
+        .collect();
+    outpoints.sort();
+    hasher.update((outpoints.len() as u64).to_le_bytes());
+    for (txid, index) in outpoints {
+        hasher.update(txid);
+        hasher.update(index.to_le_bytes());
+    }
+
+    hasher.update((recipients.len() as u64).to_le_bytes());
+    for recipient in recipients {
+        match recipient.address {
+            Receiver::Sapling(address) => {
+                hasher.update([0]);
+                hasher.update(address.to_bytes());
+            }
+            Receiver::Transparent(TransparentAddress::PublicKeyHash(hash)) => {
+                hasher.update([1]);
+                hasher.update(hash);
+            }
+            Receiver::Transparent(TransparentAddress::ScriptHash(hash)) => {
+                hasher.update([2]);
+                hasher.update(hash);
+            }
+        }
+        hasher.update(recipient.amount.to_le_bytes());
+        hasher.update([recipient.encrypt_memo as u8, recipient.reply_to as u8]);
+        hasher.update((recipient.memo.len() as u64).to_le_bytes());
+        hasher.update(&recipient.memo);
+        hasher.update((recipient.memo_fragments.len() as u64).to_le_bytes());
+        for fragment in &recipient.memo_fragments {
+            hasher.update((fragment.len() as u64).to_le_bytes());
+            hasher.update(fragment);
+        }
+    }
+    hasher.update(fee.to_le_bytes());
+    hex::encode(hasher.finalize())
+}
+
+/// Build and prove a Sapling transaction spending `spends` into `outputs`
+///
+/// `provers` must include the spend parameters. `fee` replaces the ZIP-317
+/// fee with a fixed amount; the outputs must already account for it.
+#[allow(clippy::too_many_arguments)]
+pub fn build<R: RngCore + CryptoRng>(
+    network: Network,
+    target_height: BlockHeight,
+    transparent: Vec<TransparentSpend>,
+    spends: Vec<SpendCandidate>,
+    outputs: Vec<PlannedOutput>,
+    provers: &Provers,
+    fee: Option<u64>,
+    rng: R,
+) -> Result<BuiltTransaction, String> {
+    let spend_params = provers
+        .spend
+        .as_deref()
+        .ok_or("Spend parameters are not loaded")?;
+    assemble(
+        network,
+        target_height,
+        transparent,
+        spends,
+        outputs,
+        spend_params,
+        provers.output.as_ref(),
+        fee,
+        rng,
+    )
+}
+
+/// Build a signed transaction with all-zero proofs, plus the witnesses needed to prove it
+///
+/// Only v5 (NU5 and later) transactions qualify: their signatures and txid
+/// don't cover the proofs, so proofs can be filled in without re-signing.
+/// `extsk` is the account key the spends belong to.
+#[allow(clippy::too_many_arguments)]
+pub fn build_deferred(
+    network: Network,
+    target_height: BlockHeight,
+    extsk: &ExtendedSpendingKey,
+    transparent: Vec<TransparentSpend>,
+    spends: Vec<SpendCandidate>,
+    outputs: Vec<PlannedOutput>,
+    fee: Option<u64>,
+    rng: impl RngCore + CryptoRng,
+) -> Result<(BuiltTransaction, ProvingInputs), String> {
+    let branch_id = consensus::branch_id_for_height(network, target_height);
+    if !matches!(consensus::tx_version_for_branch(branch_id), TxVersion::Zip225) {
+        return Err(format!(
+            "Deferred proving needs a v5 transaction, but height {} is on the {:?} branch",
+            target_height, branch_id
+        ));
+    }
+
+    let prover = DeferredProver::default();
+    let built = assemble(network, target_height, transparent, spends, outputs, &prover, &prover, fee, rng)?;
+    Ok((built, prover.into_inputs(extsk)))
+}
+
+#[allow(clippy::too_many_arguments)]
+fn assemble<SP: SpendProver, OP: OutputProver, R: RngCore + CryptoRng>(
+    network: Network,
+    target_height: BlockHeight,
+    transparent: Vec<TransparentSpend>,
+    spends: Vec<SpendCandidate>,
+    outputs: Vec<PlannedOutput>,
+    spend_prover: &SP,
+    output_prover: &OP,
+    fee: Option<u64>,
+    rng: R,
+) -> Result<BuiltTransaction, String> {
+    let branch_id = consensus::branch_id_for_height(network, target_height);
+    if !consensus::supports_sapling(branch_id) {
+        return Err(format!(
+            "Target height {} is on the {:?} branch, before Sapling activation",
+            target_height, branch_id
+        ));
+    }
+    // The builder picks the format from the branch; checked again after building
+    let version = consensus::tx_version_for_branch(branch_id);
+    println!(
+        "[ProofService] Building {:?} transaction for height {} (branch {:?})",
+        version, target_height, branch_id
+    );
+
+    // Without an anchor the builder takes no Sapling outputs at all. A shielding
+    // build has no spends to take it from, and then any anchor will do.
+    let sapling_anchor = match spends.first() {
+        Some(spend) => Some(spend.anchor()),
+        None => Some(Anchor::empty_tree()),
+    };
+    let mut builder = Builder::new(
+        network,
+        target_height,
+        BuildConfig::Standard {
+            sapling_anchor,
+            orchard_anchor: None,
+        },
+    );
+
+    for input in transparent {
+        builder
+            .add_transparent_input(input.key, input.outpoint, input.coin)
+            .map_err(|e| format!("Failed to add transparent input: {}", e))?;
+    }
+
+    for spend in spends {
+        builder
+            .add_sapling_spend::<Infallible>(&spend.extsk, spend.note, spend.merkle_path)
+            .map_err(|e| format!("Failed to add spend: {}", e))?;
+    }
+
+    for output in outputs {
+        println!("[ProofService] Adding {:?} output ({} zatoshi)", output.kind, output.value);
+        let value = NonNegativeAmount::from_u64(output.value)
+            .map_err(|_| format!("Output value {} is out of range", output.value))?;
+        match output.address {
+            Receiver::Sapling(address) => builder
+                .add_sapling_output::<Infallible>(output.ovk, address, value, output.memo)
+                .map_err(|e| format!("Failed to add output: {}", e))?,
+            Receiver::Transparent(address) => builder
+                .add_transparent_output(&address, value)
+                .map_err(|e| format!("Failed to add transparent output: {}", e))?,
+        }
+    }
+
+    let result = match fee {
+        Some(fee) => {
+            let fee = NonNegativeAmount::from_u64(fee)
+                .map_err(|_| format!("Fee {} is out of range", fee))?;
+            builder
+                .build(rng, spend_prover, output_prover, &fixed::FeeRule::non_standard(fee))
+                .map_err(|e| format!("Transaction build failed: {}", e))?
+        }
+        None => builder
+            .build(rng, spend_prover, output_prover, &zip317::FeeRule::standard())
+            .map_err(|e| format!("Transaction build failed: {}", e))?,
+    };
+
+    let tx = result.transaction();
+    if tx.version() != version {
+        return Err(format!(
+            "Built a {:?} transaction, but the {:?} branch needs {:?}",
+            tx.version(),
+            branch_id,
+            version
+        ));
+    }
+    let mut raw = Vec::new();
+    tx.write(&mut raw)
+        .map_err(|e| format!("Failed to serialize transaction: {}", e))?;
+
+    Ok(BuiltTransaction {
+        raw,
+        txid: tx.txid().to_string(),
+        privacy: Privacy::of(tx),
+    })
+}
+
+/// Summary of a serialized transaction, with its value balance checked
+#[derive(Serialize, ToSchema)]
+pub struct DecodedTransaction {
+    pub txid: String,
+    pub version: String,
+    pub expiry_height: u32,
+    pub privacy: Privacy,
+    pub transparent_inputs: usize,
+    pub transparent_outputs: usize,
+    /// Total of the transparent outputs, in zatoshi
+    pub transparent_output_value: u64,
+    pub sapling_spends: usize,
+    pub sapling_outputs: usize,
+    /// Net value leaving the Sapling pool, in zatoshi (negative when shielding)
+    pub sapling_value_balance: i64,
+    pub orchard_actions: usize,
+    pub orchard_value_balance: i64,
+    /// Fee implied by the value flows; unknown if transparent input values weren't given
+    pub fee: Option<i64>,
+    /// Whether the value flows balance with a valid fee; unknown if they can't all be counted
+    pub balanced: Option<bool>,
+    /// The value flows in zatoshi and ZEC, when they balance. Shielded note values are hidden,
+    /// so shielded value counts at its net balance: an input when it leaves the shielded pools,
+    /// an output when it enters them.
+    pub value_breakdown: Option<ValueBreakdown>,
+    /// Why the transaction isn't balanced or valid for the target height, or what couldn't be checked
+    pub issues: Vec<String>,
+}
+
+fn zatoshi(amount: impl Into<Amount>) -> i64 {
+    i64::from(amount.into())
+}
+
+/// Decode a transaction and check that its value flows balance
+///
+/// Consensus requires transparent inputs plus the shielded value balances,
+/// minus transparent outputs, to equal a fee in 0..=MAX_MONEY. Transparent
+/// input values aren't part of the transaction, so they are passed in
+/// `input_values` (in input order). `expected_fee` additionally checks the
+/// fee against what the builder reported.
+pub fn decode(
+    raw: &[u8],
+    branch_id: BranchId,
+    input_values: &[u64],
+    expected_fee: Option<u64>,
+) -> Result<DecodedTransaction, String> {
+    if let Some(value) = input_values.iter().find(|&&v| v > MAX_MONEY) {
+        return Err(format!("Transparent input value {} exceeds MAX_MONEY", value));
+    }
+    let tx = Transaction::read(raw, branch_id)
+        .map_err(|e| format!("Failed to parse transaction: {}", e))?;
+    let mut issues = Vec::new();
+
+    // v4 transactions take the branch from the caller, but v5 ones commit to their own
+    if !consensus::version_allowed(tx.version(), branch_id) {
+        issues.push(format!(
+            "{:?} transactions are not valid on the {:?} branch",
+            tx.version(),
+            branch_id
+        ));
+    } else if tx.consensus_branch_id() != branch_id {
+        issues.push(format!(
+            "Transaction is for the {:?} branch, but the target height is on {:?}",
+            tx.consensus_branch_id(),
+            branch_id
+        ));
+    }
+
+    let (vin, vout) = tx
+        .transparent_bundle()
+        .map_or((0, vec![]), |b| (b.vin.len(), b.vout.iter().map(|o| zatoshi(o.value)).collect()));
+    let transparent_output_value: i64 = vout.iter().sum();
+    let (sapling_spends, sapling_outputs, sapling_value_balance) = tx
+        .sapling_bundle()
+        .map_or((0, 0, 0), |b| {
+            (b.shielded_spends().len(), b.shielded_outputs().len(), zatoshi(*b.value_balance()))
+        });
+    let (orchard_actions, orchard_value_balance) = tx
+        .orchard_bundle()
+        .map_or((0, 0), |b| (b.actions().len(), zatoshi(*b.value_balance())));
+
+    let mut countable = true;
+    if tx.sprout_bundle().is_some() {
+        issues.push("Sprout JoinSplits are not counted in the balance".to_string());
+        countable = false;
+    }
+    if input_values.len() != vin {
+        if vin > 0 {
+            issues.push(format!(
+                "{} transparent input values are needed to check the balance, {} given",
+                vin,
+                input_values.len()
+            ));
+        } else {
+            issues.push("transparent input values were given, but there are no transparent inputs".to_string());
+        }
+        countable = false;
+    }
+
+    let fee = countable.then(|| {
+        let transparent_in: i64 = input_values.iter().map(|&v| v as i64).sum();
+        transparent_in + sapling_value_balance + orchard_value_balance - transparent_output_value
+    });
+    let balanced = fee.map(|fee| {
+        let mut ok = true;
+        if fee < 0 {
+            issues.push(format!("Outputs exceed inputs by {} zatoshi", -fee));
+            ok = false;
+        } else if fee as u64 > MAX_MONEY {
+            issues.push(format!("Implied fee {} zatoshi exceeds MAX_MONEY", fee));
+            ok = false;
+        }
+        if let Some(expected) = expected_fee {
+            if fee != expected as i64 {
+                issues.push(format!("Implied fee is {} zatoshi, expected {}", fee, expected));
+                ok = false;
+            }
+        }
+        ok
+    });
+
+    let value_breakdown = fee.filter(|_| balanced == Some(true)).map(|fee| {
+        let transparent_in: u64 = input_values.iter().sum();
+        let shielded_net = sapling_value_balance + orchard_value_balance;
+        ValueBreakdown {
+            inputs: ZecValue::new(transparent_in + shielded_net.max(0) as u64),
+            outputs: ZecValue::new(transparent_output_value as u64 + (-shielded_net).max(0) as u64),
+            change: None,
+            fee: ZecValue::new(fee as u64),
+        }
+    });
+
+    Ok(DecodedTransaction {
+        txid: tx.txid().to_string(),
+        version: format!("{:?}", tx.version()),
+        expiry_height: u32::from(tx.expiry_height()),
+        privacy: Privacy::of(&tx),
+        transparent_inputs: vin,
+        transparent_outputs: vout.len(),
+        transparent_output_value: transparent_output_value as u64,
+        sapling_spends,
+        sapling_outputs,
+        sapling_value_balance,
+        orchard_actions,
+        orchard_value_balance,
+        fee,
+        balanced,
+        value_breakdown,
+        issues,
+    })
+}
+
+/// Compute the shielded sighash of a serialized transaction
+///
+/// This is the message signed by every spendAuthSig and the binding
+/// signature. `branch_id` must match the height the transaction targets.
+pub fn shielded_sighash(raw: &[u8], branch_id: BranchId) -> Result<[u8; 32], String> {
+    let tx = Transaction::read(raw, branch_id)
+        .map_err(|e| format!("Failed to parse transaction: {}", e))?;
+    let txid_parts = tx.deref().digest(TxIdDigester);
+    let sighash = signature_hash(tx.deref(), &SignableInput::Shielded, &txid_parts);
+    Ok(*sighash.as_ref())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notes::NoteScope;
+    use crate::testing::{account, activation_height, diversified_address, received_notes, NETWORK};
+    use rand_chacha::{rand_core::SeedableRng, ChaCha20Rng};
+    use sapling::PaymentAddress;
+    use zcash_primitives::consensus::NetworkUpgrade;
+
+    fn output(kind: OutputKind, address: PaymentAddress, value: u64) -> PlannedOutput {
+        PlannedOutput {
+            kind,
+            ovk: None,
+            address: Receiver::Sapling(address),
+            value,
+            memo: MemoBytes::empty(),
+        }
+    }
+
+    /// An address outside the test account
+    fn recipient_address() -> PaymentAddress {
+        ExtendedSpendingKey::master(&[8; 32]).default_address().1
+    }
+
+    /// One 60_000 zatoshi note paid to a recipient at the ZIP-317 fee, built without proofs
+    fn build_at(upgrade: NetworkUpgrade) -> (BranchId, Vec<u8>) {
+        let account = account();
+        let notes = received_notes(&[(NoteScope::External, account.default_address().1, 60_000)], 1);
+        let spends = notes.iter().map(|note| note.to_spend(&account).unwrap()).collect();
+        let outputs = vec![output(OutputKind::Recipient, recipient_address(), 50_000)];
+
+        let height = activation_height(upgrade);
+        let prover = DeferredProver::default();
+        let rng = ChaCha20Rng::seed_from_u64(1);
+        let built = assemble(NETWORK, height, vec![], spends, outputs, &prover, &prover, None, rng).unwrap();
+        (consensus::branch_id_for_height(NETWORK, height), built.raw)
+    }
+
+    #[test]
+    fn decodes_a_v4_transaction() {
+        let (branch_id, raw) = build_at(NetworkUpgrade::Canopy);
+        let decoded = decode(&raw, branch_id, &[], Some(10_000)).unwrap();
+        assert_eq!(decoded.version, "Sapling");
+        assert_eq!(decoded.sapling_spends, 1);
+        assert_eq!(decoded.sapling_value_balance, 10_000);
+        assert_eq!(decoded.fee, Some(10_000));
+        assert_eq!(decoded.balanced, Some(true));
+        assert!(decoded.issues.is_empty(), "{:?}", decoded.issues);
+    }
+
+    #[test]
+    fn decodes_a_v5_transaction() {
+        let (branch_id, raw) = build_at(NetworkUpgrade::Nu5);
+        let decoded = decode(&raw, branch_id, &[], Some(10_000)).unwrap();
+        assert_eq!(decoded.version, "Zip225");
+        assert_eq!(decoded.sapling_spends, 1);
+        assert_eq!(decoded.balanced, Some(true));
+        assert!(decoded.issues.is_empty(), "{:?}", decoded.issues);
+    }
+
+    #[test]
+    fn v4_transaction_is_flagged_after_nu5() {
+        let (_, raw) = build_at(NetworkUpgrade::Canopy);
+        let nu5 = consensus::branch_id_for_height(NETWORK, activation_height(NetworkUpgrade::Nu5));
+        let decoded = decode(&raw, nu5, &[], None).unwrap();
+        assert_eq!(decoded.issues.len(), 1);
+        assert!(decoded.issues[0].contains("not valid on the"), "{:?}", decoded.issues);
+    }
+
+    #[test]
+    fn unexpected_fee_is_unbalanced() {
+        let (branch_id, raw) = build_at(NetworkUpgrade::Nu5);
+        let decoded = decode(&raw, branch_id, &[], Some(5_000)).unwrap();
+        assert_eq!(decoded.fee, Some(10_000));
+        assert_eq!(decoded.balanced, Some(false));
+        assert!(decoded.value_breakdown.is_none());
+    }
+
+    #[test]
+    fn input_values_without_transparent_inputs_leave_the_balance_unknown() {
+        let (branch_id, raw) = build_at(NetworkUpgrade::Nu5);
+        let decoded = decode(&raw, branch_id, &[1_000], None).unwrap();
+        assert_eq!(decoded.balanced, None);
+        assert_eq!(decoded.issues.len(), 1);
+    }
+
+    #[test]
+    fn deferred_builds_need_v5() {
+        let account = account();
+        let height = activation_height(NetworkUpgrade::Canopy);
+        let result = build_deferred(NETWORK, height, &account, vec![], vec![], vec![], None, ChaCha20Rng::seed_from_u64(1));
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn spends_notes_at_two_diversifiers_together() {
+        let account = account();
+        let default_address = account.default_address().1;
+        let other_address = diversified_address(&account);
+        let notes = received_notes(
+            &[(NoteScope::External, default_address, 60_000), (NoteScope::External, other_address, 50_000)],
+            1,
+        );
+        let spends = notes.iter().map(|note| note.to_spend(&account).unwrap()).collect();
+        let outputs = vec![
+            output(OutputKind::Recipient, recipient_address(), 50_000),
+            output(OutputKind::Change, default_address, 50_000),
+        ];
+
+        let height = activation_height(NetworkUpgrade::Nu5);
+        let (built, inputs) = build_deferred(
+            NETWORK,
+            height,
+            &account,
+            vec![],
+            spends,
+            outputs,
+            None,
+            ChaCha20Rng::seed_from_u64(1),
+        )
+        .unwrap();
+
+        let decoded = decode(&built.raw, consensus::branch_id_for_height(NETWORK, height), &[], Some(10_000)).unwrap();
+        assert_eq!(decoded.sapling_spends, 2);
+        assert_eq!(decoded.balanced, Some(true), "{:?}", decoded.issues);
+        // Both spends are proven with the account key, each for its own address
+        assert_eq!(inputs.spends[0].ak, inputs.spends[1].ak);
+        assert_ne!(inputs.spends[0].payment_address, inputs.spends[1].payment_address);
+    }
+    #[test]
+    fn deshields_one_note_at_the_selected_fee() {
+        let account = account();
+        let notes = received_notes(&[(NoteScope::External, account.default_address().1, 60_000)], 1);
+        let candidates = notes.iter().map(|note| note.to_spend(&account).unwrap()).collect();
+        // No Sapling outputs and no change: the builder still pads the bundle to two outputs
+        let selection = crate::notes::select_notes(candidates, 45_000, 0, 1, 100, 1, None, false).unwrap();
+        assert_eq!(selection.fee, 15_000);
+        assert_eq!(selection.change, None);
+
+        let outputs = vec![PlannedOutput {
+            kind: OutputKind::Recipient,
+            ovk: None,
+            address: Receiver::Transparent(TransparentAddress::PublicKey([9; 20])),
+            value: 45_000,
+            memo: MemoBytes::empty(),
+        }];
+        let height = activation_height(NetworkUpgrade::Nu5);
+        let (built, _) = build_deferred(
+            NETWORK,
+            height,
+            &account,
+            vec![],
+            selection.notes,
+            outputs,
+            None,
+            ChaCha20Rng::seed_from_u64(1),
+        )
+        .unwrap();
+        assert_eq!(built.privacy, Privacy::Partial);
+
+        let decoded = decode(&built.raw, consensus::branch_id_for_height(NETWORK, height), &[], Some(15_000)).unwrap();
+        assert_eq!(decoded.transparent_output_value, 45_000);
+        assert_eq!(decoded.balanced, Some(true), "{:?}", decoded.issues);
+    }
+}
@@ -0,0 +1,197 @@
+//! Transparent UTXOs for shielding
+//!
+//! A fresh zMail account is funded by shielding transparent coins: the
+//! client lists the UTXOs to spend and the key that controls them, and the
+//! whole value goes to shielded outputs. Only P2PKH coins are supported.
+
+use base58::FromBase58;
+use secp256k1::SecretKey;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use utoipa::ToSchema;
+use zcash_primitives::{
+    consensus::Network,
+    legacy::Script,
+    transaction::components::{amount::NonNegativeAmount, OutPoint, TxOut},
+};
+
+use crate::consensus::MAX_MONEY;
+use crate::fees;
+use crate::notes::{Selection, SelectionError};
+
+/// A transparent coin as sent by the client
+#[derive(Clone, Deserialize, ToSchema)]
+pub struct UtxoInput {
+    /// Id of the transaction that created the coin (hex, display order)
+    pub txid: String,
+    /// Output index within that transaction
+    pub index: u32,
+    /// The coin's scriptPubKey (hex)
+    pub script: String,
+    /// Value in zatoshi
+    pub value: u64,
+}
+
+/// A decoded coin, ready for `Builder::add_transparent_input`
+pub struct TransparentSpend {
+    pub key: SecretKey,
+    pub outpoint: OutPoint,
+    pub coin: TxOut,
+}
+
+impl UtxoInput {
+    /// Decode the coin, to be spent with `key`
+    pub fn to_spend(&self, key: SecretKey) -> Result<TransparentSpend, String> {
+        let mut txid: [u8; 32] = hex::decode(&self.txid)
+            .map_err(|e| format!("txid is not valid hex: {}", e))?
+            .try_into()
+            .map_err(|_| "txid must be 32 bytes".to_string())?;
+        // Transaction ids are displayed byte-reversed
+        txid.reverse();
+        let script = hex::decode(&self.script).map_err(|e| format!("script is not valid hex: {}", e))?;
+        let value = NonNegativeAmount::from_u64(self.value)
+            .map_err(|_| format!("value {} exceeds MAX_MONEY", self.value))?;
+
+        Ok(TransparentSpend {
+            key,
+            outpoint: OutPoint::new(txid, self.index),
+            coin: TxOut {
+                value,
+                script_pubkey: Script(script),
+            },
+        })
+    }
+}
+
+/// Decode a transparent secret key, given as 32 bytes of hex or in WIF
+pub fn decode_secret_key(network: &Network, encoded: &str) -> Result<SecretKey, String> {
+    let encoded = encoded.trim();
+    if let Ok(bytes) = hex::decode(encoded) {
+        return SecretKey::from_slice(&bytes).map_err(|e| format!("invalid secret key: {}", e));
+    }
+
+    // WIF: version || key || [0x01 if compressed] || 4-byte double-SHA256 checksum
+    let bytes = encoded
+        .from_base58()
+        .map_err(|_| "secret key is neither hex nor WIF".to_string())?;
+    if bytes.len() != 37 && bytes.len() != 38 {
+        return Err("WIF key has the wrong length".to_string());
+    }
+    let (payload, checksum) = bytes.split_at(bytes.len() - 4);
+    if Sha256::digest(Sha256::digest(payload))[..4] != *checksum {
+        return Err("WIF key has a bad checksum".to_string());
+    }
+    let version = match network {
+        Network::MainNetwork => 0x80,
+        Network::TestNetwork => 0xef,
+    };
+    if payload[0] != version {
+        return Err("WIF key is for a different network".to_string());
+    }
+    SecretKey::from_slice(&payload[1..33]).map_err(|e| format!("invalid secret key: {}", e))
+}
+
+/// Fund `target` plus the fee from `coins`, which are all spent
+///
+/// Shielding spends every listed coin, so unlike note selection there is
+/// nothing to choose: whatever exceeds the target and fee becomes change.
+/// With `always_change` there is a change output even when nothing is left.
+pub fn select(
+    coins: &[TransparentSpend],
+    target: u64,
+    outputs: usize,
+    transparent_outputs: usize,
+    fee_override: Option<u64>,
+    always_change: bool,
+) -> Result<Selection, SelectionError> {
+    let total = coins
+        .iter()
+        .try_fold(0u64, |total, coin| total.checked_add(u64::from(coin.coin.value)))
+        .filter(|total| *total <= MAX_MONEY)
+        .ok_or(SelectionError::ValueOverflow)?;
+    let fee_for = |outputs: usize| {
+        fee_override.unwrap_or_else(|| fees::zip317_fee_with_transparent(coins.len(), transparent_outputs, 0, outputs))
+    };
+    let required = |fee: u64| target.checked_add(fee).ok_or(SelectionError::ValueOverflow);
+
+    let fee = fee_for(outputs);
+    if !always_change && total == required(fee)? {
+        return Ok(Selection { notes: vec![], fee, change: None });
+    }
+    let fee = fee_for(outputs + 1);
+    let needed = required(fee)?;
+    if total >= needed {
+        return Ok(Selection {
+            notes: vec![],
+            fee,
+            change: Some(total - needed),
+        });
+    }
+    Err(SelectionError::InsufficientFunds {
+        available: total,
+        required: needed,
+        pending: 0,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::recipients::Receiver;
+    use crate::testing::{account, activation_height, NETWORK};
+    use crate::transaction::{self, OutputKind, PlannedOutput, Privacy};
+    use rand_chacha::{rand_core::SeedableRng, ChaCha20Rng};
+    use secp256k1::{PublicKey, Secp256k1};
+    use zcash_primitives::{
+        consensus::NetworkUpgrade, legacy::keys::pubkey_to_address, memo::MemoBytes,
+    };
+
+    /// A P2PKH coin of `value`, with the key that spends it
+    fn coin(value: u64) -> TransparentSpend {
+        let key = SecretKey::from_slice(&[3; 32]).unwrap();
+        let address = pubkey_to_address(&PublicKey::from_secret_key(&Secp256k1::new(), &key));
+        let utxo = UtxoInput {
+            txid: hex::encode([1; 32]),
+            index: 0,
+            script: hex::encode(address.script().0),
+            value,
+        };
+        utxo.to_spend(key).unwrap()
+    }
+
+    #[test]
+    fn shields_a_coin_into_one_output_at_the_builder_fee() {
+        let coins = vec![coin(60_000)];
+        let selection = select(&coins, 45_000, 1, 0, None, false).unwrap();
+        assert_eq!(selection.fee, 15_000);
+        assert_eq!(selection.change, None);
+
+        let account = account();
+        let outputs = vec![PlannedOutput {
+            kind: OutputKind::Recipient,
+            ovk: None,
+            address: Receiver::Sapling(account.default_address().1),
+            value: 45_000,
+            memo: MemoBytes::empty(),
+        }];
+        let height = activation_height(NetworkUpgrade::Nu5);
+        let (built, _) = transaction::build_deferred(
+            NETWORK,
+            height,
+            &account,
+            coins,
+            vec![],
+            outputs,
+            None,
+            ChaCha20Rng::seed_from_u64(1),
+        )
+        .unwrap();
+        assert_eq!(built.privacy, Privacy::Partial);
+
+        let branch_id = crate::consensus::branch_id_for_height(NETWORK, height);
+        let decoded = transaction::decode(&built.raw, branch_id, &[60_000], Some(15_000)).unwrap();
+        assert_eq!(decoded.transparent_inputs, 1);
+        assert_eq!(decoded.sapling_value_balance, -45_000);
+        assert_eq!(decoded.balanced, Some(true), "{:?}", decoded.issues);
+    }
+}
@@ -0,0 +1,245 @@
+//! Witness building from a note commitment tree checkpoint
+//!
+//! Witnessing a note means appending every Sapling note commitment after
+//! it, and the tree itself needs every commitment since Sapling activation,
+//! which is infeasible to replay from genesis. A checkpoint is the
+//! serialized tree (its frontier) as of some block, in the format returned
+//! by lightwalletd's GetTreeState and zcashd's `z_gettreestate`. Building
+//! resumes from it and appends only the commitments of later blocks.
+//!
+//! A checkpoint taken before a reorg describes a tree the chain no longer
+//! has. Checkpoints can carry the hash of their block, so resuming from a
+//! block that was reorganized away is detected instead of silently producing
+//! witnesses for the wrong tree.
+
+use incrementalmerkletree::witness::IncrementalWitness;
+use sapling::{Anchor, CommitmentTree, Node};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use utoipa::ToSchema;
+use zcash_primitives::merkle_tree::{read_commitment_tree, write_commitment_tree};
+
+use crate::lightwalletd::{CompactBlock, LightwalletdClient, TreeState};
+
+/// A serialized Sapling tree as of a block, as sent by clients or stored in a file
+#[derive(Clone, Deserialize, ToSchema)]
+pub struct CheckpointInput {
+    pub height: u64,
+    /// Serialized Sapling commitment tree (hex)
+    pub sapling_tree: String,
+    /// Hash of block `height` (hex, display order), to detect a reorg since the
+    /// checkpoint was taken
+    #[serde(default)]
+    pub hash: Option<String>,
+}
+
+/// The Sapling tree as of the end of block `height`
+pub struct Checkpoint {
+    pub height: u64,
+    pub tree: CommitmentTree,
+    /// Hash of block `height` (hex, display order), when known
+    pub hash: Option<String>,
+}
+
+impl Checkpoint {
+    /// Deserialize a hex-encoded tree; empty means the tree before Sapling activation
+    pub fn read(height: u64, sapling_tree: &str) -> Result<Self, String> {
+        let bytes = hex::decode(sapling_tree).map_err(|e| format!("Invalid Sapling tree: {}", e))?;
+        let tree = if bytes.is_empty() {
+            CommitmentTree::empty()
+        } else {
+            read_commitment_tree(&bytes[..]).map_err(|e| format!("Invalid Sapling tree: {}", e))?
+        };
+        Ok(Checkpoint { height, tree, hash: None })
+    }
+
+    pub fn from_input(input: &CheckpointInput) -> Result<Self, String> {
+        let checkpoint = Self::read(input.height, &input.sapling_tree)?;
+        Ok(Checkpoint { hash: input.hash.clone(), ..checkpoint })
+    }
+
+    pub fn from_tree_state(state: &TreeState) -> Result<Self, String> {
+        let checkpoint = Self::read(state.height, &state.sapling_tree)?;
+        Ok(Checkpoint { hash: Some(state.hash.clone()), ..checkpoint })
+    }
+
+    /// Whether block `height` on the chain is still the checkpoint's block
+    ///
+    /// `hash` is the chain's block hash at `height`, as in a TreeState.
+    /// Checkpoints without a hash can't be checked and always match.
+    pub fn matches(&self, hash: &str) -> bool {
+        match &self.hash {
+            Some(own) => own.eq_ignore_ascii_case(hash),
+            None => true,
+        }
+    }
+
+    /// Load a checkpoint file holding `{ "height": ..., "sapling_tree": "..." }`
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let json = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read checkpoint {}: {}", path.display(), e))?;
+        let input: CheckpointInput = serde_json::from_str(&json)
+            .map_err(|e| format!("Invalid checkpoint {}: {}", path.display(), e))?;
+        Self::from_input(&input)
+    }
+
+    pub fn anchor(&self) -> Anchor {
+        Anchor::from(self.tree.root())
+    }
+
+    /// The tree in the serialized form `read` takes, to resume from later
+    pub fn to_hex(&self) -> String {
+        let mut bytes = Vec::new();
+        write_commitment_tree(&self.tree, &mut bytes).expect("writing to a Vec can't fail");
+        hex::encode(bytes)
+    }
+}
+
+/// A compact block hash (internal byte order) in display order hex, as in a TreeState
+fn display_hash(hash: &[u8]) -> String {
+    let mut hash = hash.to_vec();
+    hash.reverse();
+    hex::encode(hash)
+}
+
+/// Decode hex note commitments, as they appear in compact blocks
+pub fn parse_commitments(commitments: &[String]) -> Result<Vec<[u8; 32]>, String> {
+    commitments
+        .iter()
+        .map(|cmu| {
+            hex::decode(cmu)
+                .ok()
+                .and_then(|bytes| <[u8; 32]>::try_from(bytes).ok())
+                .ok_or_else(|| format!("Note commitment {} is not 32 bytes of hex", cmu))
+        })
+        .collect()
+}
+
+/// A built witness, in the form `SpendableNote` takes
+#[derive(Serialize, ToSchema)]
+pub struct WitnessOutput {
+    pub position: u64,
+    /// Merkle authentication path, leaf to root (32 nodes, hex)
+    pub auth_path: Vec<String>,
+}
+
+/// Appends the chain's commitments to a checkpoint, witnessing chosen commitments on the way
+pub struct WitnessBuilder {
+    checkpoint: Checkpoint,
+    wanted: Vec<[u8; 32]>,
+    witnesses: Vec<Option<IncrementalWitness<Node, 32>>>,
+}
+
+impl WitnessBuilder {
+    /// Witness the note commitments `wanted` (as in compact blocks) once they are appended
+    pub fn new(checkpoint: Checkpoint, wanted: Vec<[u8; 32]>) -> Self {
+        let witnesses = wanted.iter().map(|_| None).collect();
+        WitnessBuilder {
+            checkpoint,
+            wanted,
+            witnesses,
+        }
+    }
+
+    /// Append the commitments of the block after the current height
+    pub fn append_block(&mut self, block: &CompactBlock) -> Result<(), String> {
+        let expected = self.checkpoint.height + 1;
+        // A lagging or half-synced lightwalletd can leave heights out of a range.
+        // Skipping them would leave their commitments out of the tree: wrong anchor.
+        if block.height > expected {
+            let missing = if block.height == expected + 1 {
+                format!("block {} is", expected)
+            } else {
+                format!("blocks {} to {} are", expected, block.height - 1)
+            };
+            return Err(format!(
+                "lightwalletd's compact blocks have a gap: {} missing (got block {} after {}). \
+                 Retry, or use another lightwalletd server.",
+                missing, block.height, self.checkpoint.height
+            ));
+        }
+        if block.height != expected {
+            return Err(format!("Expected block {}, got block {}", expected, block.height));
+        }
+        // Blocks streamed during a reorg may belong to a different chain than the checkpoint
+        if !self.checkpoint.matches(&display_hash(&block.prev_hash)) {
+            return Err(format!(
+                "Block {} doesn't extend block {}; the chain was reorganized",
+                block.height, self.checkpoint.height
+            ));
+        }
+        for output in block.vtx.iter().flat_map(|tx| tx.outputs.iter()) {
+            let cmu: [u8; 32] = output
+                .cmu
+                .as_slice()
+                .try_into()
+                .map_err(|_| format!("Block {} has a malformed note commitment", block.height))?;
+            let node = Option::from(Node::from_bytes(cmu))
+                .ok_or_else(|| format!("Block {} has an invalid note commitment", block.height))?;
+
+            self.checkpoint
+                .tree
+                .append(node)
+                .map_err(|_| "The note commitment tree is full".to_string())?;
+            for witness in self.witnesses.iter_mut().flatten() {
+                witness
+                    .append(node)
+                    .map_err(|_| "The note commitment tree is full".to_string())?;
+            }
+            for (i, wanted) in self.wanted.iter().enumerate() {
+                if *wanted == cmu && self.witnesses[i].is_none() {
+                    self.witnesses[i] = Some(IncrementalWitness::from_tree(self.checkpoint.tree.clone()));
+                }
+            }
+        }
+        self.checkpoint.height = block.height;
+        self.checkpoint.hash = Some(display_hash(&block.hash));
+        Ok(())
+    }
+
+    /// The tree reached, and a witness for each wanted commitment that was found
+    pub fn finish(self) -> (Checkpoint, Vec<Option<WitnessOutput>>) {
+        let witnesses = self
+            .witnesses
+            .iter()
+            .map(|witness| {
+                let path = witness.as_ref()?.path()?;
+                Some(WitnessOutput {
+                    position: u64::from(path.position()),
+                    auth_path: path.path_elems().iter().map(|node| hex::encode(node.to_bytes())).collect(),
+                })
+            })
+            .collect();
+        (self.checkpoint, witnesses)
+    }
+}
+
+/// Resume from `checkpoint` through block `tip`, witnessing the commitments `wanted`
+///
+/// `tip` must be above the checkpoint height.
+pub async fn build(
+    client: &mut LightwalletdClient,
+    checkpoint: Checkpoint,
+    tip: u64,
+    wanted: Vec<[u8; 32]>,
+) -> Result<(Checkpoint, Vec<Option<WitnessOutput>>), String> {
+    let mut blocks = client.block_range(checkpoint.height + 1, tip).await?;
+    let mut builder = WitnessBuilder::new(checkpoint, wanted);
+    while let Some(block) = blocks
+        .message()
+        .await
+        .map_err(|e| format!("Failed to read compact block: {}", e))?
+    {
+        builder.append_block(&block)?;
+    }
+    if builder.checkpoint.height != tip {
+        return Err(format!(
+            "lightwalletd stopped at block {}, before the tip {}: blocks {} to {} are missing",
+            builder.checkpoint.height,
+            tip,
+            builder.checkpoint.height + 1,
+            tip
+        ));
+    }
+    Ok(builder.finish())
+}